@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use redis_canal_rs::constants::encoding_type;
+use redis_canal_rs_fuzz::{frame_value, parse_all};
+
+fuzz_target!(|data: &[u8]| {
+    parse_all(&frame_value(encoding_type::STEAMLISTPACKS, data));
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use redis_canal_rs::parser::read_ziplist_entry;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = Cursor::new(data);
+    while read_ziplist_entry(&mut reader).is_ok() {}
+});
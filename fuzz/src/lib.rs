@@ -0,0 +1,28 @@
+extern crate redis_canal_rs as rdb;
+
+use rdb::filter::{Filter, Simple};
+use std::io::Cursor;
+
+// The same framing the integration tests use for corrupt values.
+#[path = "../../tests/common/frame.rs"]
+mod frame;
+
+pub use frame::{frame_blob, frame_value};
+
+fn parse_with<L: Filter>(data: &[u8], filter: L) {
+    let _ = rdb::parse(&mut Cursor::new(data), rdb::formatter::Nil::new(), filter);
+}
+
+/// Parses `data` once decoding every key, then with filters that reject all
+/// databases and all regular types, so both skip paths are exercised too.
+pub fn parse_all(data: &[u8]) {
+    parse_with(data, Simple::new());
+
+    let mut filter = Simple::new();
+    filter.add_database(u64::MAX);
+    parse_with(data, filter);
+
+    let mut filter = Simple::new();
+    filter.add_type(rdb::Type::Module);
+    parse_with(data, filter);
+}
@@ -0,0 +1,200 @@
+//! Reading an append-only file, the log of commands Redis replays on a
+//! restart.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, ErrorKind, Read};
+
+use crate::command::{read_command, Command};
+use crate::filter::{CommandFilter, Filter};
+use crate::formatter::Visitor;
+use crate::parser::RdbParser;
+use crate::types::{RdbOk, RdbResult};
+
+/// Reads the commands of an append-only file, after the dump a file written
+/// with `aof-use-rdb-preamble` starts with. The dump goes to the visitor,
+/// the commands are returned by `next` as the filter passes them on, the
+/// way `Canal` handles the dump and the commands of a master.
+///
+/// A file ending in the middle of a command, or of a `MULTI` without its
+/// `EXEC`, is read up to there, the way Redis loads it with
+/// `aof-load-truncated`. `truncated` then tells how much of the file is
+/// whole, which is what `redis-check-aof --fix` truncates it to.
+///
+/// ```no_run
+/// # use redis_canal_rs::aof::AofParser;
+/// # use redis_canal_rs::filter::Simple;
+/// # use redis_canal_rs::formatter::Plain;
+/// # use std::fs::File;
+/// # use std::io::BufReader;
+/// let file = BufReader::new(File::open("appendonly.aof").unwrap());
+/// let mut parser = AofParser::new(file, Plain::new(), Simple::new());
+/// for command in &mut parser {
+///     println!("{:?}", command.unwrap().args);
+/// }
+/// if let Some(length) = parser.truncated() {
+///     eprintln!("Only the first {} bytes are whole", length);
+/// }
+/// ```
+pub struct AofParser<R: BufRead, F: Visitor, L: Filter> {
+    input: Counted<R>,
+    // Only taken while the preamble is parsed.
+    visitor: Option<F>,
+    filter: CommandFilter<L>,
+    started: bool,
+    finished: bool,
+    // The offset of a MULTI not ended yet and the commands following it,
+    // passed on together once its EXEC is read.
+    transaction: Option<(u64, Vec<Command>)>,
+    ready: VecDeque<Command>,
+    truncated: Option<u64>,
+}
+
+impl<R: BufRead, F: Visitor, L: Filter> AofParser<R, F, L> {
+    pub fn new(input: R, visitor: F, filter: L) -> AofParser<R, F, L> {
+        AofParser {
+            input: Counted {
+                inner: input,
+                offset: 0,
+            },
+            visitor: Some(visitor),
+            filter: CommandFilter::new(filter),
+            started: false,
+            finished: false,
+            transaction: None,
+            ready: VecDeque::new(),
+            truncated: None,
+        }
+    }
+
+    /// The filter applied to the commands, to allow or deny some.
+    pub fn filter_mut(&mut self) -> &mut CommandFilter<L> {
+        &mut self.filter
+    }
+
+    /// How many bytes of the file hold whole commands, if it ended in the
+    /// middle of one.
+    pub fn truncated(&self) -> Option<u64> {
+        self.truncated
+    }
+
+    pub fn into_visitor(self) -> F {
+        self.visitor
+            .expect("The visitor is given back after the preamble")
+    }
+
+    fn parse_preamble(&mut self) -> RdbOk {
+        if !self.input.fill_buf()?.starts_with(b"REDIS") {
+            return Ok(());
+        }
+        let visitor = self
+            .visitor
+            .take()
+            .expect("The preamble is only parsed once");
+        let mut parser = RdbParser::new(&mut self.input, visitor, self.filter.filter());
+        let parsed = parser.parse();
+        self.visitor = Some(parser.into_formatter());
+        parsed
+    }
+
+    // Reads a command, returning false at the end of the file.
+    fn read_next(&mut self) -> RdbResult<bool> {
+        if !self.started {
+            self.started = true;
+            self.parse_preamble()?;
+        }
+
+        let offset = self.input.offset;
+        if self.input.fill_buf()?.is_empty() {
+            if let Some((multi, _)) = self.transaction.take() {
+                self.truncated = Some(multi);
+            }
+            return Ok(false);
+        }
+        let args = match read_command(&mut self.input) {
+            Ok((args, _)) => args,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                let whole = self.transaction.take().map_or(offset, |(multi, _)| multi);
+                self.truncated = Some(whole);
+                return Ok(false);
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("{} at offset {}", e, offset),
+                ))
+            }
+        };
+
+        let name = args.first().map(|name| name.to_ascii_lowercase());
+        let command = self.filter.apply_at(offset, args);
+        match name.as_deref() {
+            Some(b"multi") => {
+                if self.transaction.is_some() {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Nested MULTI at offset {}", offset),
+                    ));
+                }
+                self.transaction = Some((offset, command.into_iter().collect()));
+            }
+            Some(b"exec") if self.transaction.is_some() => {
+                let (_, commands) = self.transaction.take().unwrap();
+                self.ready.extend(commands);
+                self.ready.extend(command);
+            }
+            _ => match &mut self.transaction {
+                Some((_, commands)) => commands.extend(command),
+                None => self.ready.extend(command),
+            },
+        }
+        Ok(true)
+    }
+}
+
+impl<R: BufRead, F: Visitor, L: Filter> Iterator for AofParser<R, F, L> {
+    type Item = RdbResult<Command>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(command) = self.ready.pop_front() {
+                return Some(Ok(command));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.read_next() {
+                Ok(true) => {}
+                Ok(false) => self.finished = true,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+// Keeps count of the bytes read, for the offsets of the commands.
+struct Counted<R: BufRead> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: BufRead> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.offset += amt as u64;
+        self.inner.consume(amt);
+    }
+}
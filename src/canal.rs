@@ -1,17 +1,17 @@
+use crate::command::{read_command, Command};
+use crate::config::Reload;
 use crate::filter::*;
-use crate::formatter;
+use crate::formatter::{self, ByteEncoding};
 use crate::parse;
-use crate::parser::RdbParser;
-use byteorder::ByteOrder;
-use byteorder::ReadBytesExt;
-use redis;
-use redis::{cmd, Connection};
-use std::collections::HashMap;
+use crate::tls::{Stream, Tls};
+use crate::transform::{Transform, Transforming};
+use std::fs;
 use std::io::prelude::*;
-use std::io::Error;
-use std::net::TcpStream;
+use std::io::{self, BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::atomic::{AtomicI64,Ordering};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
 
 pub type CanalError = Error;
 
@@ -19,44 +19,191 @@ pub type CanalResult<T> = Result<T, CanalError>;
 
 pub type CanalOk = CanalResult<()>;
 
+/// How the dump and the commands following it are written.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Keys the way the plain formatter writes them, commands on a line each
+    /// with their database.
+    Plain,
+    /// Everything as Redis commands, to send to another server.
+    Protocol,
+}
+
+/// Writes a command of the stream in a format, the plain one with the
+/// database it applies to.
+pub fn write_command<W: Write>(
+    out: &mut W,
+    format: OutputFormat,
+    db: u64,
+    args: &[Vec<u8>],
+) -> CanalOk {
+    match format {
+        OutputFormat::Plain => {
+            let args: Vec<String> = args
+                .iter()
+                .map(|arg| ByteEncoding::Escape.encode(arg))
+                .collect();
+            writeln!(out, "db={} {}", db, args.join(" "))
+        }
+        OutputFormat::Protocol => {
+            write!(out, "*{}\r\n", args.len())?;
+            for arg in args {
+                write!(out, "${}\r\n", arg.len())?;
+                out.write_all(arg)?;
+                out.write_all(b"\r\n")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// redisInfo keeps the name it was published with.
+#[allow(non_snake_case)]
 pub struct Canal {
-    pub conn: TcpStream,
+    pub conn: Stream,
     pub repl_master: bool,
     pub db: u8,
     pub replid: String,
     pub offset: AtomicI64,
     pub password: String,
     pub redisInfo: Rc<Option<redis::InfoDict>>,
+    /// Applied to the dump and to the commands following it alike.
+    pub filter: CommandFilter<Box<dyn Filter>>,
+    /// Applied to what passes the filter, in the dump and the commands.
+    pub transform: Transform,
+    pub format: OutputFormat,
+    pub output: Box<dyn Write>,
+    /// Asked for a new filter and transform between commands.
+    pub reload: Option<Reload>,
+    /// Told whether they were replaced, or why the old ones are kept.
+    pub on_reload: Option<Box<dyn FnMut(CanalOk)>>,
+    // With the protocol format, the elements and bytes per command of the
+    // keys in the dump.
+    batch_size: Option<(usize, usize)>,
+    checkpoint: Option<(PathBuf, Duration)>,
+    last_checkpoint: Instant,
+    tls: Option<Tls>,
 }
 
+impl Canal {
+    pub fn new(addr: String, db: u8, offset: i64, password: String) -> CanalResult<Self> {
+        Canal::connect(addr, None, db, offset, password)
+    }
 
+    /// Connects to the master over TLS, also when reconnecting.
+    pub fn with_tls(
+        addr: String,
+        tls: Tls,
+        db: u8,
+        offset: i64,
+        password: String,
+    ) -> CanalResult<Self> {
+        Canal::connect(addr, Some(tls), db, offset, password)
+    }
 
-impl Canal {
-    pub fn new(addr: String, db: u8, offset: i64, password: String) -> Self {
-        let offs= AtomicI64::new(offset);
-        Canal {
-            conn: TcpStream::connect(addr).expect("error conntion"),
+    fn connect(
+        addr: String,
+        tls: Option<Tls>,
+        db: u8,
+        offset: i64,
+        password: String,
+    ) -> CanalResult<Self> {
+        let offs = AtomicI64::new(offset);
+        Ok(Canal {
+            conn: Stream::connect(&addr, tls.as_ref())?,
             repl_master: false,
-            db: db,
-            password: password,
+            db,
+            password,
             replid: String::from(""),
-            offset:  offs,
+            offset: offs,
             redisInfo: Rc::new(None),
+            filter: CommandFilter::new(Box::new(Simple::new())),
+            transform: Transform::new(),
+            format: OutputFormat::Plain,
+            output: Box::new(io::stdout()),
+            reload: None,
+            on_reload: None,
+            batch_size: None,
+            checkpoint: None,
+            last_checkpoint: Instant::now(),
+            tls,
+        })
+    }
+
+    /// Connects to the master again, to carry on replicating from where the
+    /// last connection stopped.
+    pub fn reconnect(&mut self, addr: &str) -> CanalOk {
+        self.conn = Stream::connect(addr, self.tls.as_ref())?;
+        self.redisInfo = Rc::new(None);
+        Ok(())
+    }
+
+    /// With the protocol format, writes up to `max_elements` elements of a
+    /// key in the dump per command, as `Protocol::set_batch_size` does.
+    pub fn set_batch_size(&mut self, max_elements: usize, max_bytes: usize) {
+        self.batch_size = Some((max_elements, max_bytes));
+    }
+
+    /// Saves the replication ID and offset to `path` at most every
+    /// `interval`, once what came before them is written. If the file
+    /// exists, replication resumes from what it holds.
+    pub fn set_checkpoint(&mut self, path: &Path, interval: Duration) -> CanalOk {
+        match fs::read_to_string(path) {
+            Ok(checkpoint) => {
+                let (replid, offset) = checkpoint
+                    .trim_end()
+                    .split_once(' ')
+                    .and_then(|(replid, offset)| Some((replid, offset.parse().ok()?)))
+                    .ok_or_else(|| invalid(format!("Invalid checkpoint in {}", path.display())))?;
+                self.replid = replid.to_string();
+                self.offset.store(offset, Ordering::Relaxed);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.checkpoint = Some((path.to_path_buf(), interval));
+        Ok(())
+    }
+
+    // Written to another file first, so a crash never leaves half of one.
+    fn save_checkpoint(&mut self, force: bool) -> CanalOk {
+        let (path, interval) = match &self.checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(()),
+        };
+        if !force && self.last_checkpoint.elapsed() < *interval {
+            return Ok(());
         }
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let checkpoint = format!("{} {}\n", self.replid, self.offset.load(Ordering::Relaxed));
+        fs::write(&tmp, checkpoint)?;
+        fs::rename(&tmp, path)?;
+        self.last_checkpoint = Instant::now();
+        Ok(())
     }
 
     fn login_by_password(&mut self) -> redis::RedisResult<()> {
         let mut auth = redis::cmd("AUTH");
-        auth.arg(format!("{}", self.password));
-        self.conn
-            .write(auth.get_packed_command().as_slice())
-            .expect("auth connection error");
+        auth.arg(&self.password);
+        self.request(&auth, "AUTH")
+    }
+
+    // Sends a command of the handshake, which the master has to answer
+    // with OK.
+    fn request(&mut self, cmd: &redis::Cmd, name: &str) -> redis::RedisResult<()> {
+        self.conn.write_all(cmd.get_packed_command().as_slice())?;
         let mut b = [0; 4108];
-        self.conn.read(&mut b).expect("auth connection read error");
-        let c = redis::parse_redis_value(&b)?;
+        let n = self.conn.read(&mut b)?;
+        if n == 0 {
+            let closed = format!("Connection closed in reply to {}", name);
+            return Err(Error::new(ErrorKind::UnexpectedEof, closed).into());
+        }
+        let c = redis::parse_redis_value(&b[..n])?;
         let res: String = redis::from_redis_value(&c)?;
         if res != "OK" {
-            println!("auth connection read error:{}", res);
+            return Err(invalid(format!("Unexpected reply to {}: {}", name, res)).into());
         }
         Ok(())
     }
@@ -69,6 +216,7 @@ impl Canal {
         "".to_string()
     }
 
+    #[allow(dead_code)]
     fn is_master(&mut self) -> bool {
         if let Some(info) = &*self.redisInfo {
             let x: Option<String> = info.get("redis_version").unwrap();
@@ -82,81 +230,43 @@ impl Canal {
         let mut port = redis::cmd("REPLCONF");
         port.arg("listening-port");
         port.arg(self.conn.local_addr()?.port());
-        self.conn
-            .write(port.get_packed_command().as_slice())
-            .expect("write listening-port cmd error");
-        println!(
-            "Current tcp client listen port:{:?}",
-            self.conn.local_addr()?.port()
-        );
-        let mut b = [0; 4108];
-        self.conn.read(&mut b).expect("read listening-port  error");
-        let c = redis::parse_redis_value(&b)?;
-        let res: String = redis::from_redis_value(&c)?;
-        if res != "OK" {
-            println!("replconf listening port failed");
-        }
-        Ok(())
+        self.request(&port, "REPLCONF listening-port")
     }
 
     fn send_ip(&mut self) -> redis::RedisResult<()> {
         let mut ip = redis::cmd("REPLCONF");
         ip.arg("ip-address");
         ip.arg(format! {"{}",self.conn.local_addr()?.ip()});
-        self.conn
-            .write(ip.get_packed_command().as_slice())
-            .expect("write ip-address cmd error");
-        let mut b = [0; 4108];
-        self.conn.read(&mut b).expect("read ip-address error");
-        let c = redis::parse_redis_value(&b)?;
-        let res: String = redis::from_redis_value(&c)?;
-        if res != "OK" {
-            println!("replconf ip-address failed");
-        }
-        Ok(())
+        self.request(&ip, "REPLCONF ip-address")
     }
 
     fn send_psync2(&mut self) -> redis::RedisResult<()> {
         let mut capa = redis::cmd("REPLCONF");
         capa.arg("capa");
         capa.arg("psync2");
-        self.conn
-            .write(capa.get_packed_command().as_slice())
-            .expect("write capa  psync2 cmd error");
-        let mut b = [0; 4108];
-        self.conn.read(&mut b).expect("read capa  psync2 error");
-        let c = redis::parse_redis_value(&b)?;
-        let res: String = redis::from_redis_value(&c)?;
-        if res != "OK" {
-            println!("replconf capa psync2 failed");
-        }
-        Ok(())
+        self.request(&capa, "REPLCONF capa psync2")
     }
 
     fn send_eof(&mut self) -> redis::RedisResult<()> {
         let mut eof = redis::cmd("REPLCONF");
         eof.arg("capa");
         eof.arg("eof");
-        self.conn
-            .write(eof.get_packed_command().as_slice())
-            .expect("write capa eof  cmd error");
-        let mut b = [0; 4108];
-        self.conn.read(&mut b).expect("read capa eof  error");
-        let c = redis::parse_redis_value(&b)?;
-        let res: String = redis::from_redis_value(&c)?;
-        if res != "OK" {
-            println!("replconf eof failed");
-        }
-        Ok(())
+        self.request(&eof, "REPLCONF capa eof")
     }
 
+    // Asks to carry on after the last byte received if there is a
+    // replication ID to carry on with.
     fn send_psync(&mut self) -> redis::RedisResult<()> {
         let mut psync = redis::cmd("psync");
-        psync.arg("?");
-        psync.arg("-1");
-        self.conn
-            .write(psync.get_packed_command().as_slice())
-            .expect("send_psync error");
+        let offset = self.offset.load(Ordering::Relaxed);
+        if self.replid.is_empty() || offset < 0 {
+            psync.arg("?");
+            psync.arg("-1");
+        } else {
+            psync.arg(&self.replid);
+            psync.arg(offset + 1);
+        }
+        self.conn.write_all(psync.get_packed_command().as_slice())?;
         Ok(())
     }
 
@@ -164,11 +274,10 @@ impl Canal {
         let version = self.version();
 
         if version.is_empty() {
-            //should be return the error
-            println!("get version error");
+            return Err(invalid("The master didn't report its version".to_string()).into());
         }
 
-        if version > String::from("4.0.0") {
+        if version.as_str() > "4.0.0" {
             self.send_port()?;
             self.send_ip()?;
             self.send_eof()?;
@@ -180,10 +289,10 @@ impl Canal {
 
     fn info(&mut self) -> redis::RedisResult<()> {
         self.conn
-            .write(redis::cmd("info").get_packed_command().as_slice())?;
+            .write_all(redis::cmd("info").get_packed_command().as_slice())?;
         let mut b = [0; 4108];
-        self.conn.read(&mut b)?;
-        let info: redis::InfoDict = redis::from_redis_value(&redis::parse_redis_value(&b)?)?;
+        let n = self.conn.read(&mut b)?;
+        let info: redis::InfoDict = redis::from_redis_value(&redis::parse_redis_value(&b[..n])?)?;
         self.redisInfo = Rc::new(Some(info));
         Ok(())
     }
@@ -193,25 +302,24 @@ impl Canal {
         Ok(())
     }
 
-    fn reply_ack(&mut self) -> redis::RedisResult<()>{
+    #[allow(dead_code)]
+    fn reply_ack(&mut self) -> redis::RedisResult<()> {
         let mut psync = redis::cmd("psync");
         psync.arg("ack");
         psync.arg(self.offset());
-        self.conn
-            .write(psync.get_packed_command().as_slice())
-            .expect("send_psync error");
+        self.conn.write_all(psync.get_packed_command().as_slice())?;
         Ok(())
     }
 
+    #[allow(dead_code)]
     fn offset(&mut self) -> i64 {
         self.offset.load(Ordering::Relaxed)
     }
 
-    fn set_offset(&mut self,offs :i64) {
+    fn set_offset(&mut self, offs: i64) {
         self.offset.fetch_add(offs, Ordering::Relaxed);
     }
 
-
     fn handler(&mut self) -> redis::RedisResult<()> {
         if !self.password.is_empty() {
             self.login_by_password()?;
@@ -220,56 +328,137 @@ impl Canal {
         self.info()?;
         self.replconf()?;
 
+        let mut input = BufReader::new(self.conn.clone());
+        let reply = String::from_utf8_lossy(&read_reply_line(&mut input)?).into_owned();
+        let parts: Vec<&str> = reply.split(' ').collect();
+        match parts[..] {
+            ["+FULLRESYNC", replid, offset] => {
+                self.replid = replid.to_string();
+                self.offset
+                    .store(offset.parse().unwrap_or(-1), Ordering::Relaxed);
+                self.read_dump(&mut input)?;
+                self.save_checkpoint(true)?;
+            }
+            ["+CONTINUE"] => {}
+            ["+CONTINUE", replid] => self.replid = replid.to_string(),
+            _ => return Err(invalid(format!("Unexpected PSYNC reply: {}", reply)).into()),
+        }
+
         loop {
-            let buf = self.conn.read_u8()?;
-            let buf_array = [buf];
-            buf_array.to_vec();
-            match buf as char {
-                '+' => {
-                    let mut b = [0; 4180];
-                    self.conn
-                        .read(&mut b)
-                        .expect("read FULLRESYNC or CONTINUE error");
-                    let mut v = Vec::new();
-                    v.extend_from_slice(&buf_array);
-                    v.extend_from_slice(&b.to_vec());
-                    let c = redis::parse_redis_value(&v)?;
-                    let res: String = redis::from_redis_value(&c)?;
-                    if res.contains("FULLRESYNC") {
-                        let filter = Simple::new();
-                        // let now = std::time::Instant::now();
-                        parse(&mut self.conn, formatter::Plain::new(), filter)?;
-                        // parse(&mut self.conn, A{}, filter)?;
-                        // let elapsed = now.elapsed();
-                        // println!("--------------------------------------------------------->parse:{:?}",elapsed);
-                    }
-                    if res.contains("CONTINUE") {
-                        let ss: Vec<&str> = res.split(" ").collect();
-                        if ss.len() != 2 {
-                            //will return the error
-                            println!("error CONTINUE resp {:?}", res);
-                            break;
-                        }
-                        self.replid = ss[1].to_string();
-                    }
-                }
-                '*' => {
-                    
-                    let mut b = [0; 13];
-                    self.conn
-                        .read(&mut b)
-                        .expect("read byte");
-                    let mut v = Vec::new();
-                    v.extend_from_slice(&buf_array);
-                    v.extend_from_slice(&b.to_vec());
-                    let c = redis::parse_redis_value(&v)?;
-                    let res: Vec<String> = redis::from_redis_value(&c)?;
-                    println!("{:?}",res[0]);
+            let offset = self.offset.load(Ordering::Relaxed) as u64;
+            let (args, size) = read_command(&mut input)?;
+            self.set_offset(size as i64);
+            self.reload();
+            // The master's keepalives and offset requests aren't for sinks.
+            let command = if is_replication_command(&args) {
+                None
+            } else {
+                self.filter.apply_at(offset, args)
+            };
+            if let Some(command) = command {
+                self.write_command(command)?;
+            }
+            // Flushes once the commands that arrived together are written.
+            if input.buffer().is_empty() {
+                self.output.flush()?;
+                self.save_checkpoint(false)?;
+            }
+        }
+    }
 
+    // Keeps the database the stream writes to, which the new filter can't
+    // know.
+    fn reload(&mut self) {
+        let reloaded = match &self.reload {
+            Some(reload) => reload.poll(),
+            None => return,
+        };
+        let reloaded = match reloaded {
+            Some(Ok((mut filter, transform))) => {
+                filter.set_db(self.filter.db());
+                self.filter = filter;
+                self.transform = transform;
+                Ok(())
+            }
+            Some(Err(e)) => Err(e),
+            None => return,
+        };
+        if let Some(on_reload) = &mut self.on_reload {
+            on_reload(reloaded);
+        }
+    }
+
+    fn write_command(&mut self, command: Command) -> CanalOk {
+        let db = self.transform.db(command.db);
+        let args = self.transform.apply_command(command.args);
+        write_command(&mut self.output, self.format, db, &args)
+    }
+
+    fn parse_dump<R: Read>(&mut self, input: &mut R) -> CanalOk {
+        let transform = self.transform.clone();
+        let filter = self.filter.filter();
+        match self.format {
+            OutputFormat::Plain => {
+                let plain = formatter::Plain::with_output(&mut self.output);
+                parse(input, Transforming::new(plain, transform), filter)?;
+            }
+            OutputFormat::Protocol => {
+                let mut protocol = formatter::Protocol::with_output(&mut self.output);
+                if let Some((max_elements, max_bytes)) = self.batch_size {
+                    protocol.set_batch_size(max_elements, max_bytes);
                 }
-                _ => (println!("nothing to do!")),
-            };
+                parse(input, Transforming::new(protocol, transform), filter)?;
+            }
+        }
+        self.output.flush()
+    }
+
+    // The dump comes as a bulk string, or with `capa eof` as one ended by a
+    // 40 byte marker instead of being counted.
+    fn read_dump<R: BufRead>(&mut self, input: &mut R) -> redis::RedisResult<()> {
+        let header = read_reply_line(input)?;
+        if let Some(marker) = header.strip_prefix(b"$EOF:") {
+            self.parse_dump(input)?;
+            let mut end = vec![0; marker.len()];
+            input.read_exact(&mut end)?;
+            if end != marker {
+                return Err(invalid("Dump not ended by its EOF marker".to_string()).into());
+            }
+        } else {
+            let len: u64 = header
+                .strip_prefix(b"$")
+                .and_then(|len| std::str::from_utf8(len).ok())
+                .and_then(|len| len.parse().ok())
+                .ok_or_else(|| invalid("Invalid dump length".to_string()))?;
+            self.parse_dump(&mut input.take(len))?;
         }
         Ok(())
     }
 }
+
+fn invalid(desc: String) -> CanalError {
+    Error::new(ErrorKind::InvalidData, desc)
+}
+
+fn is_replication_command(args: &[Vec<u8>]) -> bool {
+    args.first().is_some_and(|name| {
+        name.eq_ignore_ascii_case(b"ping") || name.eq_ignore_ascii_case(b"replconf")
+    })
+}
+
+// Reads a line of a reply, skipping the empty ones a master sends to keep
+// the connection alive while it saves the dump.
+fn read_reply_line<R: BufRead>(input: &mut R) -> CanalResult<Vec<u8>> {
+    loop {
+        let mut line = Vec::new();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Connection closed"));
+        }
+        while line.last().is_some_and(|&b| b == b'\n' || b == b'\r') {
+            line.pop();
+        }
+        if !line.is_empty() {
+            return Ok(line);
+        }
+    }
+}
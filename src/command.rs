@@ -0,0 +1,319 @@
+//! Commands of the replication stream following a dump, and where their key
+//! arguments are.
+
+use crate::constants::encoding_type;
+use crate::helper::read_exact;
+use crate::types::{RdbError, RdbResult, Type};
+use std::io::{self, BufRead};
+
+/// Where the key arguments of a command are, counting the command name as
+/// argument 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeySpec {
+    /// Keys from `first` up to `last`, counted from the end when negative,
+    /// every `step` arguments. Like the key positions `COMMAND` reports.
+    Range {
+        first: usize,
+        last: isize,
+        step: usize,
+    },
+    /// As many keys as the argument at `count` says, right after it, and a
+    /// destination key at `dest`.
+    NumKeys { dest: Option<usize>, count: usize },
+}
+
+/// A command of the replication stream or of an append-only file, where it
+/// starts and the database it applies to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub offset: u64,
+    pub db: u64,
+    pub args: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub keys: KeySpec,
+    /// The type of every key, if the command implies it.
+    pub typ: Option<Type>,
+    /// Whether each key (and the arguments up to the next one) can be
+    /// applied on its own, so a command can be cut down to some of its keys.
+    pub splittable: bool,
+}
+
+const fn range(name: &'static str, first: usize, last: isize, typ: Option<Type>) -> CommandSpec {
+    CommandSpec {
+        name,
+        keys: KeySpec::Range {
+            first,
+            last,
+            step: 1,
+        },
+        typ,
+        splittable: false,
+    }
+}
+
+const fn single(name: &'static str, typ: Type) -> CommandSpec {
+    range(name, 1, 1, Some(typ))
+}
+
+const fn numkeys(
+    name: &'static str,
+    dest: Option<usize>,
+    count: usize,
+    typ: Option<Type>,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        keys: KeySpec::NumKeys { dest, count },
+        typ,
+        splittable: false,
+    }
+}
+
+const fn split(name: &'static str, step: usize, typ: Option<Type>) -> CommandSpec {
+    CommandSpec {
+        name,
+        keys: KeySpec::Range {
+            first: 1,
+            last: -1,
+            step,
+        },
+        typ,
+        splittable: true,
+    }
+}
+
+// The commands with keys a master replicates, sorted by name.
+const COMMANDS: &[CommandSpec] = &[
+    single("append", Type::String),
+    single("bitfield", Type::String),
+    range("bitop", 2, -1, Some(Type::String)),
+    range("blmove", 1, 2, Some(Type::List)),
+    numkeys("blmpop", None, 2, Some(Type::List)),
+    range("blpop", 1, -2, Some(Type::List)),
+    range("brpop", 1, -2, Some(Type::List)),
+    range("brpoplpush", 1, 2, Some(Type::List)),
+    numkeys("bzmpop", None, 2, Some(Type::SortedSet)),
+    range("bzpopmax", 1, -2, Some(Type::SortedSet)),
+    range("bzpopmin", 1, -2, Some(Type::SortedSet)),
+    range("copy", 1, 2, None),
+    single("decr", Type::String),
+    single("decrby", Type::String),
+    split("del", 1, None),
+    numkeys("eval", None, 2, None),
+    numkeys("evalsha", None, 2, None),
+    range("expire", 1, 1, None),
+    range("expireat", 1, 1, None),
+    numkeys("fcall", None, 2, None),
+    single("geoadd", Type::SortedSet),
+    range("geosearchstore", 1, 2, Some(Type::SortedSet)),
+    single("getdel", Type::String),
+    single("getex", Type::String),
+    single("getset", Type::String),
+    single("hdel", Type::Hash),
+    single("hincrby", Type::Hash),
+    single("hincrbyfloat", Type::Hash),
+    single("hmset", Type::Hash),
+    single("hset", Type::Hash),
+    single("hsetnx", Type::Hash),
+    single("incr", Type::String),
+    single("incrby", Type::String),
+    single("incrbyfloat", Type::String),
+    single("linsert", Type::List),
+    range("lmove", 1, 2, Some(Type::List)),
+    numkeys("lmpop", None, 1, Some(Type::List)),
+    single("lpop", Type::List),
+    single("lpush", Type::List),
+    single("lpushx", Type::List),
+    single("lrem", Type::List),
+    single("lset", Type::List),
+    single("ltrim", Type::List),
+    range("move", 1, 1, None),
+    split("mset", 2, Some(Type::String)),
+    CommandSpec {
+        splittable: false,
+        ..split("msetnx", 2, Some(Type::String))
+    },
+    range("persist", 1, 1, None),
+    range("pexpire", 1, 1, None),
+    range("pexpireat", 1, 1, None),
+    single("pfadd", Type::String),
+    range("pfmerge", 1, -1, Some(Type::String)),
+    single("psetex", Type::String),
+    range("rename", 1, 2, None),
+    range("renamenx", 1, 2, None),
+    range("restore", 1, 1, None),
+    single("rpop", Type::List),
+    range("rpoplpush", 1, 2, Some(Type::List)),
+    single("rpush", Type::List),
+    single("rpushx", Type::List),
+    single("sadd", Type::Set),
+    range("sdiffstore", 1, -1, Some(Type::Set)),
+    single("set", Type::String),
+    single("setbit", Type::String),
+    single("setex", Type::String),
+    single("setnx", Type::String),
+    single("setrange", Type::String),
+    range("sinterstore", 1, -1, Some(Type::Set)),
+    range("smove", 1, 2, Some(Type::Set)),
+    range("sort", 1, 1, None),
+    single("spop", Type::Set),
+    single("srem", Type::Set),
+    range("sunionstore", 1, -1, Some(Type::Set)),
+    split("touch", 1, None),
+    split("unlink", 1, None),
+    single("xack", Type::Stream),
+    single("xadd", Type::Stream),
+    single("xautoclaim", Type::Stream),
+    single("xclaim", Type::Stream),
+    single("xdel", Type::Stream),
+    range("xgroup", 2, 2, Some(Type::Stream)),
+    single("xsetid", Type::Stream),
+    single("xtrim", Type::Stream),
+    single("zadd", Type::SortedSet),
+    // Sorted set operations also take sets as sources.
+    numkeys("zdiffstore", Some(1), 2, None),
+    single("zincrby", Type::SortedSet),
+    numkeys("zinterstore", Some(1), 2, None),
+    numkeys("zmpop", None, 1, Some(Type::SortedSet)),
+    single("zpopmax", Type::SortedSet),
+    single("zpopmin", Type::SortedSet),
+    range("zrangestore", 1, 2, Some(Type::SortedSet)),
+    single("zrem", Type::SortedSet),
+    single("zremrangebylex", Type::SortedSet),
+    single("zremrangebyrank", Type::SortedSet),
+    single("zremrangebyscore", Type::SortedSet),
+    numkeys("zunionstore", Some(1), 2, None),
+];
+
+impl CommandSpec {
+    /// Looks up a command by its name, in any case.
+    pub fn find(name: &[u8]) -> Option<&'static CommandSpec> {
+        COMMANDS
+            .binary_search_by(|spec| {
+                spec.name
+                    .bytes()
+                    .cmp(name.iter().map(u8::to_ascii_lowercase))
+            })
+            .ok()
+            .map(|i| &COMMANDS[i])
+    }
+
+    /// All known commands, sorted by name.
+    pub fn all() -> &'static [CommandSpec] {
+        COMMANDS
+    }
+
+    /// The positions of the key arguments in a call of the command.
+    pub fn key_positions(&self, args: &[Vec<u8>]) -> Vec<usize> {
+        let mut positions = match self.keys {
+            KeySpec::Range { first, last, step } => {
+                let last = if last < 0 {
+                    args.len() as isize + last
+                } else {
+                    last.min(args.len() as isize - 1)
+                };
+                if last < first as isize {
+                    Vec::new()
+                } else {
+                    (first..=last as usize).step_by(step).collect()
+                }
+            }
+            KeySpec::NumKeys { dest, count } => {
+                let keys = args
+                    .get(count)
+                    .and_then(|n| std::str::from_utf8(n).ok())
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let first = count + 1;
+                let last = first.saturating_add(keys).min(args.len());
+                dest.into_iter()
+                    .filter(|&dest| dest < args.len())
+                    .chain(first..last)
+                    .collect()
+            }
+        };
+
+        // SORT ... STORE destination
+        if self.name == "sort" {
+            let store = args
+                .iter()
+                .skip(2)
+                .position(|arg| arg.eq_ignore_ascii_case(b"store"));
+            if let Some(store) = store {
+                if store + 3 < args.len() {
+                    positions.push(store + 3);
+                }
+            }
+        }
+        positions
+    }
+
+    /// An encoding type of the type the command implies, to ask
+    /// `Filter::matches_type` with.
+    pub fn enc_type(&self) -> Option<u8> {
+        self.typ.map(|typ| match typ {
+            Type::String => encoding_type::STRING,
+            Type::List => encoding_type::LIST_QUICKLIST,
+            Type::Set => encoding_type::SET,
+            Type::SortedSet => encoding_type::ZSET2,
+            Type::Hash => encoding_type::HASH,
+            Type::Stream => encoding_type::STEAMLISTPACKS,
+            Type::Module => encoding_type::MODULE2,
+        })
+    }
+}
+
+fn invalid(desc: &'static str) -> RdbError {
+    io::Error::new(io::ErrorKind::InvalidData, desc)
+}
+
+fn read_line<R: BufRead>(input: &mut R) -> RdbResult<Vec<u8>> {
+    let mut line = Vec::new();
+    input.read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Replication stream ended",
+        ));
+    }
+    if line.ends_with(b"\r\n") {
+        line.truncate(line.len() - 2);
+    } else if line.ends_with(b"\n") {
+        line.truncate(line.len() - 1);
+    }
+    Ok(line)
+}
+
+fn read_number<R: BufRead>(input: &mut R, prefix: u8) -> RdbResult<(usize, usize)> {
+    let line = read_line(input)?;
+    if line.first() != Some(&prefix) {
+        return Err(invalid("Unexpected reply in replication stream"));
+    }
+    let number = std::str::from_utf8(&line[1..])
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| invalid("Invalid length in replication stream"))?;
+    Ok((number, line.len() + 2))
+}
+
+/// Reads the next command of a replication stream, a RESP array of bulk
+/// strings, and the number of bytes it took.
+pub fn read_command<R: BufRead>(input: &mut R) -> RdbResult<(Vec<Vec<u8>>, usize)> {
+    let (count, mut size) = read_number(input, b'*')?;
+    let mut args = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let (len, header) = read_number(input, b'$')?;
+        let mut arg = read_exact(input, len + 2)?;
+        if !arg.ends_with(b"\r\n") {
+            return Err(invalid("Bulk string not terminated in replication stream"));
+        }
+        arg.truncate(len);
+        size += header + len + 2;
+        args.push(arg);
+    }
+    Ok((args, size))
+}
@@ -0,0 +1,506 @@
+//! Settings of a sync, read from a TOML or YAML file.
+//!
+//! ```toml
+//! [source]
+//! address = "localhost:6379"
+//! password = "secret"
+//!
+//! [source.tls]
+//! ca_file = "ca.crt"
+//!
+//! [filter]
+//! databases = [0]
+//! globs = ["user:*", "session:*"]
+//! deny_commands = ["flushall"]
+//!
+//! [transform]
+//! add_prefix = "copy:"
+//! renumber = [{ from = 0, to = 3 }]
+//!
+//! [sink]
+//! type = "redis"
+//! address = "localhost:6380"
+//! batch = 100
+//!
+//! [checkpoint]
+//! path = "sync.checkpoint"
+//!
+//! [retry]
+//! attempts = 10
+//! ```
+//!
+//! Only `source` is required. The same file as YAML has the sections as
+//! mappings.
+
+use std::fs;
+use std::io::{BufReader, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use regex::bytes::Regex;
+use serde::Deserialize;
+
+use crate::canal::OutputFormat;
+use crate::filter::{self, AllOf, CommandFilter, Filter};
+use crate::tls::{self, Stream};
+use crate::transform::Transform;
+use crate::types::{RdbOk, RdbResult, Type};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub source: Source,
+    #[serde(default)]
+    pub filter: FilterConfig,
+    #[serde(default)]
+    pub transform: TransformConfig,
+    #[serde(default)]
+    pub sink: Sink,
+    pub checkpoint: Option<Checkpoint>,
+    #[serde(default)]
+    pub retry: Retry,
+}
+
+/// The master to replicate from, given by its address or found through
+/// sentinels.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    pub address: Option<String>,
+    pub password: Option<String>,
+    /// Used for the sentinels too.
+    pub tls: Option<Tls>,
+    pub sentinel: Option<Sentinel>,
+}
+
+/// Connects over TLS, trusting the public authorities without a CA file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    pub ca_file: Option<PathBuf>,
+    /// A client certificate, given together with its key.
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sentinel {
+    /// Asked in turn until one knows the master.
+    pub addresses: Vec<String>,
+    /// The name the sentinels monitor the master under.
+    pub master: String,
+    pub password: Option<String>,
+}
+
+/// Which keys and commands are passed on, as the options of `sync` select
+/// them. Everything given has to match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// A regular expression.
+    pub keys: Option<String>,
+    pub databases: Vec<u64>,
+    pub types: Vec<String>,
+    /// Redis glob patterns, any of which has to match.
+    pub globs: Vec<String>,
+    /// A file with a key prefix per line.
+    pub prefix_file: Option<PathBuf>,
+    /// A filter expression.
+    pub expr: Option<String>,
+    /// The fraction of keys to pick by a hash of the key.
+    pub sample: Option<f64>,
+    pub seed: u64,
+    pub allow_commands: Vec<String>,
+    pub deny_commands: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransformConfig {
+    pub strip_prefix: Option<String>,
+    pub rename_keys: Vec<Rename>,
+    pub add_prefix: Option<String>,
+    pub renumber: Vec<Renumber>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rename {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Renumber {
+    pub from: u64,
+    pub to: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkType {
+    #[default]
+    Stdout,
+    /// Appended to, so a sync resumed from a checkpoint carries on where it
+    /// stopped.
+    File,
+    /// Another server the commands are sent to.
+    Redis,
+}
+
+/// Where the dump and the commands following it go.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sink {
+    #[serde(rename = "type")]
+    pub kind: SinkType,
+    /// Protocol for a redis sink, plain for the others if not given.
+    pub format: Option<OutputFormat>,
+    pub path: Option<PathBuf>,
+    pub address: Option<String>,
+    /// With the protocol format, how many elements of a key in the dump are
+    /// written per command.
+    pub batch: Option<usize>,
+    /// With `batch`, how many bytes of elements at most.
+    pub batch_bytes: Option<usize>,
+}
+
+impl Sink {
+    pub fn format(&self) -> OutputFormat {
+        match (self.format, self.kind) {
+            (Some(format), _) => format,
+            (None, SinkType::Redis) => OutputFormat::Protocol,
+            (None, _) => OutputFormat::Plain,
+        }
+    }
+}
+
+/// A file the replication ID and offset are saved to as the sync goes, to
+/// resume from with a partial resynchronization.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    /// How often the file is written at most.
+    #[serde(default = "Checkpoint::default_interval_ms")]
+    pub interval_ms: u64,
+}
+
+impl Checkpoint {
+    fn default_interval_ms() -> u64 {
+        1000
+    }
+}
+
+/// How often the connection to the master is made again once lost, waiting
+/// twice as long after each failure in a row.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retry {
+    /// Failures in a row given up after, none retried by default.
+    pub attempts: u32,
+    pub delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            attempts: 0,
+            delay_ms: 1000,
+            max_delay_ms: 60_000,
+        }
+    }
+}
+
+impl Retry {
+    /// How long to wait after `failures` failures in a row.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let delay = self
+            .delay_ms
+            .saturating_mul(1 << failures.saturating_sub(1).min(32));
+        Duration::from_millis(delay.min(self.max_delay_ms))
+    }
+}
+
+fn invalid(field: &str, message: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}: {}", field, message))
+}
+
+impl Config {
+    /// Reads and validates a `.toml`, `.yaml` or `.yml` file. Errors name
+    /// the file and the field at fault.
+    pub fn load(path: &Path) -> RdbResult<Config> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&text).map_err(|e| e.to_string()),
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+            _ => Err("Unknown format, expected a .toml, .yaml or .yml file".to_string()),
+        };
+        let config: Config = parsed.map_err(|e| invalid(&path.display().to_string(), e))?;
+        config
+            .validate()
+            .map_err(|e| invalid(&path.display().to_string(), e))?;
+        Ok(config)
+    }
+
+    /// Checks what deserializing can't, down to compiling the filter and
+    /// transform.
+    pub fn validate(&self) -> RdbOk {
+        let source = &self.source;
+        match (&source.address, &source.sentinel) {
+            (Some(_), Some(_)) => {
+                return Err(invalid(
+                    "source",
+                    "Give either an address or a sentinel, not both",
+                ))
+            }
+            (None, None) => return Err(invalid("source", "An address or a sentinel is required")),
+            (None, Some(sentinel)) if sentinel.addresses.is_empty() => {
+                return Err(invalid(
+                    "source.sentinel.addresses",
+                    "At least one sentinel is required",
+                ))
+            }
+            _ => {}
+        }
+        source.tls()?;
+
+        self.filter.build()?;
+        self.transform.build()?;
+
+        let sink = &self.sink;
+        match sink.kind {
+            SinkType::File if sink.path.is_none() => {
+                return Err(invalid("sink.path", "A file sink needs a path"))
+            }
+            SinkType::Redis if sink.address.is_none() => {
+                return Err(invalid("sink.address", "A redis sink needs an address"))
+            }
+            _ => {}
+        }
+        if sink.kind != SinkType::File && sink.path.is_some() {
+            return Err(invalid("sink.path", "Only a file sink has a path"));
+        }
+        if sink.kind != SinkType::Redis && sink.address.is_some() {
+            return Err(invalid("sink.address", "Only a redis sink has an address"));
+        }
+        if sink.kind == SinkType::Redis && sink.format() == OutputFormat::Plain {
+            return Err(invalid(
+                "sink.format",
+                "A redis sink takes the protocol format",
+            ));
+        }
+        if sink.batch == Some(0) {
+            return Err(invalid("sink.batch", "Must be at least 1"));
+        }
+        if sink.batch_bytes.is_some() && sink.batch.is_none() {
+            return Err(invalid("sink.batch_bytes", "Only applies with a batch"));
+        }
+
+        if self.retry.delay_ms > self.retry.max_delay_ms {
+            return Err(invalid(
+                "retry.delay_ms",
+                "Can't be more than retry.max_delay_ms",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Source {
+    /// The TLS settings loaded, if the connections use TLS.
+    pub fn tls(&self) -> RdbResult<Option<tls::Tls>> {
+        let settings = match &self.tls {
+            Some(settings) => settings,
+            None => return Ok(None),
+        };
+        let tls = tls::Tls::new(
+            settings.ca_file.as_deref(),
+            settings.cert_file.as_deref(),
+            settings.key_file.as_deref(),
+        )
+        .map_err(|e| invalid("source.tls", e))?;
+        Ok(Some(tls))
+    }
+
+    /// The address of the master, asking the sentinels for it if there are
+    /// any, so a master which failed over is found again.
+    pub fn resolve(&self) -> RdbResult<String> {
+        let sentinel = match (&self.address, &self.sentinel) {
+            (Some(address), _) => return Ok(address.clone()),
+            (None, Some(sentinel)) => sentinel,
+            (None, None) => return Err(invalid("source", "An address or a sentinel is required")),
+        };
+        let tls = self.tls()?;
+        let mut last_error = None;
+        for address in &sentinel.addresses {
+            match ask_sentinel(address, sentinel, tls.as_ref()) {
+                Ok(Some((host, port))) => return Ok(format!("{}:{}", host, port)),
+                Ok(None) => {
+                    last_error = Some(format!(
+                        "{} doesn't know master {}",
+                        address, sentinel.master
+                    ))
+                }
+                Err(e) => last_error = Some(format!("{}: {}", address, e)),
+            }
+        }
+        Err(Error::other(format!(
+            "No sentinel gave the address of master {}: {}",
+            sentinel.master,
+            last_error.unwrap_or_default()
+        )))
+    }
+}
+
+fn ask_sentinel(
+    address: &str,
+    sentinel: &Sentinel,
+    tls: Option<&tls::Tls>,
+) -> redis::RedisResult<Option<(String, u16)>> {
+    let mut conn = Stream::connect(address, tls)?;
+    let mut replies = redis::Parser::new(BufReader::new(conn.clone()));
+    if let Some(password) = &sentinel.password {
+        conn.write_all(&redis::cmd("AUTH").arg(password).get_packed_command())?;
+        replies.parse_value()?;
+    }
+    let mut query = redis::cmd("SENTINEL");
+    query.arg("get-master-addr-by-name").arg(&sentinel.master);
+    conn.write_all(&query.get_packed_command())?;
+    redis::from_redis_value(&replies.parse_value()?)
+}
+
+impl FilterConfig {
+    pub fn build(&self) -> RdbResult<CommandFilter<Box<dyn Filter>>> {
+        let mut simple = filter::Simple::new();
+        for &db in &self.databases {
+            simple.add_database(db);
+        }
+        for (i, name) in self.types.iter().enumerate() {
+            match Type::from_name(name) {
+                Some(typ) => simple.add_type(typ),
+                None => {
+                    let field = format!("filter.types[{}]", i);
+                    return Err(invalid(&field, format!("Unknown type: {}", name)));
+                }
+            }
+        }
+        if let Some(keys) = &self.keys {
+            let re = Regex::new(keys)
+                .map_err(|e| invalid("filter.keys", format!("Incorrect regexp: {}", e)))?;
+            simple.add_keys(re);
+        }
+
+        let mut all = AllOf::new();
+        all.add(simple);
+
+        let mut glob = filter::Glob::new();
+        for pattern in &self.globs {
+            glob.add_pattern(pattern.as_bytes());
+        }
+        all.add(glob);
+
+        if let Some(path) = &self.prefix_file {
+            let prefixes = fs::read(path).map_err(|e| {
+                invalid(
+                    "filter.prefix_file",
+                    format!("Can't read {}: {}", path.display(), e),
+                )
+            })?;
+            let mut trie = filter::Prefixes::new();
+            for prefix in prefixes.split(|&b| b == b'\n') {
+                let prefix = prefix.strip_suffix(b"\r").unwrap_or(prefix);
+                if !prefix.is_empty() {
+                    trie.add_prefix(prefix);
+                }
+            }
+            all.add(trie);
+        }
+
+        if let Some(expr) = &self.expr {
+            let expr = filter::parse_expr(expr)
+                .map_err(|e| invalid("filter.expr", format!("Incorrect filter: {}", e)))?;
+            all.add(expr);
+        }
+
+        if let Some(fraction) = self.sample {
+            if !(0.0..=1.0).contains(&fraction) {
+                return Err(invalid("filter.sample", "Must be between 0 and 1"));
+            }
+            let mut sample = filter::Sample::new(fraction);
+            sample.set_seed(self.seed);
+            all.add(sample);
+        }
+
+        let mut filter: CommandFilter<Box<dyn Filter>> = CommandFilter::new(Box::new(all));
+        for name in &self.allow_commands {
+            filter.allow_command(name);
+        }
+        for name in &self.deny_commands {
+            filter.deny_command(name);
+        }
+        Ok(filter)
+    }
+}
+
+impl TransformConfig {
+    pub fn build(&self) -> RdbResult<Transform> {
+        let mut transform = Transform::new();
+        for renumber in &self.renumber {
+            transform.map_database(renumber.from, renumber.to);
+        }
+        if let Some(prefix) = &self.strip_prefix {
+            transform.strip_prefix(prefix.as_bytes());
+        }
+        for (i, rename) in self.rename_keys.iter().enumerate() {
+            let re = Regex::new(&rename.pattern).map_err(|e| {
+                let field = format!("transform.rename_keys[{}].pattern", i);
+                invalid(&field, format!("Incorrect regexp: {}", e))
+            })?;
+            transform.replace_keys(re, &rename.replacement);
+        }
+        if let Some(prefix) = &self.add_prefix {
+            transform.add_prefix(prefix.as_bytes());
+        }
+        Ok(transform)
+    }
+}
+
+/// The filter and transform a sync applies, from a `Reload`.
+pub type Reloaded = (CommandFilter<Box<dyn Filter>>, Transform);
+
+/// Reads the filter and transform sections of a config file again when the
+/// process gets a SIGHUP. The other sections only take effect on a restart.
+pub struct Reload {
+    path: PathBuf,
+    signaled: Arc<AtomicBool>,
+}
+
+impl Reload {
+    pub fn on_sighup(path: &Path) -> RdbResult<Reload> {
+        let signaled = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, signaled.clone())?;
+        Ok(Reload {
+            path: path.to_path_buf(),
+            signaled,
+        })
+    }
+
+    /// The new filter and transform if a SIGHUP arrived since the last
+    /// call, or why the file doesn't load.
+    pub fn poll(&self) -> Option<RdbResult<Reloaded>> {
+        if !self.signaled.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        Some(
+            Config::load(&self.path)
+                .and_then(|config| Ok((config.filter.build()?, config.transform.build()?))),
+        )
+    }
+}
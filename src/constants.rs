@@ -9,19 +9,29 @@ pub mod constant {
     pub const RDB_32BITLEN: u8 = 0x80;
     pub const RDB_64BITLEN: u8 = 0x81;
     pub const RDB_ENCVAL: u8 = 3;
-    pub const RDB_MAGIC: &'static str = "REDIS";
+    pub const RDB_MAGIC: &str = "REDIS";
 }
 
 pub mod op_code {
-    pub const MouduleAux: u8 = 247;
-    pub const Idle: u8 = 248;
-    pub const Freq: u8 = 249;
+    pub const MODULE_AUX: u8 = 247;
+    pub const IDLE: u8 = 248;
+    pub const FREQ: u8 = 249;
     pub const AUX: u8 = 250;
     pub const RESIZEDB: u8 = 251;
     pub const EXPIRETIME_MS: u8 = 252;
     pub const EXPIRETIME: u8 = 253;
     pub const SELECTDB: u8 = 254;
     pub const EOF: u8 = 255;
+
+    #[deprecated(note = "renamed to MODULE_AUX")]
+    #[allow(non_upper_case_globals)]
+    pub const MouduleAux: u8 = MODULE_AUX;
+    #[deprecated(note = "renamed to IDLE")]
+    #[allow(non_upper_case_globals)]
+    pub const Idle: u8 = IDLE;
+    #[deprecated(note = "renamed to FREQ")]
+    #[allow(non_upper_case_globals)]
+    pub const Freq: u8 = FREQ;
 }
 
 pub mod encoding_type {
@@ -50,10 +60,32 @@ pub mod encoding {
 }
 
 pub mod module {
-    pub const ModuleOpcodeEOF: u8 = 0; // End of module value.
-    pub const ModuleOpcodeSInt: u8 = 1;
-    pub const ModuleOpcodeUInt: u8 = 2;
-    pub const ModuleOpcodeFloat: u8 = 3;
-    pub const ModuleOpcodeDouble: u8 = 4;
-    pub const ModuleOpcodeString: u8 = 5;
+    pub const MODULE_OPCODE_EOF: u8 = 0; // End of module value.
+    pub const MODULE_OPCODE_SINT: u8 = 1;
+    pub const MODULE_OPCODE_UINT: u8 = 2;
+    pub const MODULE_OPCODE_FLOAT: u8 = 3;
+    pub const MODULE_OPCODE_DOUBLE: u8 = 4;
+    pub const MODULE_OPCODE_STRING: u8 = 5;
+
+    #[deprecated(note = "renamed to MODULE_OPCODE_EOF")]
+    #[allow(non_upper_case_globals)]
+    pub const ModuleOpcodeEOF: u8 = MODULE_OPCODE_EOF;
+    #[deprecated(note = "renamed to MODULE_OPCODE_SINT")]
+    #[allow(non_upper_case_globals)]
+    pub const ModuleOpcodeSInt: u8 = MODULE_OPCODE_SINT;
+    #[deprecated(note = "renamed to MODULE_OPCODE_UINT")]
+    #[allow(non_upper_case_globals)]
+    pub const ModuleOpcodeUInt: u8 = MODULE_OPCODE_UINT;
+    #[deprecated(note = "renamed to MODULE_OPCODE_FLOAT")]
+    #[allow(non_upper_case_globals)]
+    pub const ModuleOpcodeFloat: u8 = MODULE_OPCODE_FLOAT;
+    #[deprecated(note = "renamed to MODULE_OPCODE_DOUBLE")]
+    #[allow(non_upper_case_globals)]
+    pub const ModuleOpcodeDouble: u8 = MODULE_OPCODE_DOUBLE;
+    #[deprecated(note = "renamed to MODULE_OPCODE_STRING")]
+    #[allow(non_upper_case_globals)]
+    pub const ModuleOpcodeString: u8 = MODULE_OPCODE_STRING;
+
+    pub const MODULE_NAME_CHARSET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
 }
@@ -0,0 +1,32 @@
+// CRC-64 with the Jones polynomial, reflected, as Redis uses for the RDB
+// checksum and the DUMP payload footer.
+const POLY: u64 = 0x95AC_9329_AC4B_C9B5;
+
+const fn make_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [u64; 256] = make_table();
+
+/// Continues the checksum `crc` over `data`. Start with 0.
+pub fn crc64(crc: u64, data: &[u8]) -> u64 {
+    data.iter().fold(crc, |crc, &byte| {
+        TABLE[((crc ^ byte as u64) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
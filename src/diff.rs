@@ -0,0 +1,116 @@
+//! Comparing the keys of two dumps.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::crc64::crc64;
+use crate::filter::Filter;
+use crate::reader::{Entry, RdbReader};
+use crate::types::{KeyRecord, RdbResult, RedisValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// Only in the first dump.
+    Removed,
+    /// Only in the second dump.
+    Added,
+    /// In both, with another value or expiry.
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Difference {
+    pub db: u64,
+    pub key: Vec<u8>,
+    pub change: Change,
+}
+
+fn feed(crc: u64, data: &[u8]) -> u64 {
+    crc64(crc64(crc, &(data.len() as u64).to_le_bytes()), data)
+}
+
+/// A checksum of a value which doesn't depend on how it is encoded, or on
+/// the order of the elements of sets, sorted sets and hashes. Streams are
+/// only compared by length, modules by name, as that's all that is known
+/// of them.
+pub fn digest(value: &RedisValue) -> u64 {
+    match value {
+        RedisValue::String(value) => feed(1, value),
+        RedisValue::List(values) => values.iter().fold(2, |crc, value| feed(crc, value)),
+        RedisValue::Set(members) => {
+            let mut members: Vec<&Vec<u8>> = members.iter().collect();
+            members.sort();
+            members.iter().fold(3, |crc, member| feed(crc, member))
+        }
+        RedisValue::SortedSet(members) => {
+            let mut members: Vec<&(Vec<u8>, f64)> = members.iter().collect();
+            members.sort_by(|a, b| a.0.cmp(&b.0));
+            members.iter().fold(4, |crc, (member, score)| {
+                feed(feed(crc, member), &score.to_le_bytes())
+            })
+        }
+        RedisValue::Hash(fields) => {
+            let mut fields: Vec<&(Vec<u8>, Vec<u8>)> = fields.iter().collect();
+            fields.sort();
+            fields
+                .iter()
+                .fold(5, |crc, (field, value)| feed(feed(crc, field), value))
+        }
+        RedisValue::Stream { length } => feed(6, &length.to_le_bytes()),
+        RedisValue::Module { name } => feed(7, name.as_bytes()),
+    }
+}
+
+fn record_digest(record: &KeyRecord) -> u64 {
+    let expiry = record.meta.expiry.map_or(0, |expiry| expiry + 1);
+    feed(digest(&record.value), &expiry.to_le_bytes())
+}
+
+fn records<R: Read, L: Filter>(input: R, filter: L) -> impl Iterator<Item = RdbResult<KeyRecord>> {
+    RdbReader::with_filter(input, filter).filter_map(|entry| match entry {
+        Ok(Entry::Key(record)) => Some(Ok(record)),
+        Ok(_) => None,
+        Err(err) => Some(Err(err)),
+    })
+}
+
+/// The keys the filter matches which differ between two dumps, sorted by
+/// database and key.
+///
+/// Only the keys of the first dump and a digest of their value are kept in
+/// memory while the second is read.
+pub fn diff<A: Read, B: Read, L: Filter>(
+    first: A,
+    second: B,
+    filter: &L,
+) -> RdbResult<Vec<Difference>> {
+    let mut digests = HashMap::new();
+    for record in records(first, filter) {
+        let record = record?;
+        let digest = record_digest(&record);
+        digests.insert((record.db, record.key), digest);
+    }
+
+    let mut differences = Vec::new();
+    for record in records(second, filter) {
+        let record = record?;
+        let digest = record_digest(&record);
+        let change = match digests.remove(&(record.db, record.key.clone())) {
+            None => Change::Added,
+            Some(first) if first != digest => Change::Changed,
+            Some(_) => continue,
+        };
+        differences.push(Difference {
+            db: record.db,
+            key: record.key,
+            change,
+        });
+    }
+    differences.extend(digests.into_keys().map(|(db, key)| Difference {
+        db,
+        key,
+        change: Change::Removed,
+    }));
+    differences.sort();
+    Ok(differences)
+}
@@ -0,0 +1,148 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use std::io::{self, Cursor, Write};
+
+use crate::constants::{constant, encoding_type, version};
+use crate::crc64::crc64;
+use crate::filter::Simple;
+use crate::formatter::Visitor;
+use crate::parser::RdbParser;
+use crate::types::{RdbOk, RdbResult, RedisValue};
+
+/// RDB version written into the payload footer. Redis refuses payloads
+/// from a newer RDB version than its own, 9 is understood since Redis 5.
+pub const PAYLOAD_VERSION: u16 = 9;
+
+pub fn write_length<W: Write>(out: &mut W, length: u64) -> RdbOk {
+    if length < 1 << 6 {
+        out.write_u8(length as u8)
+    } else if length < 1 << 14 {
+        out.write_u8((constant::RDB_14BITLEN << 6) | (length >> 8) as u8)?;
+        out.write_u8(length as u8)
+    } else if length <= u32::MAX as u64 {
+        out.write_u8(constant::RDB_32BITLEN)?;
+        out.write_u32::<BigEndian>(length as u32)
+    } else {
+        out.write_u8(constant::RDB_64BITLEN)?;
+        out.write_u64::<BigEndian>(length)
+    }
+}
+
+pub fn write_blob<W: Write>(out: &mut W, blob: &[u8]) -> RdbOk {
+    write_length(out, blob.len() as u64)?;
+    out.write_all(blob)
+}
+
+fn payload<F>(enc_type: u8, write_value: F) -> Vec<u8>
+where
+    F: FnOnce(&mut Vec<u8>) -> RdbOk,
+{
+    let mut buf = vec![enc_type];
+    write_value(&mut buf).expect("writing to a Vec cannot fail");
+    finish_payload(buf, PAYLOAD_VERSION)
+}
+
+fn finish_payload(mut buf: Vec<u8>, version: u16) -> Vec<u8> {
+    buf.write_u16::<LittleEndian>(version).unwrap();
+    let checksum = crc64(0, &buf);
+    buf.write_u64::<LittleEndian>(checksum).unwrap();
+    buf
+}
+
+/// Makes a `RESTORE` payload of a value as a dump of the RDB version stores
+/// it after its key.
+pub fn raw_payload(enc_type: u8, value: &[u8], version: u16) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(1 + value.len() + 2 + 8);
+    buf.push(enc_type);
+    buf.extend_from_slice(value);
+    finish_payload(buf, version)
+}
+
+/// Encodes a string value as a `RESTORE` payload.
+pub fn string_payload(value: &[u8]) -> Vec<u8> {
+    payload(encoding_type::STRING, |buf| write_blob(buf, value))
+}
+
+/// Encodes a list value as a `RESTORE` payload.
+pub fn list_payload(values: &[Vec<u8>]) -> Vec<u8> {
+    payload(encoding_type::LIST, |buf| {
+        write_length(buf, values.len() as u64)?;
+        values.iter().try_for_each(|value| write_blob(buf, value))
+    })
+}
+
+/// Encodes a set value as a `RESTORE` payload.
+pub fn set_payload(members: &[Vec<u8>]) -> Vec<u8> {
+    payload(encoding_type::SET, |buf| {
+        write_length(buf, members.len() as u64)?;
+        members
+            .iter()
+            .try_for_each(|member| write_blob(buf, member))
+    })
+}
+
+/// Encodes a hash value as a `RESTORE` payload.
+pub fn hash_payload(fields: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+    payload(encoding_type::HASH, |buf| {
+        write_length(buf, fields.len() as u64)?;
+        fields.iter().try_for_each(|(field, value)| {
+            write_blob(buf, field)?;
+            write_blob(buf, value)
+        })
+    })
+}
+
+/// Encodes a sorted set as a `RESTORE` payload, with binary scores.
+pub fn sorted_set_payload(members: &[(Vec<u8>, f64)]) -> Vec<u8> {
+    payload(encoding_type::ZSET2, |buf| {
+        write_length(buf, members.len() as u64)?;
+        members.iter().try_for_each(|(member, score)| {
+            write_blob(buf, member)?;
+            buf.write_f64::<LittleEndian>(*score)
+        })
+    })
+}
+
+/// Encodes a value as a `RESTORE` payload. Streams and module values can't
+/// be encoded as their contents are never read, `raw_payload` takes them as
+/// they were read instead.
+pub fn encode(value: &RedisValue) -> RdbResult<Vec<u8>> {
+    match value {
+        RedisValue::String(value) => Ok(string_payload(value)),
+        RedisValue::List(values) => Ok(list_payload(values)),
+        RedisValue::Set(members) => Ok(set_payload(members)),
+        RedisValue::SortedSet(members) => Ok(sorted_set_payload(members)),
+        RedisValue::Hash(fields) => Ok(hash_payload(fields)),
+        RedisValue::Stream { .. } | RedisValue::Module { .. } => {
+            Err(io::Error::other("Value can't be encoded"))
+        }
+    }
+}
+
+/// Decodes a `DUMP` payload, reporting the value to the formatter as if it
+/// was read from a dump under the given key.
+pub fn decode<F: Visitor>(key: &[u8], payload: &[u8], formatter: F) -> RdbResult<F> {
+    // The type, at least one byte of value, the version and the checksum.
+    if payload.len() < 1 + 1 + 2 + 8 {
+        return Err(io::Error::other("Payload too short"));
+    }
+
+    let (data, checksum) = payload.split_at(payload.len() - 8);
+    if crc64(0, data) != LittleEndian::read_u64(checksum) {
+        return Err(io::Error::other("Invalid payload checksum"));
+    }
+
+    let (value, payload_version) = data.split_at(data.len() - 2);
+    if LittleEndian::read_u16(payload_version) as u32 > version::SUPPORTED_MAXIMUM {
+        return Err(io::Error::other("Payload version not supported"));
+    }
+
+    let mut input = Cursor::new(&value[1..]);
+    let mut parser = RdbParser::new(&mut input, formatter, Simple::new());
+    parser.parse_value(key, value[0])?;
+    let formatter = parser.into_formatter();
+
+    if input.position() != value.len() as u64 - 1 {
+        return Err(io::Error::other("Trailing data after payload value"));
+    }
+    Ok(formatter)
+}
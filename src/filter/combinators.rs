@@ -0,0 +1,164 @@
+use super::{CommandKey, Filter, KeyInfo, ValueInfo};
+
+/// Matches keys every one of its filters matches. Matches everything when
+/// empty.
+#[derive(Default)]
+pub struct AllOf {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl AllOf {
+    pub fn new() -> Self {
+        AllOf::default()
+    }
+
+    pub fn add<L: Filter + 'static>(&mut self, filter: L) {
+        self.filters.push(Box::new(filter));
+    }
+}
+
+impl Filter for AllOf {
+    fn matches_db(&self, db: u64) -> bool {
+        self.filters.iter().all(|f| f.matches_db(db))
+    }
+
+    fn selects_databases(&self) -> bool {
+        self.filters.iter().any(|f| f.selects_databases())
+    }
+
+    fn matches_type(&self, enc_type: u8) -> bool {
+        self.filters.iter().all(|f| f.matches_type(enc_type))
+    }
+
+    fn matches_key(&self, key: &[u8]) -> bool {
+        self.filters.iter().all(|f| f.matches_key(key))
+    }
+
+    fn matches_expiry(&self, expiry: Option<u64>) -> bool {
+        self.filters.iter().all(|f| f.matches_expiry(expiry))
+    }
+
+    fn matches_value(&self, value: &ValueInfo) -> bool {
+        self.filters.iter().all(|f| f.matches_value(value))
+    }
+
+    fn inspects_values(&self) -> bool {
+        self.filters.iter().any(|f| f.inspects_values())
+    }
+
+    fn matches(&self, key: &KeyInfo) -> bool {
+        self.filters.iter().all(|f| f.matches(key))
+    }
+
+    fn matches_command(&self, key: &CommandKey) -> Option<bool> {
+        let mut verdict = Some(true);
+        for filter in &self.filters {
+            match filter.matches_command(key) {
+                Some(false) => return Some(false),
+                Some(true) => {}
+                None => verdict = None,
+            }
+        }
+        verdict
+    }
+}
+
+/// Matches keys at least one of its filters matches. Matches nothing when
+/// empty.
+#[derive(Default)]
+pub struct AnyOf {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl AnyOf {
+    pub fn new() -> Self {
+        AnyOf::default()
+    }
+
+    pub fn add<L: Filter + 'static>(&mut self, filter: L) {
+        self.filters.push(Box::new(filter));
+    }
+}
+
+impl Filter for AnyOf {
+    fn matches_db(&self, db: u64) -> bool {
+        self.filters.iter().any(|f| f.matches_db(db))
+    }
+
+    fn selects_databases(&self) -> bool {
+        self.filters.iter().any(|f| f.selects_databases())
+    }
+
+    fn matches_type(&self, enc_type: u8) -> bool {
+        self.filters.iter().any(|f| f.matches_type(enc_type))
+    }
+
+    fn matches_key(&self, key: &[u8]) -> bool {
+        self.filters.iter().any(|f| f.matches_key(key))
+    }
+
+    fn matches_expiry(&self, expiry: Option<u64>) -> bool {
+        self.filters.iter().any(|f| f.matches_expiry(expiry))
+    }
+
+    fn matches_value(&self, value: &ValueInfo) -> bool {
+        self.filters.iter().any(|f| f.matches_value(value))
+    }
+
+    fn inspects_values(&self) -> bool {
+        self.filters.iter().any(|f| f.inspects_values())
+    }
+
+    fn matches(&self, key: &KeyInfo) -> bool {
+        self.filters.iter().any(|f| f.matches(key))
+    }
+
+    fn matches_command(&self, key: &CommandKey) -> Option<bool> {
+        let mut verdict = Some(false);
+        for filter in &self.filters {
+            match filter.matches_command(key) {
+                Some(true) => return Some(true),
+                Some(false) => {}
+                None => verdict = None,
+            }
+        }
+        verdict
+    }
+}
+
+/// Matches the keys its filter doesn't.
+///
+/// The inner filter accepting a database, type or expiry doesn't mean it
+/// accepts every key with it, so nothing is skipped before the key was read.
+pub struct Not<L: Filter> {
+    filter: L,
+}
+
+impl<L: Filter> Not<L> {
+    pub fn new(filter: L) -> Self {
+        Not { filter }
+    }
+}
+
+impl<L: Filter> Filter for Not<L> {
+    fn inspects_values(&self) -> bool {
+        self.filter.inspects_values()
+    }
+
+    fn selects_databases(&self) -> bool {
+        self.filter.selects_databases()
+    }
+
+    fn matches(&self, key: &KeyInfo) -> bool {
+        // Until the value was scanned the inner filter only says whether the
+        // key might match.
+        if key.value.is_none() && self.filter.inspects_values() {
+            return true;
+        }
+        !self.filter.matches(key)
+    }
+
+    fn matches_command(&self, key: &CommandKey) -> Option<bool> {
+        self.filter.matches_command(key).map(|matched| !matched)
+    }
+}
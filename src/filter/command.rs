@@ -0,0 +1,143 @@
+use super::{CommandKey, Filter};
+use crate::command::{Command, CommandSpec};
+
+/// Applies a filter to the command stream following a dump, so it only
+/// writes the keys a dump parsed with the same filter holds.
+///
+/// Keys are found in the arguments from the command key-spec table. A
+/// command is dropped if none of its keys match. If only some do, commands
+/// applying to each key on its own, like `DEL` and `MSET`, are cut down to
+/// those, others are passed on whole. So are commands with keys the filter
+/// can't decide on without their value or expiry, and commands without
+/// keys, including ones missing from the table. `SELECT` is tracked for the
+/// database of the keys and always passed on, `FLUSHDB` only for matching
+/// databases and `FLUSHALL` only if the filter doesn't select databases.
+pub struct CommandFilter<L: Filter> {
+    filter: L,
+    db: u64,
+    allowed: Vec<Vec<u8>>,
+    denied: Vec<Vec<u8>>,
+}
+
+impl<L: Filter> CommandFilter<L> {
+    pub fn new(filter: L) -> Self {
+        CommandFilter {
+            filter,
+            db: 0,
+            allowed: Vec::new(),
+            denied: Vec::new(),
+        }
+    }
+
+    /// Only passes on commands with the name, and the others allowed. Can be
+    /// called multiple times.
+    pub fn allow_command(&mut self, name: &str) {
+        self.allowed.push(name.to_ascii_lowercase().into_bytes());
+    }
+
+    /// Drops commands with the name.
+    pub fn deny_command(&mut self, name: &str) {
+        self.denied.push(name.to_ascii_lowercase().into_bytes());
+    }
+
+    pub fn filter(&self) -> &L {
+        &self.filter
+    }
+
+    /// The database the stream currently writes to.
+    pub fn db(&self) -> u64 {
+        self.db
+    }
+
+    /// Sets the database the stream writes to, as a `SELECT` does.
+    pub fn set_db(&mut self, db: u64) {
+        self.db = db;
+    }
+
+    fn allows(&self, name: &[u8]) -> bool {
+        (self.allowed.is_empty() || self.allowed.iter().any(|n| n == name))
+            && !self.denied.iter().any(|n| n == name)
+    }
+
+    /// Decides on a command starting at `offset` of the stream, returning
+    /// what should be passed on of it with the database it applies to.
+    pub fn apply_at(&mut self, offset: u64, args: Vec<Vec<u8>>) -> Option<Command> {
+        self.apply(args).map(|args| Command {
+            offset,
+            db: self.db,
+            args,
+        })
+    }
+
+    /// Decides on a command, returning what should be passed on of it.
+    pub fn apply(&mut self, args: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+        let name = args.first()?.to_ascii_lowercase();
+        if name == b"select" {
+            if let Some(db) = args
+                .get(1)
+                .and_then(|db| std::str::from_utf8(db).ok())
+                .and_then(|db| db.parse().ok())
+            {
+                self.db = db;
+            }
+            return Some(args);
+        }
+        if !self.allows(&name) {
+            return None;
+        }
+        if name == b"flushdb" {
+            return if self.filter.matches_db(self.db) {
+                Some(args)
+            } else {
+                None
+            };
+        }
+        if name == b"flushall" {
+            return if self.filter.selects_databases() {
+                None
+            } else {
+                Some(args)
+            };
+        }
+
+        let spec = match CommandSpec::find(&name) {
+            Some(spec) => spec,
+            None => return Some(args),
+        };
+        let positions = spec.key_positions(&args);
+        if positions.is_empty() {
+            return Some(args);
+        }
+
+        let enc_type = spec.enc_type();
+        let matching: Vec<bool> = positions
+            .iter()
+            .map(|&i| {
+                let key = CommandKey {
+                    db: self.db,
+                    enc_type,
+                    key: &args[i],
+                };
+                self.filter.matches_command(&key).unwrap_or(true)
+            })
+            .collect();
+
+        if matching.iter().all(|&matched| matched) {
+            Some(args)
+        } else if !matching.iter().any(|&matched| matched) {
+            None
+        } else if spec.splittable {
+            // Keeps every matching key with the arguments up to the next key.
+            let mut kept = vec![args[0].clone()];
+            for (n, &start) in positions.iter().enumerate() {
+                if matching[n] {
+                    let end = positions.get(n + 1).copied().unwrap_or(args.len());
+                    kept.extend_from_slice(&args[start..end]);
+                }
+            }
+            Some(kept)
+        } else {
+            Some(args)
+        }
+    }
+}
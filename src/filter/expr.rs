@@ -0,0 +1,477 @@
+//! A small language to combine filters on the command line:
+//!
+//! ```text
+//! db in (0, 3) and type = hash and key ~ "^user:" and not key ~ "tmp"
+//! ```
+//!
+//! `db`, `type` and `encoding` compare with `=`, `!=` or `in (..)`. `key`
+//! compares with `=` and `!=` against a literal, with `~` and `!~` against a
+//! regular expression, and with `glob` and `prefix` against a Redis glob
+//! pattern or a prefix, or any out of `in (..)`. `size`, in bytes or with a
+//! `k`, `m` or `g` suffix, and `len` compare with `=`, `!=`, `<`, `<=`, `>`
+//! and `>=`. `expiry` compares with `=` and `!=` against `none`, and with
+//! `<`, `<=`, `>` and `>=` against a unix time in milliseconds, `expired`
+//! matches keys which already expired.
+//!
+//! Predicates combine with `and`, `or`, `not` and parentheses, `not` binding
+//! tighter than `and`, which binds tighter than `or`.
+
+use super::{AllOf, AnyOf, Encodings, Expiry, Filter, Glob, Length, Not, Prefixes, Simple, Size};
+use crate::types::{EncodingType, RdbError, RdbResult, Type};
+use regex::bytes::Regex;
+use std::io;
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Open,
+    Close,
+    Comma,
+    Eq,
+    NotEq,
+    Match,
+    NotMatch,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+}
+
+fn error(desc: &str, offset: usize) -> RdbError {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} at offset {}", desc, offset),
+    )
+}
+
+fn tokenize(input: &str) -> RdbResult<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '=' => Token::Eq,
+            '~' => Token::Match,
+            '<' | '>' => {
+                let eq = chars.peek().map(|&(_, c)| c) == Some('=');
+                if eq {
+                    chars.next();
+                }
+                match (c, eq) {
+                    ('<', false) => Token::Less,
+                    ('<', true) => Token::LessEq,
+                    (_, false) => Token::Greater,
+                    (_, true) => Token::GreaterEq,
+                }
+            }
+            '!' => match chars.next() {
+                Some((_, '=')) => Token::NotEq,
+                Some((_, '~')) => Token::NotMatch,
+                _ => return Err(error("Expected != or !~", offset)),
+            },
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => string.push(c),
+                            None => return Err(error("Unterminated string", offset)),
+                        },
+                        Some((_, c)) => string.push(c),
+                        None => return Err(error("Unterminated string", offset)),
+                    }
+                }
+                Token::Str(string)
+            }
+            _ if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !is_word_char(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+            _ => return Err(error(&format!("Unexpected character {:?}", c), offset)),
+        };
+        tokens.push((token, offset));
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == ':'
+}
+
+fn negated<L: Filter + 'static>(filter: L, negate: bool) -> Box<dyn Filter> {
+    if negate {
+        Box::new(Not::new(filter))
+    } else {
+        Box::new(filter)
+    }
+}
+
+fn db_filter(dbs: Vec<u64>) -> Simple {
+    let mut filter = Simple::new();
+    for db in dbs {
+        filter.add_database(db);
+    }
+    filter
+}
+
+fn type_filter(types: Vec<Type>) -> Simple {
+    let mut filter = Simple::new();
+    for typ in types {
+        filter.add_type(typ);
+    }
+    filter
+}
+
+fn encoding_filter(names: Vec<String>) -> Encodings {
+    let mut filter = Encodings::new();
+    for name in names {
+        filter.add_encoding(&name);
+    }
+    filter
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |&(_, offset)| offset)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w == word => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, token: Token, desc: &str) -> RdbResult<()> {
+        let offset = self.offset();
+        match self.next() {
+            Some(ref t) if *t == token => Ok(()),
+            _ => Err(error(&format!("Expected {}", desc), offset)),
+        }
+    }
+
+    fn or(&mut self) -> RdbResult<Box<dyn Filter>> {
+        let first = self.and()?;
+        if self.peek() != Some(&Token::Word("or".to_string())) {
+            return Ok(first);
+        }
+
+        let mut any = AnyOf::new();
+        any.add(first);
+        while self.eat_word("or") {
+            any.add(self.and()?);
+        }
+        Ok(Box::new(any))
+    }
+
+    fn and(&mut self) -> RdbResult<Box<dyn Filter>> {
+        let first = self.unary()?;
+        if self.peek() != Some(&Token::Word("and".to_string())) {
+            return Ok(first);
+        }
+
+        let mut all = AllOf::new();
+        all.add(first);
+        while self.eat_word("and") {
+            all.add(self.unary()?);
+        }
+        Ok(Box::new(all))
+    }
+
+    fn unary(&mut self) -> RdbResult<Box<dyn Filter>> {
+        if self.eat_word("not") {
+            return Ok(Box::new(Not::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let filter = self.or()?;
+            self.expect(Token::Close, ")")?;
+            return Ok(filter);
+        }
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> RdbResult<Box<dyn Filter>> {
+        let field_offset = self.offset();
+        let field = match self.next() {
+            Some(Token::Word(field)) => field,
+            _ => return Err(error("Expected a field", field_offset)),
+        };
+        if field == "expired" {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_millis() as u64);
+            return Ok(Box::new(Expiry::Before(now)));
+        }
+
+        let offset = self.offset();
+        let op = self.next();
+        let (filter, negate) = match (&field[..], op) {
+            ("db", Some(Token::Eq)) => (db_filter(vec![self.database()?]), false),
+            ("db", Some(Token::NotEq)) => (db_filter(vec![self.database()?]), true),
+            ("db", Some(Token::Word(ref w))) if w == "in" => {
+                let dbs = self.list(Parser::database)?;
+                (db_filter(dbs), false)
+            }
+            ("type", Some(Token::Eq)) => (type_filter(vec![self.typ()?]), false),
+            ("type", Some(Token::NotEq)) => (type_filter(vec![self.typ()?]), true),
+            ("type", Some(Token::Word(ref w))) if w == "in" => {
+                let types = self.list(Parser::typ)?;
+                (type_filter(types), false)
+            }
+            ("key", Some(Token::Eq)) => (self.key(true)?, false),
+            ("key", Some(Token::NotEq)) => (self.key(true)?, true),
+            ("key", Some(Token::Match)) => (self.key(false)?, false),
+            ("key", Some(Token::NotMatch)) => (self.key(false)?, true),
+            ("key", Some(Token::Word(ref w))) if w == "glob" => {
+                let mut glob = Glob::new();
+                for pattern in self.keys()? {
+                    glob.add_pattern(pattern.as_bytes());
+                }
+                return Ok(Box::new(glob));
+            }
+            ("key", Some(Token::Word(ref w))) if w == "prefix" => {
+                let mut prefixes = Prefixes::new();
+                for prefix in self.keys()? {
+                    prefixes.add_prefix(prefix.as_bytes());
+                }
+                return Ok(Box::new(prefixes));
+            }
+            ("encoding", Some(Token::Eq)) => {
+                return Ok(negated(encoding_filter(vec![self.encoding()?]), false))
+            }
+            ("encoding", Some(Token::NotEq)) => {
+                return Ok(negated(encoding_filter(vec![self.encoding()?]), true))
+            }
+            ("encoding", Some(Token::Word(ref w))) if w == "in" => {
+                let names = self.list(Parser::encoding)?;
+                return Ok(negated(encoding_filter(names), false));
+            }
+            ("size", Some(op)) => {
+                let (range, negate) = self.range(op, offset, Parser::size)?;
+                return Ok(negated(Size::new(range), negate));
+            }
+            ("len", Some(op)) => {
+                let (range, negate) = self.range(op, offset, Parser::number)?;
+                return Ok(negated(Length::new(range), negate));
+            }
+            ("expiry", Some(op)) => return self.expiry(op, offset),
+            ("db", _) | ("type", _) | ("encoding", _) => {
+                return Err(error("Expected =, != or in", offset))
+            }
+            ("key", _) => return Err(error("Expected =, !=, ~, !~, glob or prefix", offset)),
+            _ => return Err(error(&format!("Unknown field {}", field), field_offset)),
+        };
+
+        Ok(negated(filter, negate))
+    }
+
+    fn encoding(&mut self) -> RdbResult<String> {
+        let offset = self.offset();
+        let name = self.string()?;
+        let known = (0..=u8::MAX)
+            .filter_map(EncodingType::from_enc_type)
+            .any(|encoding| encoding.name() == name);
+        if !known {
+            return Err(error(&format!("Unknown encoding {}", name), offset));
+        }
+        Ok(name)
+    }
+
+    // The values a comparison matches, and whether it matches the others
+    // instead.
+    fn range(
+        &mut self,
+        op: Token,
+        offset: usize,
+        number: fn(&mut Parser) -> RdbResult<u64>,
+    ) -> RdbResult<(RangeInclusive<u64>, bool)> {
+        let everything = (0..=u64::MAX, true);
+        let range = match op {
+            Token::Eq => {
+                let n = number(self)?;
+                (n..=n, false)
+            }
+            Token::NotEq => {
+                let n = number(self)?;
+                (n..=n, true)
+            }
+            Token::Less => match number(self)?.checked_sub(1) {
+                Some(n) => (0..=n, false),
+                None => everything,
+            },
+            Token::LessEq => (0..=number(self)?, false),
+            Token::Greater => match number(self)?.checked_add(1) {
+                Some(n) => (n..=u64::MAX, false),
+                None => everything,
+            },
+            Token::GreaterEq => (number(self)?..=u64::MAX, false),
+            _ => return Err(error("Expected =, !=, <, <=, > or >=", offset)),
+        };
+        Ok(range)
+    }
+
+    fn expiry(&mut self, op: Token, offset: usize) -> RdbResult<Box<dyn Filter>> {
+        let expiry = match op {
+            Token::Eq | Token::NotEq => {
+                if !self.eat_word("none") {
+                    return Err(error("Expected none", self.offset()));
+                }
+                if op == Token::Eq {
+                    Expiry::Persistent
+                } else {
+                    Expiry::Volatile
+                }
+            }
+            Token::Less => Expiry::Before(self.number()?),
+            Token::LessEq => Expiry::Before(self.number()?.saturating_add(1)),
+            Token::Greater => Expiry::After(self.number()?.saturating_add(1)),
+            Token::GreaterEq => Expiry::After(self.number()?),
+            _ => return Err(error("Expected =, !=, <, <=, > or >=", offset)),
+        };
+        Ok(Box::new(expiry))
+    }
+
+    fn number(&mut self) -> RdbResult<u64> {
+        let offset = self.offset();
+        match self.next() {
+            Some(Token::Word(ref w)) => w.parse().map_err(|_| error("Expected a number", offset)),
+            _ => Err(error("Expected a number", offset)),
+        }
+    }
+
+    // A number of bytes, optionally in kilobytes, megabytes or gigabytes.
+    fn size(&mut self) -> RdbResult<u64> {
+        let offset = self.offset();
+        let word = match self.next() {
+            Some(Token::Word(w)) => w.to_ascii_lowercase(),
+            _ => return Err(error("Expected a size", offset)),
+        };
+        let digits = word.trim_end_matches(char::is_alphabetic);
+        let unit: u64 = match &word[digits.len()..] {
+            "" | "b" => 1,
+            "k" | "kb" => 1 << 10,
+            "m" | "mb" => 1 << 20,
+            "g" | "gb" => 1 << 30,
+            _ => return Err(error("Expected a size", offset)),
+        };
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+            .ok_or_else(|| error("Expected a size", offset))
+    }
+
+    // A single key or pattern, or a list of them after `in`.
+    fn keys(&mut self) -> RdbResult<Vec<String>> {
+        if self.eat_word("in") {
+            self.list(Parser::string)
+        } else {
+            Ok(vec![self.string()?])
+        }
+    }
+
+    fn string(&mut self) -> RdbResult<String> {
+        let offset = self.offset();
+        match self.next() {
+            Some(Token::Str(s)) | Some(Token::Word(s)) => Ok(s),
+            _ => Err(error("Expected a key", offset)),
+        }
+    }
+
+    fn list<T>(&mut self, item: fn(&mut Parser) -> RdbResult<T>) -> RdbResult<Vec<T>> {
+        self.expect(Token::Open, "(")?;
+        let mut items = vec![item(self)?];
+        while self.peek() == Some(&Token::Comma) {
+            self.pos += 1;
+            items.push(item(self)?);
+        }
+        self.expect(Token::Close, ")")?;
+        Ok(items)
+    }
+
+    fn database(&mut self) -> RdbResult<u64> {
+        let offset = self.offset();
+        match self.next() {
+            Some(Token::Word(ref w)) => w.parse().map_err(|_| error("Expected a database", offset)),
+            _ => Err(error("Expected a database", offset)),
+        }
+    }
+
+    fn typ(&mut self) -> RdbResult<Type> {
+        let offset = self.offset();
+        match self.next() {
+            Some(Token::Word(ref w)) | Some(Token::Str(ref w)) => {
+                Type::from_name(w).ok_or_else(|| error(&format!("Unknown type {}", w), offset))
+            }
+            _ => Err(error("Expected a type", offset)),
+        }
+    }
+
+    fn key(&mut self, literal: bool) -> RdbResult<Simple> {
+        let offset = self.offset();
+        let pattern = self.string()?;
+        let pattern = if literal {
+            format!("^{}$", regex::escape(&pattern))
+        } else {
+            pattern
+        };
+        let re = Regex::new(&pattern)
+            .map_err(|e| error(&format!("Invalid regular expression: {}", e), offset))?;
+
+        let mut filter = Simple::new();
+        filter.add_keys(re);
+        Ok(filter)
+    }
+}
+
+/// Compiles a filter expression into the filters it describes.
+pub fn parse_expr(input: &str) -> RdbResult<Box<dyn Filter>> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        end: input.len(),
+    };
+    let filter = parser.or()?;
+    if parser.peek().is_some() {
+        return Err(error("Unexpected input", parser.offset()));
+    }
+    Ok(filter)
+}
@@ -0,0 +1,153 @@
+use super::Filter;
+
+// Redis gives up on patterns nesting `*` deeper than this.
+const MAX_NESTING: usize = 1000;
+
+/// Matches keys against Redis glob patterns, as used by `KEYS` and `SCAN`.
+/// A key matching any of the patterns matches, no patterns match every key.
+#[derive(Default)]
+pub struct Glob {
+    patterns: Vec<Vec<u8>>,
+}
+
+impl Glob {
+    pub fn new() -> Self {
+        Glob::default()
+    }
+
+    pub fn add_pattern(&mut self, pattern: &[u8]) {
+        self.patterns.push(pattern.to_vec());
+    }
+}
+
+impl Filter for Glob {
+    fn matches_key(&self, key: &[u8]) -> bool {
+        self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| glob_match(pattern, key, false))
+    }
+}
+
+/// Matches `string` against a glob `pattern` the way Redis' `stringmatchlen`
+/// does: `*` matches any bytes, `?` any single byte, `[abc]`, `[^abc]` and
+/// `[a-z]` a byte out of (or not out of) a set, and `\` escapes the next
+/// byte.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut skip_longer = false;
+    matches(pattern, string, nocase, &mut skip_longer, 0)
+}
+
+fn eq(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+// Once a `*` failed to match with the rest of the string, a `*` before it
+// trying a shorter prefix can't match either, so `skip_longer` stops the
+// search instead of backtracking exponentially.
+fn matches(
+    mut pattern: &[u8],
+    mut string: &[u8],
+    nocase: bool,
+    skip_longer: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    while !pattern.is_empty() && !string.is_empty() {
+        match pattern[0] {
+            b'*' => {
+                while pattern.len() > 1 && pattern[1] == b'*' {
+                    pattern = &pattern[1..];
+                }
+                if pattern.len() == 1 {
+                    return true;
+                }
+                while !string.is_empty() {
+                    if matches(&pattern[1..], string, nocase, skip_longer, nesting + 1) {
+                        return true;
+                    }
+                    if *skip_longer {
+                        return false;
+                    }
+                    string = &string[1..];
+                }
+                *skip_longer = true;
+                return false;
+            }
+            b'?' => string = &string[1..],
+            b'[' => {
+                let mut i = 1;
+                let negate = pattern.get(i) == Some(&b'^');
+                if negate {
+                    i += 1;
+                }
+
+                let mut matched = false;
+                loop {
+                    if i + 1 < pattern.len() && pattern[i] == b'\\' {
+                        i += 1;
+                        matched |= pattern[i] == string[0];
+                    } else if i < pattern.len() && pattern[i] == b']' {
+                        break;
+                    } else if i >= pattern.len() {
+                        // An unterminated class ends with the pattern.
+                        i -= 1;
+                        break;
+                    } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' {
+                        let (mut start, mut end, mut c) = (pattern[i], pattern[i + 2], string[0]);
+                        if start > end {
+                            std::mem::swap(&mut start, &mut end);
+                        }
+                        if nocase {
+                            start = start.to_ascii_lowercase();
+                            end = end.to_ascii_lowercase();
+                            c = c.to_ascii_lowercase();
+                        }
+                        i += 2;
+                        matched |= c >= start && c <= end;
+                    } else {
+                        matched |= eq(pattern[i], string[0], nocase);
+                    }
+                    i += 1;
+                }
+
+                if negate {
+                    matched = !matched;
+                }
+                if !matched {
+                    return false;
+                }
+                pattern = &pattern[i..];
+                string = &string[1..];
+            }
+            mut c => {
+                if c == b'\\' && pattern.len() >= 2 {
+                    pattern = &pattern[1..];
+                    c = pattern[0];
+                }
+                if !eq(c, string[0], nocase) {
+                    return false;
+                }
+                string = &string[1..];
+            }
+        }
+
+        pattern = &pattern[1..];
+        if string.is_empty() {
+            while pattern.first() == Some(&b'*') {
+                pattern = &pattern[1..];
+            }
+            break;
+        }
+    }
+
+    pattern.is_empty() && string.is_empty()
+}
@@ -0,0 +1,174 @@
+pub use self::combinators::{AllOf, AnyOf, Not};
+pub use self::command::CommandFilter;
+pub use self::expr::parse_expr;
+pub use self::glob::{glob_match, Glob};
+pub use self::prefix::Prefixes;
+pub use self::sample::{KeySet, Reservoir, Sample};
+pub use self::simple::Simple;
+pub use self::value::{Encodings, Expiry, Length, Size};
+
+use crate::types::EncodingType;
+
+pub mod combinators;
+pub mod command;
+pub mod expr;
+pub mod glob;
+pub mod prefix;
+pub mod sample;
+pub mod simple;
+pub mod value;
+
+/// What is known about a key when the filter is asked about it.
+pub struct KeyInfo<'a> {
+    pub db: u64,
+    pub enc_type: u8,
+    pub key: &'a [u8],
+    /// The absolute expiry in milliseconds.
+    pub expiry: Option<u64>,
+    /// Only filled in for filters inspecting values, once the value was
+    /// scanned.
+    pub value: Option<&'a ValueInfo>,
+}
+
+/// What a scan of a value found out without decoding its elements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueInfo {
+    pub encoding: EncodingType,
+    /// The number of elements, or the length of a string, as `STRLEN`,
+    /// `LLEN`, `SCARD`, `ZCARD`, `HLEN` and `XLEN` report them.
+    pub len: u64,
+    /// The size of the serialized value in the dump.
+    pub size: u64,
+}
+
+/// A key a replicated command touches. Its type is only known if the command
+/// implies it.
+pub struct CommandKey<'a> {
+    pub db: u64,
+    pub enc_type: Option<u8>,
+    pub key: &'a [u8],
+}
+
+/// Decides which keys the parser hands to the formatter.
+///
+/// `matches_db` is asked once per database, `matches_type` and
+/// `matches_expiry` once per key, before anything else is read, so the
+/// parser can skip what can't match without decoding it. They must only
+/// return false if no key in the database, of the type or with the expiry
+/// can match. `matches` makes the call once the key was read.
+///
+/// A filter that `inspects_values` is asked a second time with the
+/// `ValueInfo` of a key it didn't reject the first time. The parser finds
+/// it out from the length prefixes and headers of the value, keeping what
+/// it read in memory in case the key matches. Values made of many strings,
+/// such as hash tables and quicklists, are read whole, so only filters
+/// deciding on values should ask for this.
+///
+/// `matches_command` decides on keys of the command stream following a
+/// dump, where neither values nor expiries are known, and types only for
+/// some commands. It returns `None` if the filter depends on something that
+/// isn't known, which filters deciding on types or expiries have to
+/// implement themselves. `selects_databases` tells whether `matches_db` may
+/// reject some database, for commands touching all of them like `FLUSHALL`.
+pub trait Filter {
+    fn matches_db(&self, _db: u64) -> bool {
+        true
+    }
+    fn matches_type(&self, _enc_type: u8) -> bool {
+        true
+    }
+    fn matches_key(&self, _key: &[u8]) -> bool {
+        true
+    }
+    fn matches_expiry(&self, _expiry: Option<u64>) -> bool {
+        true
+    }
+    fn matches_value(&self, _value: &ValueInfo) -> bool {
+        true
+    }
+    fn inspects_values(&self) -> bool {
+        false
+    }
+    fn selects_databases(&self) -> bool {
+        false
+    }
+    fn matches(&self, key: &KeyInfo) -> bool {
+        self.matches_db(key.db)
+            && self.matches_type(key.enc_type)
+            && self.matches_key(key.key)
+            && self.matches_expiry(key.expiry)
+            && key.value.is_none_or(|value| self.matches_value(value))
+    }
+    fn matches_command(&self, key: &CommandKey) -> Option<bool> {
+        if !self.matches_db(key.db) || !self.matches_key(key.key) {
+            return Some(false);
+        }
+        if self.inspects_values() {
+            return None;
+        }
+        Some(
+            key.enc_type
+                .is_none_or(|enc_type| self.matches_type(enc_type)),
+        )
+    }
+}
+
+impl<L: Filter + ?Sized> Filter for Box<L> {
+    fn matches_db(&self, db: u64) -> bool {
+        (**self).matches_db(db)
+    }
+    fn matches_type(&self, enc_type: u8) -> bool {
+        (**self).matches_type(enc_type)
+    }
+    fn matches_key(&self, key: &[u8]) -> bool {
+        (**self).matches_key(key)
+    }
+    fn matches_expiry(&self, expiry: Option<u64>) -> bool {
+        (**self).matches_expiry(expiry)
+    }
+    fn matches_value(&self, value: &ValueInfo) -> bool {
+        (**self).matches_value(value)
+    }
+    fn inspects_values(&self) -> bool {
+        (**self).inspects_values()
+    }
+    fn selects_databases(&self) -> bool {
+        (**self).selects_databases()
+    }
+    fn matches(&self, key: &KeyInfo) -> bool {
+        (**self).matches(key)
+    }
+    fn matches_command(&self, key: &CommandKey) -> Option<bool> {
+        (**self).matches_command(key)
+    }
+}
+
+impl<L: Filter + ?Sized> Filter for &L {
+    fn matches_db(&self, db: u64) -> bool {
+        (**self).matches_db(db)
+    }
+    fn matches_type(&self, enc_type: u8) -> bool {
+        (**self).matches_type(enc_type)
+    }
+    fn matches_key(&self, key: &[u8]) -> bool {
+        (**self).matches_key(key)
+    }
+    fn matches_expiry(&self, expiry: Option<u64>) -> bool {
+        (**self).matches_expiry(expiry)
+    }
+    fn matches_value(&self, value: &ValueInfo) -> bool {
+        (**self).matches_value(value)
+    }
+    fn inspects_values(&self) -> bool {
+        (**self).inspects_values()
+    }
+    fn selects_databases(&self) -> bool {
+        (**self).selects_databases()
+    }
+    fn matches(&self, key: &KeyInfo) -> bool {
+        (**self).matches(key)
+    }
+    fn matches_command(&self, key: &CommandKey) -> Option<bool> {
+        (**self).matches_command(key)
+    }
+}
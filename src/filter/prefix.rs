@@ -0,0 +1,72 @@
+use super::Filter;
+
+#[derive(Default)]
+struct Node {
+    // Sorted by byte, pointing into `Prefixes::nodes`.
+    children: Vec<(u8, usize)>,
+    terminal: bool,
+}
+
+/// Matches keys starting with any of its prefixes. Keeps them in a trie, so
+/// a key is matched in a single pass over its bytes however many prefixes
+/// there are. No prefixes match every key.
+pub struct Prefixes {
+    nodes: Vec<Node>,
+}
+
+impl Default for Prefixes {
+    fn default() -> Self {
+        Prefixes {
+            nodes: vec![Node::default()],
+        }
+    }
+}
+
+impl Prefixes {
+    pub fn new() -> Self {
+        Prefixes::default()
+    }
+
+    pub fn add_prefix(&mut self, prefix: &[u8]) {
+        let mut node = 0;
+        for &byte in prefix {
+            node = match self.nodes[node]
+                .children
+                .binary_search_by_key(&byte, |&(b, _)| b)
+            {
+                Ok(i) => self.nodes[node].children[i].1,
+                Err(i) => {
+                    let child = self.nodes.len();
+                    self.nodes.push(Node::default());
+                    self.nodes[node].children.insert(i, (byte, child));
+                    child
+                }
+            };
+        }
+        self.nodes[node].terminal = true;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1 && !self.nodes[0].terminal
+    }
+}
+
+impl Filter for Prefixes {
+    fn matches_key(&self, key: &[u8]) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        let mut node = &self.nodes[0];
+        for &byte in key {
+            if node.terminal {
+                return true;
+            }
+            match node.children.binary_search_by_key(&byte, |&(b, _)| b) {
+                Ok(i) => node = &self.nodes[node.children[i].1],
+                Err(_) => return false,
+            }
+        }
+        node.terminal
+    }
+}
@@ -0,0 +1,190 @@
+use super::{CommandKey, Filter, KeyInfo};
+use crate::types::Type;
+use std::cell::RefCell;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// FNV-1a over the key, finished like splitmix64 so nearby keys spread over
+// the whole range. Only depends on the bytes and the seed, so the same keys
+// are picked on every run and platform.
+fn key_hash(key: &[u8], seed: u64) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325 ^ seed;
+    for &byte in key {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Matches a fraction of the keys picked by a hash of the key, so the same
+/// keys are picked from a dump and from the commands following it, and by
+/// every run with the same seed.
+pub struct Sample {
+    fraction: f64,
+    seed: u64,
+}
+
+impl Sample {
+    /// Picks `fraction` of the keys, from 0.0 for none to 1.0 for all.
+    pub fn new(fraction: f64) -> Self {
+        Sample { fraction, seed: 0 }
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+impl Filter for Sample {
+    fn matches_key(&self, key: &[u8]) -> bool {
+        // The top 53 bits, as many as a f64 holds exactly.
+        let hash = key_hash(key, self.seed) >> 11;
+        (hash as f64) < self.fraction * (1u64 << 53) as f64
+    }
+}
+
+// The keys picked of a type with their hash and database, the highest hash
+// first so it can be replaced by a lower one.
+type Picked = BinaryHeap<(u64, u64, Vec<u8>)>;
+
+/// Picks `size` keys of every type among the keys another filter matches,
+/// the ones with the lowest hash, which are a uniformly random sample for a
+/// seed.
+///
+/// Which keys are picked is only known at the end of the dump, so this
+/// takes two passes: this filter matches nothing, the parser skips every key
+/// without decoding it, and the picked keys then make up a `KeySet` to
+/// parse the dump again with.
+pub struct Reservoir<L: Filter> {
+    filter: L,
+    size: usize,
+    seed: u64,
+    picked: RefCell<HashMap<Type, Picked>>,
+}
+
+impl<L: Filter> Reservoir<L> {
+    pub fn new(filter: L, size: usize) -> Self {
+        Reservoir {
+            filter,
+            size,
+            seed: 0,
+            picked: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// The keys picked from what was parsed.
+    pub fn into_keys(self) -> KeySet {
+        let mut keys = KeySet::new();
+        for heap in self.picked.into_inner().into_values() {
+            for (_, db, key) in heap {
+                keys.add_key(db, &key);
+            }
+        }
+        keys
+    }
+
+    fn pick(&self, info: &KeyInfo) {
+        let hash = key_hash(info.key, self.seed);
+        let mut picked = self.picked.borrow_mut();
+        let heap = picked
+            .entry(Type::from_encoding(info.enc_type))
+            .or_default();
+        if heap.len() < self.size {
+            heap.push((hash, info.db, info.key.to_vec()));
+        } else if heap.peek().is_some_and(|&(highest, _, _)| hash < highest) {
+            heap.pop();
+            heap.push((hash, info.db, info.key.to_vec()));
+        }
+    }
+}
+
+impl<L: Filter> Filter for Reservoir<L> {
+    fn matches_db(&self, db: u64) -> bool {
+        self.size > 0 && self.filter.matches_db(db)
+    }
+
+    fn selects_databases(&self) -> bool {
+        self.filter.selects_databases()
+    }
+
+    fn matches_type(&self, enc_type: u8) -> bool {
+        self.filter.matches_type(enc_type)
+    }
+
+    fn matches_expiry(&self, expiry: Option<u64>) -> bool {
+        self.filter.matches_expiry(expiry)
+    }
+
+    fn inspects_values(&self) -> bool {
+        self.filter.inspects_values()
+    }
+
+    fn matches(&self, key: &KeyInfo) -> bool {
+        let matched = self.filter.matches(key);
+        if key.value.is_none() && self.filter.inspects_values() {
+            // Has the parser scan the value to finish the decision.
+            return matched;
+        }
+        if matched {
+            self.pick(key);
+        }
+        false
+    }
+
+    fn matches_command(&self, _key: &CommandKey) -> Option<bool> {
+        Some(false)
+    }
+}
+
+/// Matches exactly the listed keys of each database.
+#[derive(Default)]
+pub struct KeySet {
+    keys: HashMap<u64, HashSet<Vec<u8>>>,
+}
+
+impl KeySet {
+    pub fn new() -> Self {
+        KeySet::default()
+    }
+
+    pub fn add_key(&mut self, db: u64, key: &[u8]) {
+        self.keys.entry(db).or_default().insert(key.to_vec());
+    }
+
+    pub fn contains(&self, db: u64, key: &[u8]) -> bool {
+        self.keys.get(&db).is_some_and(|keys| keys.contains(key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.values().map(HashSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Filter for KeySet {
+    fn matches_db(&self, db: u64) -> bool {
+        self.keys.contains_key(&db)
+    }
+
+    fn selects_databases(&self) -> bool {
+        true
+    }
+
+    fn matches(&self, key: &KeyInfo) -> bool {
+        self.contains(key.db, key.key)
+    }
+
+    fn matches_command(&self, key: &CommandKey) -> Option<bool> {
+        Some(self.contains(key.db, key.key))
+    }
+}
@@ -1,18 +1,6 @@
+use super::{CommandKey, Filter};
 use crate::types::Type;
-use regex::Regex;
-use std::str;
-
-pub trait Filter {
-    fn matches_db(&self, _db: u64) -> bool {
-        true
-    }
-    fn matches_type(&self, _enc_type: u8) -> bool {
-        true
-    }
-    fn matches_key(&self, _key: &[u8]) -> bool {
-        true
-    }
-}
+use regex::bytes::Regex;
 
 #[derive(Default)]
 pub struct Simple {
@@ -44,26 +32,38 @@ impl Filter for Simple {
         if self.databases.is_empty() {
             true
         } else {
-            self.databases.iter().any(|&x| x == db)
+            self.databases.contains(&db)
         }
     }
 
+    fn selects_databases(&self) -> bool {
+        !self.databases.is_empty()
+    }
+
     fn matches_type(&self, enc_type: u8) -> bool {
         if self.types.is_empty() {
             return true;
         }
 
         let typ = Type::from_encoding(enc_type);
-        self.types.iter().any(|x| *x == typ)
+        self.types.contains(&typ)
+    }
+
+    fn matches_command(&self, key: &CommandKey) -> Option<bool> {
+        if !self.matches_db(key.db) || !self.matches_key(key.key) {
+            return Some(false);
+        }
+        match key.enc_type {
+            Some(enc_type) => Some(self.matches_type(enc_type)),
+            None if self.types.is_empty() => Some(true),
+            None => None,
+        }
     }
 
     fn matches_key(&self, key: &[u8]) -> bool {
-        match self.keys.clone() {
+        match self.keys {
             None => true,
-            Some(re) => {
-                let key = unsafe { str::from_utf8_unchecked(key) };
-                re.is_match(key)
-            }
+            Some(ref re) => re.is_match(key),
         }
     }
 }
@@ -0,0 +1,112 @@
+use super::{CommandKey, Filter, ValueInfo};
+use crate::types::EncodingType;
+use std::ops::RangeInclusive;
+
+/// Matches keys by their expiry, given as an absolute time in milliseconds.
+/// Decided before the key is read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    /// Keys without an expiry.
+    Persistent,
+    /// Keys with an expiry.
+    Volatile,
+    /// Keys expiring before the time, which includes the ones already
+    /// expired when given the current time.
+    Before(u64),
+    /// Keys expiring at or after the time.
+    After(u64),
+}
+
+impl Filter for Expiry {
+    fn matches_expiry(&self, expiry: Option<u64>) -> bool {
+        match (*self, expiry) {
+            (Expiry::Persistent, expiry) => expiry.is_none(),
+            (Expiry::Volatile, expiry) => expiry.is_some(),
+            (Expiry::Before(time), Some(expiry)) => expiry < time,
+            (Expiry::After(time), Some(expiry)) => expiry >= time,
+            (_, None) => false,
+        }
+    }
+
+    fn matches_command(&self, _key: &CommandKey) -> Option<bool> {
+        None
+    }
+}
+
+/// Matches values taking a number of bytes within the range in the dump.
+pub struct Size {
+    range: RangeInclusive<u64>,
+}
+
+impl Size {
+    pub fn new(range: RangeInclusive<u64>) -> Self {
+        Size { range }
+    }
+}
+
+impl Filter for Size {
+    fn inspects_values(&self) -> bool {
+        true
+    }
+
+    fn matches_value(&self, value: &ValueInfo) -> bool {
+        self.range.contains(&value.size)
+    }
+}
+
+/// Matches values with a number of elements, or strings with a length,
+/// within the range.
+pub struct Length {
+    range: RangeInclusive<u64>,
+}
+
+impl Length {
+    pub fn new(range: RangeInclusive<u64>) -> Self {
+        Length { range }
+    }
+}
+
+impl Filter for Length {
+    fn inspects_values(&self) -> bool {
+        true
+    }
+
+    fn matches_value(&self, value: &ValueInfo) -> bool {
+        self.range.contains(&value.len)
+    }
+}
+
+/// Matches values stored with any of the encodings, named like Redis'
+/// `OBJECT ENCODING` names them. No encodings match every value.
+#[derive(Default)]
+pub struct Encodings {
+    names: Vec<String>,
+}
+
+impl Encodings {
+    pub fn new() -> Self {
+        Encodings::default()
+    }
+
+    pub fn add_encoding(&mut self, name: &str) {
+        self.names.push(name.to_string());
+    }
+}
+
+impl Filter for Encodings {
+    fn matches_type(&self, enc_type: u8) -> bool {
+        // The type fixes the encoding of everything but strings, all of
+        // which are named "string".
+        let name = EncodingType::from_enc_type(enc_type).map_or("", |e| e.name());
+        self.names.is_empty() || self.names.iter().any(|n| n == name)
+    }
+
+    // A command's type doesn't tell how the value is stored.
+    fn matches_command(&self, _key: &CommandKey) -> Option<bool> {
+        if self.names.is_empty() {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::types::RdbResult;
+use std::io;
+
+// Put in front of base64 encoded strings.
+const BASE64_MARKER: &str = "base64:";
+
+/// How the JSON formatters turn keys, values, fields and members into
+/// strings, which have to be valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteEncoding {
+    /// Keeps valid UTF-8 and writes every other byte as `\xNN`, the way
+    /// redis-rdb-tools does. Backslashes are doubled.
+    #[default]
+    Escape,
+    /// Keeps valid UTF-8 and writes anything else, or anything starting
+    /// with the `base64:` marker, as the marker followed by the base64 of
+    /// the bytes.
+    Base64,
+    /// Writes the bytes in lowercase hex.
+    Hex,
+}
+
+fn invalid(desc: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, desc)
+}
+
+impl ByteEncoding {
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            ByteEncoding::Escape => {
+                let mut encoded = String::with_capacity(bytes.len());
+                for chunk in bytes.utf8_chunks() {
+                    encoded.push_str(&chunk.valid().replace('\\', "\\\\"));
+                    for byte in chunk.invalid() {
+                        encoded.push_str(&format!("\\x{:02x}", byte));
+                    }
+                }
+                encoded
+            }
+            ByteEncoding::Base64 => match std::str::from_utf8(bytes) {
+                Ok(string) if !string.starts_with(BASE64_MARKER) => string.to_string(),
+                _ => format!("{}{}", BASE64_MARKER, base64::encode(bytes)),
+            },
+            ByteEncoding::Hex => hex::encode(bytes),
+        }
+    }
+
+    /// Turns a string written by `encode` back into the original bytes.
+    pub fn decode(&self, encoded: &str) -> RdbResult<Vec<u8>> {
+        match self {
+            ByteEncoding::Escape => {
+                let mut bytes = Vec::with_capacity(encoded.len());
+                let mut rest = encoded.as_bytes();
+                while let Some((&byte, tail)) = rest.split_first() {
+                    rest = tail;
+                    if byte != b'\\' {
+                        bytes.push(byte);
+                        continue;
+                    }
+                    match rest {
+                        [b'\\', tail @ ..] => {
+                            bytes.push(b'\\');
+                            rest = tail;
+                        }
+                        [b'x', high, low, tail @ ..] => {
+                            let digits = [*high, *low];
+                            let byte = std::str::from_utf8(&digits)
+                                .ok()
+                                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                                .ok_or_else(|| invalid("Invalid \\x escape"))?;
+                            bytes.push(byte);
+                            rest = tail;
+                        }
+                        _ => return Err(invalid("Invalid escape")),
+                    }
+                }
+                Ok(bytes)
+            }
+            ByteEncoding::Base64 => match encoded.strip_prefix(BASE64_MARKER) {
+                Some(data) => base64::decode(data).map_err(|_| invalid("Invalid base64")),
+                None => Ok(encoded.as_bytes().to_vec()),
+            },
+            ByteEncoding::Hex => hex::decode(encoded).map_err(|_| invalid("Invalid hex")),
+        }
+    }
+}
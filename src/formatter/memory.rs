@@ -0,0 +1,412 @@
+use crate::formatter::{ByteEncoding, Formatter};
+use crate::helper::as_integer;
+use crate::types::{EncodingType, KeyMetadata, RdbOk};
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use std::io::{self, Stdout, Write};
+
+// Sizes of a 64 bit build of Redis, as estimated by redis-rdb-tools.
+const POINTER_SIZE: u64 = 8;
+const LONG_SIZE: u64 = 8;
+const ROBJ_OVERHEAD: u64 = POINTER_SIZE + 8;
+const DICT_ENTRY_OVERHEAD: u64 = 2 * POINTER_SIZE + 8;
+const LINKEDLIST_OVERHEAD: u64 = LONG_SIZE + 5 * POINTER_SIZE;
+const LINKEDLIST_ENTRY_OVERHEAD: u64 = 3 * POINTER_SIZE;
+const QUICKLIST_OVERHEAD: u64 = 2 * POINTER_SIZE + LONG_SIZE + 2 * 4;
+const QUICKLIST_NODE_OVERHEAD: u64 = 4 * POINTER_SIZE + 8;
+const QUICKLIST_NODE_BYTES: u64 = 8192;
+const ZIPLIST_HEADER: u64 = 4 + 4 + 2 + 1;
+// Integers below this are shared objects and take no memory of their own.
+const SHARED_INTEGERS: i64 = 10000;
+
+const HEADER: &str =
+    "database,type,key,size_in_bytes,encoding,num_elements,len_largest_element,expiry\n";
+
+/// Writes a CSV report of the memory every key is estimated to take, the
+/// way the memory profiler of redis-rdb-tools does.
+///
+/// Estimates assume a 64 bit Redis using jemalloc. Compact encodings are
+/// sized from the length of their blob, the others from their elements.
+/// The contents of streams and module values aren't read, only the key
+/// itself is counted for them.
+pub struct Memory<W: Write = Stdout> {
+    out: W,
+    db: u64,
+    current: Option<Row>,
+    layout: Layout,
+    element_bytes: u64,
+    top: Option<usize>,
+    rows: Vec<Row>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    // Sized as a whole when the key starts.
+    Value,
+    Compact(u64),
+    LinkedList,
+    Quicklist,
+    Set,
+    Hash,
+    SortedSet,
+}
+
+struct Row {
+    db: u64,
+    type_name: &'static str,
+    key: Vec<u8>,
+    size: u64,
+    encoding: &'static str,
+    elements: u64,
+    largest: u64,
+    expiry: Option<u64>,
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory::with_output(io::stdout())
+    }
+}
+
+impl<W: Write> Memory<W> {
+    pub fn with_output(out: W) -> Memory<W> {
+        Memory {
+            out,
+            db: 0,
+            current: None,
+            layout: Layout::Value,
+            element_bytes: 0,
+            top: None,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn into_output(self) -> W {
+        self.out
+    }
+
+    /// Only reports the `top` biggest keys, biggest first, once the whole
+    /// dump was read.
+    pub fn set_top(&mut self, top: usize) {
+        self.top = Some(top);
+    }
+
+    fn start_key(
+        &mut self,
+        type_name: &'static str,
+        key: &[u8],
+        meta: &KeyMetadata,
+        info: EncodingType,
+        layout: Layout,
+    ) {
+        self.current = Some(Row {
+            db: self.db,
+            type_name,
+            key: key.to_vec(),
+            size: 0,
+            encoding: info.name(),
+            elements: 0,
+            largest: 0,
+            expiry: meta.expiry,
+        });
+        self.layout = match info {
+            EncodingType::Ziplist(length)
+            | EncodingType::Intset(length)
+            | EncodingType::Zipmap(length) => Layout::Compact(length),
+            _ => layout,
+        };
+        self.element_bytes = 0;
+    }
+
+    fn add_element(&mut self, parts: &[&[u8]]) {
+        let row = match self.current.as_mut() {
+            Some(row) => row,
+            None => return,
+        };
+        row.elements += 1;
+        for part in parts {
+            row.largest = row.largest.max(part.len() as u64);
+        }
+
+        let strings = parts.iter().map(|part| string_size(part)).sum::<u64>();
+        self.element_bytes += match self.layout {
+            Layout::Value | Layout::Compact(_) => 0,
+            Layout::LinkedList => LINKEDLIST_ENTRY_OVERHEAD + ROBJ_OVERHEAD + strings,
+            Layout::Quicklist => parts.iter().map(|part| ziplist_entry_size(part)).sum(),
+            Layout::Set | Layout::Hash => DICT_ENTRY_OVERHEAD + strings,
+            // The member is shared by the dict and the skiplist node, which
+            // has on average 4/3 levels of a pointer and a span each.
+            Layout::SortedSet => {
+                let levels = (POINTER_SIZE + 8) * 4 / 3;
+                DICT_ENTRY_OVERHEAD + malloc_size(2 * POINTER_SIZE + 8 + levels) + strings
+            }
+        };
+    }
+
+    fn end_key(&mut self) -> RdbOk {
+        let mut row = match self.current.take() {
+            Some(row) => row,
+            None => return Ok(()),
+        };
+        let structure = match self.layout {
+            Layout::Value => 0,
+            Layout::Compact(length) => malloc_size(length),
+            Layout::LinkedList => LINKEDLIST_OVERHEAD + self.element_bytes,
+            Layout::Quicklist => {
+                let nodes = self.element_bytes.div_ceil(QUICKLIST_NODE_BYTES).max(1);
+                let node_bytes = self.element_bytes / nodes + ZIPLIST_HEADER;
+                QUICKLIST_OVERHEAD + nodes * (QUICKLIST_NODE_OVERHEAD + malloc_size(node_bytes))
+            }
+            Layout::Set | Layout::Hash => dict_size(row.elements) + self.element_bytes,
+            Layout::SortedSet => {
+                let skiplist = 2 * POINTER_SIZE + 2 * LONG_SIZE;
+                dict_size(row.elements) + skiplist + self.element_bytes
+            }
+        };
+        row.size += key_size(&row.key, row.expiry) + structure;
+        self.add_row(row)
+    }
+
+    fn add_row(&mut self, row: Row) -> RdbOk {
+        match self.top {
+            Some(top) => {
+                self.rows.push(row);
+                if self.rows.len() >= 2 * top.max(1) {
+                    self.keep_top(top);
+                }
+                Ok(())
+            }
+            None => self.write_row(&row),
+        }
+    }
+
+    fn keep_top(&mut self, top: usize) {
+        self.rows.sort_by_key(|row| Reverse(row.size));
+        self.rows.truncate(top);
+    }
+
+    fn write_row(&mut self, row: &Row) -> RdbOk {
+        let expiry = row
+            .expiry
+            .map_or(String::new(), |expiry| expiry.to_string());
+        writeln!(
+            self.out,
+            "{},{},{},{},{},{},{},{}",
+            row.db,
+            row.type_name,
+            csv_field(&ByteEncoding::Escape.encode(&row.key)),
+            row.size,
+            row.encoding,
+            row.elements,
+            row.largest,
+            expiry
+        )
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Quotes fields containing a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// The size class jemalloc rounds an allocation up to.
+fn malloc_size(size: u64) -> u64 {
+    if size <= 8 {
+        8
+    } else if size <= 128 {
+        size.div_ceil(16) * 16
+    } else {
+        // Four classes between consecutive powers of two.
+        let step = size.next_power_of_two() / 8;
+        size.div_ceil(step) * step
+    }
+}
+
+// Strings holding an integer are stored as one, others as an sds whose
+// header grows with its length.
+fn string_size(value: &[u8]) -> u64 {
+    match as_integer(value) {
+        Some(number) if (0..SHARED_INTEGERS).contains(&number) => 0,
+        Some(_) => 8,
+        None => {
+            let length = value.len() as u64;
+            let header = match length {
+                0..=31 => 1,
+                32..=255 => 3,
+                256..=65535 => 5,
+                65536..=0xFFFF_FFFF => 9,
+                _ => 17,
+            };
+            malloc_size(length + header + 1)
+        }
+    }
+}
+
+fn ziplist_entry_size(value: &[u8]) -> u64 {
+    let length = value.len() as u64;
+    let data = match as_integer(value) {
+        Some(0..=12) => 1,
+        Some(number) if i8::try_from(number).is_ok() => 2,
+        Some(number) if i16::try_from(number).is_ok() => 3,
+        Some(number) if (-(1 << 23)..(1 << 23)).contains(&number) => 4,
+        Some(number) if i32::try_from(number).is_ok() => 5,
+        Some(_) => 9,
+        None if length < 64 => 1 + length,
+        None if length < 16384 => 2 + length,
+        None => 5 + length,
+    };
+    // Assumes the previous entry was short enough for a 1 byte length.
+    1 + data
+}
+
+// A dict with its two tables, of which only the first is used outside of
+// rehashing. The table holds one pointer per bucket, a power of two.
+fn dict_size(elements: u64) -> u64 {
+    let buckets = elements.next_power_of_two();
+    4 + 7 * LONG_SIZE + 4 * POINTER_SIZE + buckets * POINTER_SIZE * 3 / 2
+}
+
+// The entry in the keyspace, the object of the value and the expiry.
+fn key_size(key: &[u8], expiry: Option<u64>) -> u64 {
+    let expiry = match expiry {
+        Some(_) => DICT_ENTRY_OVERHEAD + LONG_SIZE,
+        None => 0,
+    };
+    DICT_ENTRY_OVERHEAD + string_size(key) + ROBJ_OVERHEAD + expiry
+}
+
+impl<W: Write> Formatter for Memory<W> {
+    fn start_rdb(&mut self) -> RdbOk {
+        self.out.write_all(HEADER.as_bytes())
+    }
+
+    fn end_rdb(&mut self) -> RdbOk {
+        if let Some(top) = self.top {
+            self.keep_top(top);
+            for row in std::mem::take(&mut self.rows) {
+                self.write_row(&row)?;
+            }
+        }
+        self.out.flush()
+    }
+
+    fn start_database(&mut self, db_index: u64) -> RdbOk {
+        self.db = db_index;
+        Ok(())
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8], meta: &KeyMetadata) -> RdbOk {
+        self.start_key("string", key, meta, EncodingType::String, Layout::Value);
+        if let Some(row) = self.current.as_mut() {
+            row.size = string_size(value);
+            row.elements = value.len() as u64;
+            row.largest = value.len() as u64;
+        }
+        self.end_key()
+    }
+
+    fn start_hash(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key("hash", key, meta, info, Layout::Hash);
+        Ok(())
+    }
+    fn end_hash(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key()
+    }
+    fn hash_element(&mut self, _key: &[u8], field: &[u8], value: &[u8]) -> RdbOk {
+        self.add_element(&[field, value]);
+        Ok(())
+    }
+
+    fn start_set(
+        &mut self,
+        key: &[u8],
+        _cardinality: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key("set", key, meta, info, Layout::Set);
+        Ok(())
+    }
+    fn end_set(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key()
+    }
+    fn set_element(&mut self, _key: &[u8], member: &[u8]) -> RdbOk {
+        self.add_element(&[member]);
+        Ok(())
+    }
+
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        let layout = match info {
+            EncodingType::Quicklist => Layout::Quicklist,
+            _ => Layout::LinkedList,
+        };
+        self.start_key("list", key, meta, info, layout);
+        Ok(())
+    }
+    fn end_list(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key()
+    }
+    fn list_element(&mut self, _key: &[u8], value: &[u8]) -> RdbOk {
+        self.add_element(&[value]);
+        Ok(())
+    }
+
+    fn start_sorted_set(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key("sortedset", key, meta, info, Layout::SortedSet);
+        Ok(())
+    }
+    fn end_sorted_set(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key()
+    }
+    fn sorted_set_element(&mut self, _key: &[u8], _score: f64, member: &[u8]) -> RdbOk {
+        self.add_element(&[member]);
+        Ok(())
+    }
+
+    fn stream(&mut self, key: &[u8], length: u64, meta: &KeyMetadata) -> RdbOk {
+        self.start_key(
+            "stream",
+            key,
+            meta,
+            EncodingType::StreamListpacks,
+            Layout::Value,
+        );
+        if let Some(row) = self.current.as_mut() {
+            row.elements = length;
+        }
+        self.end_key()
+    }
+
+    fn module(&mut self, key: &[u8], _name: &str, meta: &KeyMetadata) -> RdbOk {
+        self.start_key("module", key, meta, EncodingType::Moudle2, Layout::Value);
+        self.end_key()
+    }
+}
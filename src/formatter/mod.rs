@@ -1,56 +1,142 @@
 use std::io::Write;
 
+pub use self::bytes::ByteEncoding;
 pub use self::json::JSON;
+pub use self::memory::Memory;
+pub use self::ndjson::NDJSON;
 pub use self::nil::Nil;
 pub use self::plain::Plain;
 pub use self::protocol::Protocol;
+pub use self::records::{RecordHandler, Records};
+pub use self::stats::{Stats, StatsFormat};
+pub use self::visitor::{Control, Visitor};
 
-use super::types::EncodingType;
+use super::types::{EncodingType, KeyMetadata, RdbOk};
 
+pub mod bytes;
 pub mod json;
+pub mod memory;
+pub mod ndjson;
 pub mod nil;
 pub mod plain;
 pub mod protocol;
+pub mod records;
+pub mod stats;
+pub mod visitor;
 
-pub fn write_str<W: Write>(out: &mut W, data: &str) {
-    out.write(data.as_bytes()).unwrap();
+pub fn write_str<W: Write>(out: &mut W, data: &str) -> RdbOk {
+    out.write_all(data.as_bytes())
 }
 
+// Errors returned by a callback, usually from writing the output, end the
+// parse with that error.
 #[allow(unused_variables)]
 pub trait Formatter {
-    fn start_rdb(&mut self) {}
-    fn end_rdb(&mut self) {}
-    fn checksum(&mut self, checksum: &[u8]) {}
+    fn start_rdb(&mut self) -> RdbOk {
+        Ok(())
+    }
+    fn end_rdb(&mut self) -> RdbOk {
+        Ok(())
+    }
+    fn checksum(&mut self, checksum: &[u8]) -> RdbOk {
+        Ok(())
+    }
 
-    fn start_database(&mut self, db_index: u64) {}
-    fn end_database(&mut self, db_index: u64) {}
+    fn start_database(&mut self, db_index: u64) -> RdbOk {
+        Ok(())
+    }
+    fn end_database(&mut self, db_index: u64) -> RdbOk {
+        Ok(())
+    }
 
-    fn resizedb(&mut self, db_size: u64, expires_size: u64) {}
-    fn aux_field(&mut self, key: &[u8], value: &[u8]) {}
+    fn resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbOk {
+        Ok(())
+    }
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        Ok(())
+    }
 
-    fn set(&mut self, key: &[u8], value: &[u8], expiry: Option<u64>) {}
+    fn set(&mut self, key: &[u8], value: &[u8], meta: &KeyMetadata) -> RdbOk {
+        Ok(())
+    }
 
-    fn start_hash(&mut self, key: &[u8], length: u64, expiry: Option<u64>, info: EncodingType) {}
-    fn end_hash(&mut self, key: &[u8]) {}
-    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) {}
+    fn start_hash(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        Ok(())
+    }
+    fn end_hash(&mut self, key: &[u8]) -> RdbOk {
+        Ok(())
+    }
+    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) -> RdbOk {
+        Ok(())
+    }
 
-    fn start_set(&mut self, key: &[u8], cardinality: u64, expiry: Option<u64>, info: EncodingType) {
+    fn start_set(
+        &mut self,
+        key: &[u8],
+        cardinality: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        Ok(())
+    }
+    fn end_set(&mut self, key: &[u8]) -> RdbOk {
+        Ok(())
+    }
+    fn set_element(&mut self, key: &[u8], member: &[u8]) -> RdbOk {
+        Ok(())
     }
-    fn end_set(&mut self, key: &[u8]) {}
-    fn set_element(&mut self, key: &[u8], member: &[u8]) {}
 
-    fn start_list(&mut self, key: &[u8], length: u64, expiry: Option<u64>, info: EncodingType) {}
-    fn end_list(&mut self, key: &[u8]) {}
-    fn list_element(&mut self, key: &[u8], value: &[u8]) {}
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        Ok(())
+    }
+    fn end_list(&mut self, key: &[u8]) -> RdbOk {
+        Ok(())
+    }
+    fn list_element(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        Ok(())
+    }
 
     fn start_sorted_set(
         &mut self,
         key: &[u8],
         length: u64,
-        expiry: Option<u64>,
+        meta: &KeyMetadata,
         info: EncodingType,
-    ) {
+    ) -> RdbOk {
+        Ok(())
+    }
+    fn end_sorted_set(&mut self, key: &[u8]) -> RdbOk {
+        Ok(())
+    }
+    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) -> RdbOk {
+        Ok(())
+    }
+
+    // Stream entries and module values are skipped, only their presence is
+    // reported. A formatter which `wants_payloads` then gets them whole, as
+    // the payload `DUMP` returns.
+    fn stream(&mut self, key: &[u8], length: u64, meta: &KeyMetadata) -> RdbOk {
+        Ok(())
+    }
+    fn module(&mut self, key: &[u8], name: &str, meta: &KeyMetadata) -> RdbOk {
+        Ok(())
+    }
+    fn wants_payloads(&self) -> bool {
+        false
+    }
+    fn payload(&mut self, key: &[u8], payload: &[u8], meta: &KeyMetadata) -> RdbOk {
+        Ok(())
     }
-    fn end_sorted_set(&mut self, key: &[u8]) {}
-    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) {}
 }
@@ -0,0 +1,122 @@
+use crate::formatter::{ByteEncoding, RecordHandler};
+use crate::types::{KeyRecord, RdbOk, RedisValue};
+use serialize::{json, Map, Value};
+use std::io::{self, Stdout, Write};
+
+/// Writes one JSON object per line for every key, with its database, key,
+/// type, encoding, expiry, size and value.
+///
+/// Used through a `Records` adapter. When the adapter is given a maximum
+/// size, bigger values are split over several lines which repeat the fields
+/// of the key and carry the `chunk` number and a `last` flag. The size is
+/// the length of a string, the number of elements of a collection or the
+/// number of entries of a stream, counting only the elements on the line.
+pub struct NDJSON<W: Write = Stdout> {
+    out: W,
+    chunk: u64,
+    bytes: ByteEncoding,
+}
+
+impl NDJSON {
+    pub fn new() -> NDJSON {
+        NDJSON::with_output(io::stdout())
+    }
+}
+
+impl<W: Write> NDJSON<W> {
+    pub fn with_output(out: W) -> NDJSON<W> {
+        NDJSON {
+            out,
+            chunk: 0,
+            bytes: ByteEncoding::default(),
+        }
+    }
+
+    /// How keys, values, fields and members are written as JSON strings.
+    pub fn set_byte_encoding(&mut self, bytes: ByteEncoding) {
+        self.bytes = bytes;
+    }
+
+    pub fn into_output(self) -> W {
+        self.out
+    }
+
+    fn write_line(&mut self, line: Value) -> RdbOk {
+        serialize::to_writer(&mut self.out, &line)?;
+        self.out.write_all(b"\n")
+    }
+}
+
+impl Default for NDJSON {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// JSON has no infinite scores, they are written the way Redis prints them.
+fn score(score: f64) -> Value {
+    if score.is_finite() {
+        json!(score)
+    } else if score.is_nan() {
+        json!("nan")
+    } else if score > 0.0 {
+        json!("inf")
+    } else {
+        json!("-inf")
+    }
+}
+
+fn line(record: &KeyRecord, bytes: ByteEncoding) -> Map<String, Value> {
+    let string = |value: &[u8]| Value::String(bytes.encode(value));
+    let (size, value) = match &record.value {
+        RedisValue::String(value) => (value.len() as u64, string(value)),
+        RedisValue::List(values) | RedisValue::Set(values) => (
+            values.len() as u64,
+            Value::Array(values.iter().map(|value| string(value)).collect()),
+        ),
+        RedisValue::SortedSet(members) => (
+            members.len() as u64,
+            Value::Array(
+                members
+                    .iter()
+                    .map(|(member, value)| json!([string(member), score(*value)]))
+                    .collect(),
+            ),
+        ),
+        RedisValue::Hash(fields) => (
+            fields.len() as u64,
+            Value::Object(
+                fields
+                    .iter()
+                    .map(|(field, value)| (bytes.encode(field), string(value)))
+                    .collect(),
+            ),
+        ),
+        RedisValue::Stream { length } => (*length, Value::Null),
+        RedisValue::Module { name } => (1, json!(name)),
+    };
+
+    let mut line = Map::new();
+    line.insert("db".to_string(), json!(record.db));
+    line.insert("key".to_string(), string(&record.key));
+    line.insert("type".to_string(), json!(record.value.type_name()));
+    line.insert("encoding".to_string(), json!(record.encoding.name()));
+    line.insert("expiry".to_string(), json!(record.meta.expiry));
+    line.insert("size".to_string(), json!(size));
+    line.insert("value".to_string(), value);
+    line
+}
+
+impl<W: Write> RecordHandler for NDJSON<W> {
+    fn record(&mut self, record: KeyRecord) -> RdbOk {
+        self.write_line(Value::Object(line(&record, self.bytes)))
+    }
+
+    fn chunk(&mut self, record: KeyRecord, last: bool) -> RdbOk {
+        let mut line = line(&record, self.bytes);
+        line.insert("chunk".to_string(), json!(self.chunk));
+        line.insert("last".to_string(), json!(last));
+        self.chunk = if last { 0 } else { self.chunk + 1 };
+        self.write_line(Value::Object(line))
+    }
+}
@@ -1,124 +1,143 @@
-#![allow(unused_must_use)]
 use super::write_str;
 use crate::formatter::Formatter;
-// use serialize::hex::ToHex;
-use crate::types::EncodingType;
-use std::io;
-use std::io::Write;
+use crate::types::{EncodingType, KeyMetadata, RdbOk};
+use std::io::{self, Stdout, Write};
 
-pub struct Plain {
-    out: Box<Write + 'static>,
+pub struct Plain<W: Write = Stdout> {
+    out: W,
     dbnum: u64,
     index: u64,
 }
 
 impl Plain {
     pub fn new() -> Plain {
-        let out = Box::new(io::stdout());
+        Plain::with_output(io::stdout())
+    }
+}
+
+impl<W: Write> Plain<W> {
+    pub fn with_output(out: W) -> Plain<W> {
         Plain {
-            out: out,
+            out,
             dbnum: 0,
             index: 0,
         }
     }
 
-    fn write_line_start(&mut self) {
-        write_str(&mut self.out, &format!("db={} ", self.dbnum));
+    pub fn into_output(self) -> W {
+        self.out
+    }
+
+    fn write_line_start(&mut self) -> RdbOk {
+        write_str(&mut self.out, &format!("db={} ", self.dbnum))
     }
 }
 
-impl Drop for Plain {
-    fn drop(&mut self) {}
+impl Default for Plain {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Formatter for Plain {
-    fn checksum(&mut self, checksum: &[u8]) {
-        write_str(&mut self.out, "checksum ");
-        write_str(&mut self.out, &hex::encode(checksum));
-        write_str(&mut self.out, "\n");
+impl<W: Write> Formatter for Plain<W> {
+    fn checksum(&mut self, checksum: &[u8]) -> RdbOk {
+        write_str(&mut self.out, "checksum ")?;
+        write_str(&mut self.out, &hex::encode(checksum))?;
+        write_str(&mut self.out, "\n")
     }
 
-    fn start_database(&mut self, db_number: u64) {
+    fn start_database(&mut self, db_number: u64) -> RdbOk {
         self.dbnum = db_number;
+        Ok(())
     }
 
-    fn set(&mut self, key: &[u8], value: &[u8], _expiry: Option<u64>) {
-        self.write_line_start();
-        self.out.write_all(key);
-        write_str(&mut self.out, " -> ");
+    fn set(&mut self, key: &[u8], value: &[u8], _meta: &KeyMetadata) -> RdbOk {
+        self.write_line_start()?;
+        self.out.write_all(key)?;
+        write_str(&mut self.out, " -> ")?;
 
-        self.out.write_all(value);
-        write_str(&mut self.out, "\n");
-        self.out.flush();
+        self.out.write_all(value)?;
+        write_str(&mut self.out, "\n")?;
+        self.out.flush()
     }
 
-    fn aux_field(&mut self, key: &[u8], value: &[u8]) {
-        write_str(&mut self.out, "aux ");
-        self.out.write_all(key);
-        write_str(&mut self.out, " -> ");
-        self.out.write_all(value);
-        write_str(&mut self.out, "\n");
-        self.out.flush();
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        write_str(&mut self.out, "aux ")?;
+        self.out.write_all(key)?;
+        write_str(&mut self.out, " -> ")?;
+        self.out.write_all(value)?;
+        write_str(&mut self.out, "\n")?;
+        self.out.flush()
     }
 
-    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) {
-        self.write_line_start();
+    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) -> RdbOk {
+        self.write_line_start()?;
 
-        self.out.write_all(key);
-        write_str(&mut self.out, " . ");
-        self.out.write_all(field);
-        write_str(&mut self.out, " -> ");
-        self.out.write_all(value);
-        write_str(&mut self.out, "\n");
-        self.out.flush();
+        self.out.write_all(key)?;
+        write_str(&mut self.out, " . ")?;
+        self.out.write_all(field)?;
+        write_str(&mut self.out, " -> ")?;
+        self.out.write_all(value)?;
+        write_str(&mut self.out, "\n")?;
+        self.out.flush()
     }
 
-    fn set_element(&mut self, key: &[u8], member: &[u8]) {
-        self.write_line_start();
+    fn set_element(&mut self, key: &[u8], member: &[u8]) -> RdbOk {
+        self.write_line_start()?;
 
-        self.out.write_all(key);
-        write_str(&mut self.out, " { ");
-        self.out.write_all(member);
-        write_str(&mut self.out, " } ");
-        write_str(&mut self.out, "\n");
-        self.out.flush();
+        self.out.write_all(key)?;
+        write_str(&mut self.out, " { ")?;
+        self.out.write_all(member)?;
+        write_str(&mut self.out, " } ")?;
+        write_str(&mut self.out, "\n")?;
+        self.out.flush()
     }
 
-    fn start_list(&mut self, _key: &[u8], _length: u64, _expiry: Option<u64>, _info: EncodingType) {
+    fn start_list(
+        &mut self,
+        _key: &[u8],
+        _length: u64,
+        _meta: &KeyMetadata,
+        _info: EncodingType,
+    ) -> RdbOk {
         self.index = 0;
+        Ok(())
     }
-    fn list_element(&mut self, key: &[u8], value: &[u8]) {
-        self.write_line_start();
-
-        self.out.write_all(key);
-        write_str(&mut self.out, &format!("[{}]", self.index));
-        write_str(&mut self.out, " -> ");
-        self.out.write_all(value);
-        write_str(&mut self.out, "\n");
-        self.out.flush();
+    fn list_element(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        self.write_line_start()?;
+
+        self.out.write_all(key)?;
+        write_str(&mut self.out, &format!("[{}]", self.index))?;
+        write_str(&mut self.out, " -> ")?;
+        self.out.write_all(value)?;
+        write_str(&mut self.out, "\n")?;
+        self.out.flush()?;
         self.index += 1;
+        Ok(())
     }
 
     fn start_sorted_set(
         &mut self,
         _key: &[u8],
         _length: u64,
-        _expiry: Option<u64>,
+        _meta: &KeyMetadata,
         _info: EncodingType,
-    ) {
+    ) -> RdbOk {
         self.index = 0;
+        Ok(())
     }
 
-    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) {
-        self.write_line_start();
+    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) -> RdbOk {
+        self.write_line_start()?;
 
-        self.out.write_all(key);
-        write_str(&mut self.out, &format!("[{}]", self.index));
-        write_str(&mut self.out, " -> {");
-        self.out.write_all(member);
-        write_str(&mut self.out, &format!(", score={}", score));
-        write_str(&mut self.out, "}\n");
-        self.out.flush();
+        self.out.write_all(key)?;
+        write_str(&mut self.out, &format!("[{}]", self.index))?;
+        write_str(&mut self.out, " -> {")?;
+        self.out.write_all(member)?;
+        write_str(&mut self.out, &format!(", score={}", score))?;
+        write_str(&mut self.out, "}\n")?;
+        self.out.flush()?;
         self.index += 1;
+        Ok(())
     }
 }
@@ -1,119 +1,381 @@
-#![allow(unused_must_use)]
-
 use super::write_str;
+use crate::dump;
 use crate::formatter::Formatter;
-use crate::types::EncodingType;
-use std::io;
-use std::io::Write;
+use crate::types::{EncodingType, KeyMetadata, RdbOk, RedisValue};
+use std::io::{self, Stdout, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub struct Protocol {
-    out: Box<dyn Write + 'static>,
-    last_expiry: Option<u64>,
+pub struct Protocol<W: Write = Stdout> {
+    out: W,
+    last_meta: KeyMetadata,
+    // Values of keys carrying an LRU idle time or LFU counter are collected
+    // and written as a single RESTORE, the only command able to set both. In
+    // restore mode this is done for every key.
+    restore: Option<RedisValue>,
+    restore_mode: bool,
+    replace: bool,
+    // Command and arguments of the elements not written yet.
+    batch: Vec<Vec<u8>>,
+    batch_elements: usize,
+    batch_bytes: usize,
+    max_batch_elements: usize,
+    max_batch_bytes: usize,
+    reference_time: u64,
+    drop_expired: bool,
+    relative_expiry: bool,
+    string_pxat: bool,
+    // Set while an expired key is being left out.
+    skip_key: bool,
+    key_elements: usize,
 }
 
 impl Protocol {
     pub fn new() -> Protocol {
-        let out = Box::new(io::stdout());
+        Protocol::with_output(io::stdout())
+    }
+}
+
+impl<W: Write> Protocol<W> {
+    pub fn with_output(out: W) -> Protocol<W> {
         Protocol {
-            out: out,
-            last_expiry: None,
+            out,
+            last_meta: KeyMetadata::default(),
+            restore: None,
+            restore_mode: false,
+            replace: false,
+            batch: Vec::new(),
+            batch_elements: 0,
+            batch_bytes: 0,
+            max_batch_elements: 1,
+            max_batch_bytes: usize::MAX,
+            reference_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_millis() as u64),
+            drop_expired: false,
+            relative_expiry: false,
+            string_pxat: false,
+            skip_key: false,
+            key_elements: 0,
         }
     }
+
+    pub fn into_output(self) -> W {
+        self.out
+    }
+
+    /// The unix time in milliseconds expiries are compared with, the time
+    /// the formatter was created by default.
+    pub fn set_reference_time(&mut self, time: u64) {
+        self.reference_time = time;
+    }
+
+    /// Leaves out keys which expired before the reference time.
+    pub fn set_drop_expired(&mut self, drop_expired: bool) {
+        self.drop_expired = drop_expired;
+    }
+
+    /// Writes expiries relative to the reference time with `PEXPIRE`, for
+    /// targets whose clock differs from the one the dump was written with.
+    pub fn set_relative_expiry(&mut self, relative_expiry: bool) {
+        self.relative_expiry = relative_expiry;
+    }
+
+    /// Sets the expiry of strings together with the value, as
+    /// `SET key value PXAT ts` or `PX ttl`. Needs Redis 6.2.
+    pub fn set_string_pxat(&mut self, string_pxat: bool) {
+        self.string_pxat = string_pxat;
+    }
+
+    /// Writes up to `max_elements` elements of a key in one variadic
+    /// `HSET`, `SADD`, `RPUSH` or `ZADD`, as long as their arguments stay
+    /// within `max_bytes`. A single element is written on its own if it is
+    /// bigger than that. Variadic `HSET` needs Redis 4.0.
+    pub fn set_batch_size(&mut self, max_elements: usize, max_bytes: usize) {
+        self.max_batch_elements = max_elements.max(1);
+        self.max_batch_bytes = max_bytes;
+    }
+
+    /// Deletes every hash, set, list and sorted set before writing its
+    /// elements, so they replace whatever the target holds under that key.
+    pub fn set_replace(&mut self, replace: bool) {
+        self.replace = replace;
+    }
+
+    /// Writes every key as a single `RESTORE key ttl payload REPLACE`
+    /// instead of one command per element, much faster to load for big keys.
+    pub fn set_restore_mode(&mut self, restore_mode: bool) {
+        self.restore_mode = restore_mode;
+    }
 }
 
-impl Protocol {
-    fn emit(&mut self, args: Vec<&[u8]>) {
-        write_str(&mut self.out, "*");
-        self.out.write_all(args.len().to_string().as_bytes());
-        write_str(&mut self.out, "\r\n");
+impl Default for Protocol {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Write> Protocol<W> {
+    fn emit(&mut self, args: Vec<&[u8]>) -> RdbOk {
+        write_str(&mut self.out, "*")?;
+        self.out.write_all(args.len().to_string().as_bytes())?;
+        write_str(&mut self.out, "\r\n")?;
         for arg in &args {
-            write_str(&mut self.out, "$");
-            self.out.write_all(arg.len().to_string().as_bytes());
-            write_str(&mut self.out, "\r\n");
-            self.out.write_all(arg);
-            write_str(&mut self.out, "\r\n");
+            write_str(&mut self.out, "$")?;
+            self.out.write_all(arg.len().to_string().as_bytes())?;
+            write_str(&mut self.out, "\r\n")?;
+            self.out.write_all(arg)?;
+            write_str(&mut self.out, "\r\n")?;
         }
+        Ok(())
     }
 
-    fn pre_expire(&mut self, expiry: Option<u64>) {
-        self.last_expiry = expiry
+    fn uses_restore(&self, meta: &KeyMetadata) -> bool {
+        self.restore_mode || meta.idle.is_some() || meta.freq.is_some()
     }
 
-    fn post_expire(&mut self, key: &[u8]) {
-        if let Some(expire) = self.last_expiry {
-            let expire = expire.to_string();
-            self.emit(vec!["PEXPIREAT".as_bytes(), key, expire.as_bytes()]);
-            self.last_expiry = None;
+    fn pre_expire(&mut self, key: &[u8], meta: &KeyMetadata, restore: RedisValue) -> RdbOk {
+        self.last_meta = *meta;
+        self.key_elements = 0;
+        self.skip_key = self.drop_expired
+            && meta
+                .expiry
+                .is_some_and(|expiry| expiry <= self.reference_time);
+
+        if self.skip_key {
+            // Nothing is written for the key.
+        } else if self.uses_restore(meta) {
+            self.restore = Some(restore);
+        } else if self.replace && !matches!(restore, RedisValue::String(_)) {
+            // SET replaces strings by itself.
+            self.emit(vec!["DEL".as_bytes(), key])?;
         }
+        Ok(())
+    }
+
+    fn add_element(&mut self, command: &str, key: &[u8], args: &[&[u8]]) -> RdbOk {
+        if self.skip_key {
+            return Ok(());
+        }
+        self.key_elements += 1;
+
+        let bytes = args.iter().map(|arg| arg.len()).sum::<usize>();
+        if self.batch_elements > 0 && self.batch_bytes + bytes > self.max_batch_bytes {
+            self.flush_batch()?;
+        }
+
+        if self.batch.is_empty() {
+            self.batch.push(command.as_bytes().to_vec());
+            self.batch.push(key.to_vec());
+        }
+        self.batch.extend(args.iter().map(|arg| arg.to_vec()));
+        self.batch_elements += 1;
+        self.batch_bytes += bytes;
+
+        if self.batch_elements >= self.max_batch_elements {
+            self.flush_batch()?;
+        }
+        Ok(())
     }
-}
 
-impl Formatter for Protocol {
-    fn start_rdb(&mut self) {}
+    fn flush_batch(&mut self) -> RdbOk {
+        self.batch_elements = 0;
+        self.batch_bytes = 0;
+        if !self.batch.is_empty() {
+            let batch = std::mem::take(&mut self.batch);
+            self.emit(batch.iter().map(Vec::as_slice).collect())?;
+        }
+        Ok(())
+    }
 
-    fn end_rdb(&mut self) {}
+    // Redis doesn't keep empty keys, so neither a RESTORE nor an expiry is
+    // written for them.
+    fn post_expire(&mut self, key: &[u8]) -> RdbOk {
+        self.flush_batch()?;
+        let meta = std::mem::take(&mut self.last_meta);
+        if self.skip_key {
+            self.skip_key = false;
+        } else if let Some(restore) = self.restore.take() {
+            if !restore.is_empty() {
+                let payload = dump::encode(&restore)?;
+                self.emit_restore(key, &payload, meta)?;
+            }
+        } else if self.key_elements > 0 {
+            if let Some(expiry) = meta.expiry {
+                self.emit_expire(key, expiry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_expire(&mut self, key: &[u8], expiry: u64) -> RdbOk {
+        if self.relative_expiry {
+            let ttl = self.ttl(expiry).to_string();
+            self.emit(vec!["PEXPIRE".as_bytes(), key, ttl.as_bytes()])
+        } else {
+            let expiry = expiry.to_string();
+            self.emit(vec!["PEXPIREAT".as_bytes(), key, expiry.as_bytes()])
+        }
+    }
 
-    fn start_database(&mut self, db_number: u64) {
+    // A ttl of 0 means no expiry to RESTORE and is an error to SET, so keys
+    // already expired get the shortest one possible instead.
+    fn ttl(&self, expiry: u64) -> u64 {
+        expiry.saturating_sub(self.reference_time).max(1)
+    }
+
+    fn emit_restore(&mut self, key: &[u8], payload: &[u8], meta: KeyMetadata) -> RdbOk {
+        let ttl = match meta.expiry {
+            Some(expiry) if self.relative_expiry => self.ttl(expiry),
+            Some(expiry) => expiry,
+            None => 0,
+        }
+        .to_string();
+        let mut args = vec!["RESTORE".as_bytes(), key, ttl.as_bytes(), payload];
+        args.push(b"REPLACE");
+        if meta.expiry.is_some() && !self.relative_expiry {
+            args.push(b"ABSTTL");
+        }
+
+        // IDLETIME and FREQ are mutually exclusive, a dump only carries the
+        // one matching the maxmemory-policy it was written with.
+        let eviction = match (meta.idle, meta.freq) {
+            (Some(idle), _) => Some(("IDLETIME", idle.to_string())),
+            (None, Some(freq)) => Some(("FREQ", freq.to_string())),
+            (None, None) => None,
+        };
+        if let Some((name, value)) = &eviction {
+            args.push(name.as_bytes());
+            args.push(value.as_bytes());
+        }
+        self.emit(args)
+    }
+}
+
+impl<W: Write> Formatter for Protocol<W> {
+    fn start_database(&mut self, db_number: u64) -> RdbOk {
         let db = db_number.to_string();
         self.emit(vec!["SELECT".as_bytes(), db.as_bytes()])
     }
 
-    fn set(&mut self, key: &[u8], value: &[u8], expiry: Option<u64>) {
-        self.pre_expire(expiry);
-        self.emit(vec!["SET".as_bytes(), key, value]);
-        self.post_expire(key);
+    fn set(&mut self, key: &[u8], value: &[u8], meta: &KeyMetadata) -> RdbOk {
+        self.pre_expire(key, meta, RedisValue::String(value.to_vec()))?;
+        if self.restore.is_none() && !self.skip_key {
+            match meta.expiry {
+                Some(expiry) if self.string_pxat => {
+                    let (option, expiry) = if self.relative_expiry {
+                        ("PX", self.ttl(expiry))
+                    } else {
+                        ("PXAT", expiry)
+                    };
+                    let expiry = expiry.to_string();
+                    self.emit(vec![
+                        "SET".as_bytes(),
+                        key,
+                        value,
+                        option.as_bytes(),
+                        expiry.as_bytes(),
+                    ])?;
+                    self.last_meta.expiry = None;
+                }
+                _ => self.emit(vec!["SET".as_bytes(), key, value])?,
+            }
+            self.key_elements = 1;
+        }
+        self.post_expire(key)
     }
 
-    fn start_hash(&mut self, _key: &[u8], _length: u64, expiry: Option<u64>, _info: EncodingType) {
-        self.pre_expire(expiry);
+    fn start_hash(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        _info: EncodingType,
+    ) -> RdbOk {
+        self.pre_expire(key, meta, RedisValue::Hash(Vec::new()))
     }
-    fn end_hash(&mut self, key: &[u8]) {
-        self.post_expire(key);
+    fn end_hash(&mut self, key: &[u8]) -> RdbOk {
+        self.post_expire(key)
     }
-    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) {
-        self.emit(vec!["HSET".as_bytes(), key, field, value]);
+    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) -> RdbOk {
+        if let Some(RedisValue::Hash(fields)) = &mut self.restore {
+            fields.push((field.to_vec(), value.to_vec()));
+            return Ok(());
+        }
+        self.add_element("HSET", key, &[field, value])
     }
 
     fn start_set(
         &mut self,
-        _key: &[u8],
+        key: &[u8],
         _cardinality: u64,
-        expiry: Option<u64>,
+        meta: &KeyMetadata,
         _info: EncodingType,
-    ) {
-        self.pre_expire(expiry);
+    ) -> RdbOk {
+        self.pre_expire(key, meta, RedisValue::Set(Vec::new()))
     }
-    fn end_set(&mut self, key: &[u8]) {
-        self.post_expire(key);
+    fn end_set(&mut self, key: &[u8]) -> RdbOk {
+        self.post_expire(key)
     }
-    fn set_element(&mut self, key: &[u8], member: &[u8]) {
-        self.emit(vec!["SADD".as_bytes(), key, member]);
+    fn set_element(&mut self, key: &[u8], member: &[u8]) -> RdbOk {
+        if let Some(RedisValue::Set(members)) = &mut self.restore {
+            members.push(member.to_vec());
+            return Ok(());
+        }
+        self.add_element("SADD", key, &[member])
     }
 
-    fn start_list(&mut self, _key: &[u8], _length: u64, expiry: Option<u64>, _info: EncodingType) {
-        self.pre_expire(expiry);
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        _info: EncodingType,
+    ) -> RdbOk {
+        self.pre_expire(key, meta, RedisValue::List(Vec::new()))
     }
-    fn end_list(&mut self, key: &[u8]) {
-        self.post_expire(key);
+    fn end_list(&mut self, key: &[u8]) -> RdbOk {
+        self.post_expire(key)
     }
-    fn list_element(&mut self, key: &[u8], value: &[u8]) {
-        self.emit(vec!["RPUSH".as_bytes(), key, value]);
+    fn list_element(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        if let Some(RedisValue::List(values)) = &mut self.restore {
+            values.push(value.to_vec());
+            return Ok(());
+        }
+        self.add_element("RPUSH", key, &[value])
     }
 
     fn start_sorted_set(
         &mut self,
-        _key: &[u8],
+        key: &[u8],
         _length: u64,
-        expiry: Option<u64>,
+        meta: &KeyMetadata,
         _info: EncodingType,
-    ) {
-        self.pre_expire(expiry);
+    ) -> RdbOk {
+        self.pre_expire(key, meta, RedisValue::SortedSet(Vec::new()))
     }
-    fn end_sorted_set(&mut self, key: &[u8]) {
-        self.post_expire(key);
+    fn end_sorted_set(&mut self, key: &[u8]) -> RdbOk {
+        self.post_expire(key)
     }
-    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) {
+    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) -> RdbOk {
+        if let Some(RedisValue::SortedSet(members)) = &mut self.restore {
+            members.push((member.to_vec(), score));
+            return Ok(());
+        }
         let score = score.to_string();
-        self.emit(vec!["ZADD".as_bytes(), key, score.as_bytes(), member]);
+        self.add_element("ZADD", key, &[score.as_bytes(), member])
+    }
+
+    // Streams and module values can only be written as they were dumped.
+    fn wants_payloads(&self) -> bool {
+        true
+    }
+    fn payload(&mut self, key: &[u8], payload: &[u8], meta: &KeyMetadata) -> RdbOk {
+        let expired = meta
+            .expiry
+            .is_some_and(|expiry| expiry <= self.reference_time);
+        if self.drop_expired && expired {
+            return Ok(());
+        }
+        self.emit_restore(key, payload, *meta)
     }
 }
@@ -0,0 +1,240 @@
+use std::mem;
+
+use crate::formatter::Formatter;
+use crate::types::{EncodingType, KeyMetadata, KeyRecord, RdbOk, RedisValue};
+
+/// Receives the keys put together by `Records`.
+#[allow(unused_variables)]
+pub trait RecordHandler {
+    fn record(&mut self, record: KeyRecord) -> RdbOk;
+
+    /// Called instead of `record` for values bigger than the configured
+    /// maximum size. Every chunk carries the next part of the elements and
+    /// `last` is set on the final one. By default chunks are passed on to
+    /// `record` as they are.
+    fn chunk(&mut self, record: KeyRecord, last: bool) -> RdbOk {
+        self.record(record)
+    }
+
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        Ok(())
+    }
+    fn start_database(&mut self, db_index: u64) -> RdbOk {
+        Ok(())
+    }
+    fn resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbOk {
+        Ok(())
+    }
+}
+
+/// Collects the formatter callbacks of a key into a `KeyRecord`.
+///
+/// Without a maximum size every value is kept in memory as a whole. With
+/// one, a value is handed out in chunks as soon as its elements exceed that
+/// many bytes.
+pub struct Records<H: RecordHandler> {
+    handler: H,
+    max_size: Option<usize>,
+    db: u64,
+    current: Option<KeyRecord>,
+    size: usize,
+    chunked: bool,
+}
+
+impl<H: RecordHandler> Records<H> {
+    pub fn new(handler: H) -> Records<H> {
+        Records {
+            handler,
+            max_size: None,
+            db: 0,
+            current: None,
+            size: 0,
+            chunked: false,
+        }
+    }
+
+    /// Passes values bigger than `max_size` bytes to the handler in
+    /// chunks. Only the records are split, a handler that keeps the chunks
+    /// until the key ends still holds the whole value.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = Some(max_size);
+    }
+
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+
+    fn start_key(&mut self, key: &[u8], meta: &KeyMetadata, value: RedisValue, info: EncodingType) {
+        self.current = Some(KeyRecord {
+            db: self.db,
+            key: key.to_vec(),
+            meta: *meta,
+            value,
+            encoding: info,
+        });
+        self.size = 0;
+        self.chunked = false;
+    }
+
+    fn end_key(&mut self) -> RdbOk {
+        match self.current.take() {
+            Some(record) if self.chunked => self.handler.chunk(record, true),
+            Some(record) => self.handler.record(record),
+            None => Ok(()),
+        }
+    }
+
+    fn add_element(&mut self, size: usize, add: impl FnOnce(&mut RedisValue)) -> RdbOk {
+        self.size += size;
+        let exceeded = self.max_size.is_some_and(|max_size| self.size > max_size);
+
+        let record = match self.current.as_mut() {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+        add(&mut record.value);
+
+        if exceeded {
+            let elements = match record.value {
+                RedisValue::List(_) => RedisValue::List(Vec::new()),
+                RedisValue::Set(_) => RedisValue::Set(Vec::new()),
+                RedisValue::SortedSet(_) => RedisValue::SortedSet(Vec::new()),
+                RedisValue::Hash(_) => RedisValue::Hash(Vec::new()),
+                _ => return Ok(()),
+            };
+            let elements = mem::replace(&mut record.value, elements);
+            let chunk = KeyRecord {
+                value: elements,
+                key: record.key.clone(),
+                ..*record
+            };
+            self.size = 0;
+            self.chunked = true;
+            self.handler.chunk(chunk, false)?;
+        }
+        Ok(())
+    }
+}
+
+impl<H: RecordHandler> Formatter for Records<H> {
+    fn start_database(&mut self, db_index: u64) -> RdbOk {
+        self.db = db_index;
+        self.handler.start_database(db_index)
+    }
+
+    fn resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbOk {
+        self.handler.resizedb(db_size, expires_size)
+    }
+
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        self.handler.aux_field(key, value)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8], meta: &KeyMetadata) -> RdbOk {
+        let value = RedisValue::String(value.to_vec());
+        self.start_key(key, meta, value, EncodingType::String);
+        self.end_key()
+    }
+
+    fn start_hash(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key(key, meta, RedisValue::Hash(Vec::new()), info);
+        Ok(())
+    }
+    fn end_hash(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key()
+    }
+    fn hash_element(&mut self, _key: &[u8], field: &[u8], value: &[u8]) -> RdbOk {
+        self.add_element(field.len() + value.len(), |hash| {
+            if let RedisValue::Hash(fields) = hash {
+                fields.push((field.to_vec(), value.to_vec()));
+            }
+        })
+    }
+
+    fn start_set(
+        &mut self,
+        key: &[u8],
+        _cardinality: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key(key, meta, RedisValue::Set(Vec::new()), info);
+        Ok(())
+    }
+    fn end_set(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key()
+    }
+    fn set_element(&mut self, _key: &[u8], member: &[u8]) -> RdbOk {
+        self.add_element(member.len(), |set| {
+            if let RedisValue::Set(members) = set {
+                members.push(member.to_vec());
+            }
+        })
+    }
+
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key(key, meta, RedisValue::List(Vec::new()), info);
+        Ok(())
+    }
+    fn end_list(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key()
+    }
+    fn list_element(&mut self, _key: &[u8], value: &[u8]) -> RdbOk {
+        self.add_element(value.len(), |list| {
+            if let RedisValue::List(values) = list {
+                values.push(value.to_vec());
+            }
+        })
+    }
+
+    fn start_sorted_set(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key(key, meta, RedisValue::SortedSet(Vec::new()), info);
+        Ok(())
+    }
+    fn end_sorted_set(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key()
+    }
+    fn sorted_set_element(&mut self, _key: &[u8], score: f64, member: &[u8]) -> RdbOk {
+        self.add_element(member.len() + 8, |zset| {
+            if let RedisValue::SortedSet(members) = zset {
+                members.push((member.to_vec(), score));
+            }
+        })
+    }
+
+    fn stream(&mut self, key: &[u8], length: u64, meta: &KeyMetadata) -> RdbOk {
+        let value = RedisValue::Stream { length };
+        self.start_key(key, meta, value, EncodingType::StreamListpacks);
+        self.end_key()
+    }
+
+    fn module(&mut self, key: &[u8], name: &str, meta: &KeyMetadata) -> RdbOk {
+        let value = RedisValue::Module {
+            name: name.to_string(),
+        };
+        self.start_key(key, meta, value, EncodingType::Moudle2);
+        self.end_key()
+    }
+}
@@ -0,0 +1,583 @@
+use crate::formatter::{ByteEncoding, Formatter};
+use crate::types::{EncodingType, KeyMetadata, RdbOk};
+use serialize::{json, Map, Value};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{self, Stdout, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SIZE_BUCKETS: &[(u64, &str)] = &[
+    (64, "<64B"),
+    (1024, "<1KB"),
+    (16 * 1024, "<16KB"),
+    (256 * 1024, "<256KB"),
+    (1024 * 1024, "<1MB"),
+    (u64::MAX, ">=1MB"),
+];
+
+const HOUR: u64 = 60 * 60 * 1000;
+const TTL_BUCKETS: &[(u64, &str)] = &[
+    (HOUR, "<1h"),
+    (24 * HOUR, "<1d"),
+    (7 * 24 * HOUR, "<7d"),
+    (30 * 24 * HOUR, "<30d"),
+    (u64::MAX, ">=30d"),
+];
+
+// Put in front of the TTL buckets.
+const NO_TTL: &str = "none";
+const EXPIRED: &str = "expired";
+
+const PERCENTILES: &[u64] = &[50, 90, 99];
+
+// Shown for the keys without the namespace delimiter.
+const NO_NAMESPACE: &str = "(none)";
+
+// Element counts below this have a bucket of their own in the histogram,
+// above it every power of two is split into 8 buckets.
+const EXACT_COUNTS: u64 = 16;
+const SUB_BUCKETS: u64 = 8;
+
+/// How `Stats` writes its report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    Text,
+    JSON,
+}
+
+/// Writes a report of the keyspace once the whole dump was read: per
+/// database and type the number of keys, their elements, a histogram of
+/// value sizes, their expiries, encodings and the biggest keys.
+///
+/// The size of a value is the sum of the lengths of its elements, fields
+/// and members as stored in the dump. With a delimiter the keys are also
+/// counted per namespace, the part of the key in front of the delimiter.
+///
+/// Percentiles of the number of elements are taken from a histogram, exact
+/// below 16 elements and within an eighth above.
+pub struct Stats<W: Write = Stdout> {
+    out: W,
+    format: StatsFormat,
+    top: usize,
+    delimiter: Option<Vec<u8>>,
+    reference_time: u64,
+    db: u64,
+    current: Option<Key>,
+    databases: BTreeMap<u64, DatabaseStats>,
+}
+
+struct Key {
+    name: Vec<u8>,
+    type_name: &'static str,
+    encoding: &'static str,
+    expiry: Option<u64>,
+    elements: u64,
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct DatabaseStats {
+    keys: u64,
+    types: BTreeMap<&'static str, TypeStats>,
+    // Keys without the delimiter are counted under None.
+    namespaces: BTreeMap<Option<Vec<u8>>, (u64, u64)>,
+}
+
+#[derive(Default)]
+struct TypeStats {
+    keys: u64,
+    elements: Histogram,
+    bytes: u64,
+    sizes: Vec<u64>,
+    expiries: BTreeMap<&'static str, u64>,
+    encodings: BTreeMap<&'static str, u64>,
+    biggest: Vec<(u64, u64, Vec<u8>)>,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::with_output(io::stdout())
+    }
+}
+
+impl<W: Write> Stats<W> {
+    pub fn with_output(out: W) -> Stats<W> {
+        Stats {
+            out,
+            format: StatsFormat::Text,
+            top: 10,
+            delimiter: None,
+            reference_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_millis() as u64),
+            db: 0,
+            current: None,
+            databases: BTreeMap::new(),
+        }
+    }
+
+    pub fn into_output(self) -> W {
+        self.out
+    }
+
+    pub fn set_format(&mut self, format: StatsFormat) {
+        self.format = format;
+    }
+
+    /// The number of biggest keys reported per type and of namespaces
+    /// reported per database, 10 by default.
+    pub fn set_top(&mut self, top: usize) {
+        self.top = top;
+    }
+
+    /// Counts keys per namespace, the part in front of the first
+    /// `delimiter`. Keys without one are counted together.
+    pub fn set_namespace_delimiter(&mut self, delimiter: &[u8]) {
+        self.delimiter = Some(delimiter.to_vec()).filter(|delimiter| !delimiter.is_empty());
+    }
+
+    /// The unix time in milliseconds expiries are compared with, the time
+    /// the formatter was created by default.
+    pub fn set_reference_time(&mut self, time: u64) {
+        self.reference_time = time;
+    }
+
+    fn start_key(
+        &mut self,
+        type_name: &'static str,
+        key: &[u8],
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) {
+        self.current = Some(Key {
+            name: key.to_vec(),
+            type_name,
+            encoding: info.name(),
+            expiry: meta.expiry,
+            elements: 0,
+            bytes: 0,
+        });
+    }
+
+    fn add_element(&mut self, bytes: usize) {
+        if let Some(key) = self.current.as_mut() {
+            key.elements += 1;
+            key.bytes += bytes as u64;
+        }
+    }
+
+    fn end_key(&mut self) {
+        let key = match self.current.take() {
+            Some(key) => key,
+            None => return,
+        };
+        let expiry = self.expiry_bucket(key.expiry);
+        let namespace = self.namespace(&key.name).map(<[u8]>::to_vec);
+
+        let database = self.databases.entry(self.db).or_default();
+        database.keys += 1;
+        let namespace = database.namespaces.entry(namespace).or_default();
+        namespace.0 += 1;
+        namespace.1 += key.bytes;
+
+        let stats = database.types.entry(key.type_name).or_default();
+        stats.keys += 1;
+        stats.elements.add(key.elements);
+        stats.bytes += key.bytes;
+        if stats.sizes.is_empty() {
+            stats.sizes = vec![0; SIZE_BUCKETS.len()];
+        }
+        let size = SIZE_BUCKETS
+            .iter()
+            .position(|&(limit, _)| key.bytes < limit)
+            .unwrap_or(SIZE_BUCKETS.len() - 1);
+        stats.sizes[size] += 1;
+        *stats.expiries.entry(expiry).or_default() += 1;
+        *stats.encodings.entry(key.encoding).or_default() += 1;
+
+        stats.biggest.push((key.bytes, key.elements, key.name));
+        if stats.biggest.len() >= 2 * self.top.max(1) {
+            keep_biggest(&mut stats.biggest, self.top);
+        }
+    }
+
+    fn expiry_bucket(&self, expiry: Option<u64>) -> &'static str {
+        let expiry = match expiry {
+            Some(expiry) => expiry,
+            None => return NO_TTL,
+        };
+        if expiry <= self.reference_time {
+            return EXPIRED;
+        }
+        let ttl = expiry - self.reference_time;
+        TTL_BUCKETS
+            .iter()
+            .find(|&&(limit, _)| ttl < limit)
+            .map_or(TTL_BUCKETS[TTL_BUCKETS.len() - 1].1, |&(_, name)| name)
+    }
+
+    fn namespace<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        match &self.delimiter {
+            Some(delimiter) => key
+                .windows(delimiter.len())
+                .position(|window| window == &delimiter[..])
+                .map(|end| &key[..end]),
+            None => Some(&[]),
+        }
+    }
+
+    fn write_text(&mut self) -> RdbOk {
+        let top = self.top;
+        for (db, database) in &mut self.databases {
+            writeln!(self.out, "db {}: {} keys", db, database.keys)?;
+            for (type_name, stats) in &mut database.types {
+                keep_biggest(&mut stats.biggest, top);
+                let elements = percentiles(&stats.elements);
+                writeln!(
+                    self.out,
+                    "  {}: {} keys, {} elements (p50 {}, p90 {}, p99 {}, max {}), {} bytes",
+                    type_name,
+                    stats.keys,
+                    elements.0,
+                    elements.1[0],
+                    elements.1[1],
+                    elements.1[2],
+                    elements.2,
+                    stats.bytes
+                )?;
+
+                let sizes = SIZE_BUCKETS
+                    .iter()
+                    .zip(&stats.sizes)
+                    .filter(|(_, &count)| count > 0)
+                    .map(|(&(_, name), count)| format!("{} {}", name, count));
+                writeln!(self.out, "    sizes: {}", join(sizes))?;
+                let expiries = expiry_names()
+                    .filter_map(|name| Some((name, *stats.expiries.get(name)?)))
+                    .map(|(name, count)| format!("{} {}", name, count));
+                writeln!(self.out, "    expiry: {}", join(expiries))?;
+                let encodings = stats
+                    .encodings
+                    .iter()
+                    .map(|(name, count)| format!("{} {}", name, count));
+                writeln!(self.out, "    encodings: {}", join(encodings))?;
+                let biggest = stats.biggest.iter().map(|(bytes, elements, key)| {
+                    format!(
+                        "{} ({} bytes, {} elements)",
+                        ByteEncoding::Escape.encode(key),
+                        bytes,
+                        elements
+                    )
+                });
+                writeln!(self.out, "    biggest: {}", join(biggest))?;
+            }
+
+            if self.delimiter.is_some() {
+                writeln!(self.out, "  namespaces:")?;
+                for (namespace, (keys, bytes)) in top_namespaces(&database.namespaces, top) {
+                    writeln!(
+                        self.out,
+                        "    {}: {} keys, {} bytes",
+                        namespace
+                            .as_ref()
+                            .map_or(NO_NAMESPACE.to_string(), |namespace| {
+                                ByteEncoding::Escape.encode(namespace)
+                            }),
+                        keys,
+                        bytes
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn json_report(&mut self) -> Value {
+        let top = self.top;
+        let mut databases = Vec::new();
+        for (db, database) in &mut self.databases {
+            let mut types = Map::new();
+            for (type_name, stats) in &mut database.types {
+                keep_biggest(&mut stats.biggest, top);
+                let (total, values, max) = percentiles(&stats.elements);
+                let mut elements = Map::new();
+                elements.insert("total".to_string(), json!(total));
+                for (percentile, value) in PERCENTILES.iter().zip(values.iter()) {
+                    elements.insert(format!("p{}", percentile), json!(value));
+                }
+                elements.insert("max".to_string(), json!(max));
+
+                let sizes: Vec<Value> = SIZE_BUCKETS
+                    .iter()
+                    .zip(&stats.sizes)
+                    .map(|(&(_, name), count)| json!({"bucket": name, "keys": count}))
+                    .collect();
+                let expiries: Vec<Value> = expiry_names()
+                    .map(|name| {
+                        let count = stats.expiries.get(name).copied().unwrap_or(0);
+                        json!({"bucket": name, "keys": count})
+                    })
+                    .collect();
+                let biggest: Vec<Value> = stats
+                    .biggest
+                    .iter()
+                    .map(|(bytes, elements, key)| {
+                        json!({
+                            "key": ByteEncoding::Escape.encode(key),
+                            "bytes": bytes,
+                            "elements": elements,
+                        })
+                    })
+                    .collect();
+
+                types.insert(
+                    type_name.to_string(),
+                    json!({
+                        "keys": stats.keys,
+                        "elements": elements,
+                        "bytes": stats.bytes,
+                        "sizes": sizes,
+                        "expiry": expiries,
+                        "encodings": stats.encodings,
+                        "biggest": biggest,
+                    }),
+                );
+            }
+
+            let mut entry = json!({"db": db, "keys": database.keys, "types": types});
+            if self.delimiter.is_some() {
+                let namespaces: Vec<Value> = top_namespaces(&database.namespaces, top)
+                    .map(|(namespace, (keys, bytes))| {
+                        json!({
+                            "namespace": namespace
+                                .as_ref()
+                                .map(|namespace| ByteEncoding::Escape.encode(namespace)),
+                            "keys": keys,
+                            "bytes": bytes,
+                        })
+                    })
+                    .collect();
+                entry["namespaces"] = Value::Array(namespaces);
+            }
+            databases.push(entry);
+        }
+        json!({ "databases": databases })
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn keep_biggest(biggest: &mut Vec<(u64, u64, Vec<u8>)>, top: usize) {
+    biggest.sort_by_key(|&(bytes, _, _)| Reverse(bytes));
+    biggest.truncate(top);
+}
+
+// Counts values in buckets of a fixed size relative to them.
+#[derive(Default)]
+struct Histogram {
+    counts: Vec<u64>,
+    values: u64,
+    total: u64,
+    max: u64,
+}
+
+impl Histogram {
+    fn add(&mut self, value: u64) {
+        let bucket = Histogram::bucket(value);
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+        self.values += 1;
+        self.total = self.total.saturating_add(value);
+        self.max = self.max.max(value);
+    }
+
+    fn bucket(value: u64) -> usize {
+        if value < EXACT_COUNTS {
+            return value as usize;
+        }
+        let power = 63 - value.leading_zeros() as u64;
+        let shift = power - SUB_BUCKETS.trailing_zeros() as u64;
+        let sub_bucket = (value >> shift) - SUB_BUCKETS;
+        let first = EXACT_COUNTS.trailing_zeros() as u64;
+        (EXACT_COUNTS + (power - first) * SUB_BUCKETS + sub_bucket) as usize
+    }
+
+    // The highest value counted in the bucket.
+    fn highest(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < EXACT_COUNTS {
+            return bucket;
+        }
+        let first = EXACT_COUNTS.trailing_zeros() as u64;
+        let power = (bucket - EXACT_COUNTS) / SUB_BUCKETS + first;
+        let shift = power - SUB_BUCKETS.trailing_zeros() as u64;
+        let sub_bucket = (bucket - EXACT_COUNTS) % SUB_BUCKETS;
+        ((SUB_BUCKETS + sub_bucket) << shift) + ((1 << shift) - 1)
+    }
+
+    // The nearest rank percentile, as the highest value of its bucket.
+    fn percentile(&self, percentile: u64) -> u64 {
+        let rank = (percentile * self.values).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Histogram::highest(bucket).min(self.max);
+            }
+        }
+        0
+    }
+}
+
+// The total, the percentiles and the maximum.
+fn percentiles(values: &Histogram) -> (u64, Vec<u64>, u64) {
+    let percentiles = PERCENTILES.iter().map(|&p| values.percentile(p)).collect();
+    (values.total, percentiles, values.max)
+}
+
+fn expiry_names() -> impl Iterator<Item = &'static str> {
+    [NO_TTL, EXPIRED]
+        .iter()
+        .copied()
+        .chain(TTL_BUCKETS.iter().map(|&(_, name)| name))
+}
+
+// The namespaces with the most keys.
+fn top_namespaces(
+    namespaces: &BTreeMap<Option<Vec<u8>>, (u64, u64)>,
+    top: usize,
+) -> impl Iterator<Item = (&Option<Vec<u8>>, (u64, u64))> {
+    let mut namespaces: Vec<_> = namespaces
+        .iter()
+        .map(|(name, &counts)| (name, counts))
+        .collect();
+    namespaces.sort_by_key(|&(_, (keys, _))| Reverse(keys));
+    namespaces.into_iter().take(top)
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
+
+impl<W: Write> Formatter for Stats<W> {
+    fn end_rdb(&mut self) -> RdbOk {
+        match self.format {
+            StatsFormat::Text => self.write_text()?,
+            StatsFormat::JSON => {
+                let report = self.json_report();
+                serialize::to_writer_pretty(&mut self.out, &report)?;
+                self.out.write_all(b"\n")?;
+            }
+        }
+        self.out.flush()
+    }
+
+    fn start_database(&mut self, db_index: u64) -> RdbOk {
+        self.db = db_index;
+        Ok(())
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8], meta: &KeyMetadata) -> RdbOk {
+        self.start_key("string", key, meta, EncodingType::String);
+        self.add_element(value.len());
+        self.end_key();
+        Ok(())
+    }
+
+    fn start_hash(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key("hash", key, meta, info);
+        Ok(())
+    }
+    fn end_hash(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key();
+        Ok(())
+    }
+    fn hash_element(&mut self, _key: &[u8], field: &[u8], value: &[u8]) -> RdbOk {
+        self.add_element(field.len() + value.len());
+        Ok(())
+    }
+
+    fn start_set(
+        &mut self,
+        key: &[u8],
+        _cardinality: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key("set", key, meta, info);
+        Ok(())
+    }
+    fn end_set(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key();
+        Ok(())
+    }
+    fn set_element(&mut self, _key: &[u8], member: &[u8]) -> RdbOk {
+        self.add_element(member.len());
+        Ok(())
+    }
+
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key("list", key, meta, info);
+        Ok(())
+    }
+    fn end_list(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key();
+        Ok(())
+    }
+    fn list_element(&mut self, _key: &[u8], value: &[u8]) -> RdbOk {
+        self.add_element(value.len());
+        Ok(())
+    }
+
+    fn start_sorted_set(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbOk {
+        self.start_key("zset", key, meta, info);
+        Ok(())
+    }
+    fn end_sorted_set(&mut self, _key: &[u8]) -> RdbOk {
+        self.end_key();
+        Ok(())
+    }
+    fn sorted_set_element(&mut self, _key: &[u8], _score: f64, member: &[u8]) -> RdbOk {
+        self.add_element(member.len() + 8);
+        Ok(())
+    }
+
+    fn stream(&mut self, key: &[u8], length: u64, meta: &KeyMetadata) -> RdbOk {
+        self.start_key("stream", key, meta, EncodingType::StreamListpacks);
+        if let Some(current) = self.current.as_mut() {
+            current.elements = length;
+        }
+        self.end_key();
+        Ok(())
+    }
+
+    fn module(&mut self, key: &[u8], _name: &str, meta: &KeyMetadata) -> RdbOk {
+        self.start_key("module", key, meta, EncodingType::Moudle2);
+        self.end_key();
+        Ok(())
+    }
+}
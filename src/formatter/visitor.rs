@@ -0,0 +1,263 @@
+use crate::formatter::Formatter;
+use crate::types::{EncodingType, KeyMetadata, RdbResult};
+
+/// What the parser does after a `Visitor` callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    /// Reads over the rest of the current key without any further callbacks
+    /// for it, its `end_*` callback included. Outside of a key this is the
+    /// same as `Continue`.
+    SkipKey,
+    /// Ends the parse successfully right away. No further callbacks are
+    /// made, not even `end_database` or `end_rdb`.
+    Stop,
+}
+
+/// The callbacks of the parser, able to skip keys or stop the parse early.
+///
+/// Every `Formatter` is a `Visitor` which always continues, so the parser
+/// takes either.
+#[allow(unused_variables)]
+pub trait Visitor {
+    fn start_rdb(&mut self) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn end_rdb(&mut self) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn checksum(&mut self, checksum: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn start_database(&mut self, db_index: u64) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn end_database(&mut self, db_index: u64) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8], meta: &KeyMetadata) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn start_hash(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn end_hash(&mut self, key: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn start_set(
+        &mut self,
+        key: &[u8],
+        cardinality: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn end_set(&mut self, key: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn set_element(&mut self, key: &[u8], member: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn end_list(&mut self, key: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn list_element(&mut self, key: &[u8], value: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn start_sorted_set(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn end_sorted_set(&mut self, key: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn stream(&mut self, key: &[u8], length: u64, meta: &KeyMetadata) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+    fn module(&mut self, key: &[u8], name: &str, meta: &KeyMetadata) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+
+    /// Whether the parser should keep streams and module values as it reads
+    /// them, for `payload`.
+    fn wants_payloads(&self) -> bool {
+        false
+    }
+    /// A stream or module value as the payload `DUMP` returns, following
+    /// the `stream` or `module` callback.
+    fn payload(&mut self, key: &[u8], payload: &[u8], meta: &KeyMetadata) -> RdbResult<Control> {
+        Ok(Control::Continue)
+    }
+}
+
+impl<F: Formatter> Visitor for F {
+    fn start_rdb(&mut self) -> RdbResult<Control> {
+        Formatter::start_rdb(self)?;
+        Ok(Control::Continue)
+    }
+    fn end_rdb(&mut self) -> RdbResult<Control> {
+        Formatter::end_rdb(self)?;
+        Ok(Control::Continue)
+    }
+    fn checksum(&mut self, checksum: &[u8]) -> RdbResult<Control> {
+        Formatter::checksum(self, checksum)?;
+        Ok(Control::Continue)
+    }
+
+    fn start_database(&mut self, db_index: u64) -> RdbResult<Control> {
+        Formatter::start_database(self, db_index)?;
+        Ok(Control::Continue)
+    }
+    fn end_database(&mut self, db_index: u64) -> RdbResult<Control> {
+        Formatter::end_database(self, db_index)?;
+        Ok(Control::Continue)
+    }
+
+    fn resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbResult<Control> {
+        Formatter::resizedb(self, db_size, expires_size)?;
+        Ok(Control::Continue)
+    }
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbResult<Control> {
+        Formatter::aux_field(self, key, value)?;
+        Ok(Control::Continue)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8], meta: &KeyMetadata) -> RdbResult<Control> {
+        Formatter::set(self, key, value, meta)?;
+        Ok(Control::Continue)
+    }
+
+    fn start_hash(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        Formatter::start_hash(self, key, length, meta, info)?;
+        Ok(Control::Continue)
+    }
+    fn end_hash(&mut self, key: &[u8]) -> RdbResult<Control> {
+        Formatter::end_hash(self, key)?;
+        Ok(Control::Continue)
+    }
+    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) -> RdbResult<Control> {
+        Formatter::hash_element(self, key, field, value)?;
+        Ok(Control::Continue)
+    }
+
+    fn start_set(
+        &mut self,
+        key: &[u8],
+        cardinality: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        Formatter::start_set(self, key, cardinality, meta, info)?;
+        Ok(Control::Continue)
+    }
+    fn end_set(&mut self, key: &[u8]) -> RdbResult<Control> {
+        Formatter::end_set(self, key)?;
+        Ok(Control::Continue)
+    }
+    fn set_element(&mut self, key: &[u8], member: &[u8]) -> RdbResult<Control> {
+        Formatter::set_element(self, key, member)?;
+        Ok(Control::Continue)
+    }
+
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        Formatter::start_list(self, key, length, meta, info)?;
+        Ok(Control::Continue)
+    }
+    fn end_list(&mut self, key: &[u8]) -> RdbResult<Control> {
+        Formatter::end_list(self, key)?;
+        Ok(Control::Continue)
+    }
+    fn list_element(&mut self, key: &[u8], value: &[u8]) -> RdbResult<Control> {
+        Formatter::list_element(self, key, value)?;
+        Ok(Control::Continue)
+    }
+
+    fn start_sorted_set(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        Formatter::start_sorted_set(self, key, length, meta, info)?;
+        Ok(Control::Continue)
+    }
+    fn end_sorted_set(&mut self, key: &[u8]) -> RdbResult<Control> {
+        Formatter::end_sorted_set(self, key)?;
+        Ok(Control::Continue)
+    }
+    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) -> RdbResult<Control> {
+        Formatter::sorted_set_element(self, key, score, member)?;
+        Ok(Control::Continue)
+    }
+
+    fn stream(&mut self, key: &[u8], length: u64, meta: &KeyMetadata) -> RdbResult<Control> {
+        Formatter::stream(self, key, length, meta)?;
+        Ok(Control::Continue)
+    }
+    fn module(&mut self, key: &[u8], name: &str, meta: &KeyMetadata) -> RdbResult<Control> {
+        Formatter::module(self, key, name, meta)?;
+        Ok(Control::Continue)
+    }
+
+    fn wants_payloads(&self) -> bool {
+        Formatter::wants_payloads(self)
+    }
+    fn payload(&mut self, key: &[u8], payload: &[u8], meta: &KeyMetadata) -> RdbResult<Control> {
+        Formatter::payload(self, key, payload, meta)?;
+        Ok(Control::Continue)
+    }
+}
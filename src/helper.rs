@@ -1,5 +1,13 @@
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
 use std::io::Read;
 use std::io::Result as IoResult;
+use std::str;
+
+#[inline]
+pub fn other_error(desc: &'static str) -> IoError {
+    IoError::other(desc)
+}
 
 pub fn int_to_vec(number: i32) -> Vec<u8> {
     let number = number.to_string();
@@ -10,9 +18,29 @@ pub fn int_to_vec(number: i32) -> Vec<u8> {
     result
 }
 
+// The length comes straight from the input, so the buffer grows with the
+// bytes actually read instead of being allocated up front.
 pub fn read_exact<T: Read>(reader: &mut T, len: usize) -> IoResult<Vec<u8>> {
-    let mut buf = vec![0; len];
-    reader.read_exact(&mut buf)?;
+    let mut buf = Vec::with_capacity(len.min(64 * 1024));
+    reader.take(len as u64).read_to_end(&mut buf)?;
+
+    if buf.len() != len {
+        return Err(IoError::new(
+            IoErrorKind::UnexpectedEof,
+            "failed to fill whole buffer",
+        ));
+    }
 
     Ok(buf)
 }
+
+// The integer a string holds, if turning it back into a string gives the
+// very same bytes, the way Redis decides to store a string as an integer.
+pub fn as_integer(value: &[u8]) -> Option<i64> {
+    let number = str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+    if number.to_string().as_bytes() == value {
+        Some(number)
+    } else {
+        None
+    }
+}
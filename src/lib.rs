@@ -1,28 +1,47 @@
 #[doc(hidden)]
-pub use types::{/* error and result types */ RdbError, RdbOk, RdbResult, Type, ZiplistEntry};
+pub use types::{
+    KeyMetadata, KeyRecord, /* error and result types */ RdbError, RdbOk, RdbResult,
+    RedisValue, Type, ZiplistEntry,
+};
 
+extern crate hex;
 extern crate lzf;
 extern crate redis;
 extern crate regex;
-extern crate hex;
+extern crate rustls;
 extern crate serde_json as serialize;
+extern crate serde_yaml;
+extern crate signal_hook;
+extern crate toml;
+extern crate webpki_roots;
 
+pub mod aof;
 pub mod canal;
+pub mod command;
+pub mod config;
 pub mod constants;
+pub mod crc64;
+pub mod diff;
+pub mod dump;
 pub mod filter;
 pub mod formatter;
 mod helper;
 pub mod parser;
+pub mod reader;
+pub mod rewrite;
+pub mod tls;
+pub mod transform;
 pub mod types;
+pub mod verify;
+pub mod writer;
 
 pub use canal::*;
-use constants::*;
 use filter::*;
-use formatter::Formatter;
+use formatter::Visitor;
 use parser::RdbParser;
 use std::io::Read;
 
-pub fn parse<T: Read, F: Formatter, L: Filter>(input: &mut T, formatter: F, filter: L) -> RdbOk {
+pub fn parse<T: Read, F: Visitor, L: Filter>(input: &mut T, formatter: F, filter: L) -> RdbOk {
     let mut parser = RdbParser::new(input, formatter, filter);
     parser.parse()
 }
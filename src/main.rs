@@ -1,41 +1,139 @@
 extern crate getopts;
 extern crate redis_canal_rs as rdb;
 extern crate regex;
-use getopts::Options;
-use regex::Regex;
+use getopts::{Matches, Options};
+use rdb::aof::AofParser;
+use rdb::config::{Config, Reload, SinkType};
+use rdb::diff::Change;
+use rdb::filter::{AllOf, CommandFilter, Filter};
+use rdb::formatter::ByteEncoding;
+use rdb::transform::{Transform, Transforming};
+use rdb::verify::Checksum;
+use regex::bytes::Regex;
 use std::env;
-use std::fs::File;
-use std::io::{BufReader, Write};
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
 use std::path::Path;
+use std::process;
+use std::sync::atomic::Ordering;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} [options] dump.rdb", program);
-    print!("{}", opts.usage(&brief));
-}
+// Exit codes, besides 0 for success. `diff` exits with 1 if the dumps
+// differ, like diff(1).
+const FAILURE: i32 = 1;
+const USAGE_ERROR: i32 = 2;
 
-pub fn main() -> redis::RedisResult<()> {
+const COMMANDS: &str = "Commands:
+    parse     Format or rewrite the keys of a dump
+    sync      Replicate from a master, writing the dump and the commands following it
+    replay    Load the keys of a dump into a server
+    stats     Report keyspace statistics and the biggest keys of a dump
+    diff      List the keys that differ between two dumps
+    verify    Check dumps for corruption and compare their checksums
+    aof       Write out the commands of an append-only file
 
-    //10.1.1.232:7010 10.200.100.219:6379
-    let addr = String::from("10.200.100.219:6379");
-    let password = "".to_string();
-    let offset = -1;
-    let db = 0;
-    let mut canal = rdb::Canal::new(addr, db, offset, password);
-    canal.dump_and_parse()?;
-    Ok(())
-} 
+Run a command with --help for its options. Exits with 1 if the command
+failed, 2 for incorrect arguments.";
 
-pub fn main1() {
+fn main() {
     let mut args = env::args();
-    let program = args.next().unwrap();
-    let mut opts = Options::new();
+    let program = args.next().unwrap_or_else(|| "redis-canal-rs".to_string());
+    let command = args.next();
+    let args: Vec<String> = args.collect();
 
-    opts.optopt(
-        "f",
-        "format",
-        "Format to output. Valid: json, plain, nil, protocol",
-        "FORMAT",
-    );
+    let result = match command.as_deref() {
+        Some("parse") => parse(&program, args),
+        Some("sync") => sync(&program, args),
+        Some("replay") => replay(&program, args),
+        Some("stats") => stats(&program, args),
+        Some("diff") => diff(&program, args),
+        Some("verify") => verify(&program, args),
+        Some("aof") => aof(&program, args),
+        Some("-h") | Some("--help") | Some("help") => {
+            println!("Usage: {} COMMAND [options]\n\n{}", program, COMMANDS);
+            Ok(())
+        }
+        Some(other) => {
+            eprintln!("Unknown command: {}\n", other);
+            eprintln!("Usage: {} COMMAND [options]\n\n{}", program, COMMANDS);
+            Err(USAGE_ERROR)
+        }
+        None => {
+            eprintln!("Usage: {} COMMAND [options]\n\n{}", program, COMMANDS);
+            Err(USAGE_ERROR)
+        }
+    };
+    process::exit(result.err().unwrap_or(0));
+}
+
+// The options of a command and how to report using them wrong. Errors are
+// returned as the exit code.
+struct Cli {
+    usage: String,
+    opts: Options,
+}
+
+impl Cli {
+    fn new(program: &str, command: &str, arguments: &str) -> Cli {
+        let mut opts = Options::new();
+        opts.optflag("h", "help", "print this help menu");
+        Cli {
+            usage: format!("Usage: {} {} [options] {}", program, command, arguments),
+            opts,
+        }
+    }
+
+    // Parses the arguments, expecting `free` of them, or at least `free` and
+    // any number more with `more`. Prints the usage for `--help`.
+    fn parse(&self, args: Vec<String>, free: usize, more: bool) -> Result<Matches, i32> {
+        if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+            print!("{}", self.opts.usage(&self.usage));
+            return Err(0);
+        }
+        let matches = self.opts.parse(args).map_err(|e| self.error(e))?;
+        if matches.free.len() < free || (!more && matches.free.len() > free) {
+            return Err(self.error("Wrong number of arguments"));
+        }
+        Ok(matches)
+    }
+
+    fn error(&self, message: impl Display) -> i32 {
+        eprintln!("{}\n", message);
+        eprint!("{}", self.opts.usage(&self.usage));
+        USAGE_ERROR
+    }
+
+    // Parses an option with a default for when it isn't given.
+    fn number<T: std::str::FromStr>(
+        &self,
+        matches: &Matches,
+        name: &str,
+        default: T,
+    ) -> Result<T, i32> {
+        match matches.opt_str(name) {
+            None => Ok(default),
+            Some(value) => value
+                .parse()
+                .map_err(|_| self.error(format!("Invalid --{}: {}", name, value))),
+        }
+    }
+}
+
+fn failure(message: impl Display) -> i32 {
+    eprintln!("{}", message);
+    FAILURE
+}
+
+fn open(path: &str) -> Result<BufReader<File>, i32> {
+    File::open(Path::new(path))
+        .map(BufReader::new)
+        .map_err(|e| failure(format!("Can't open {}: {}", path, e)))
+}
+
+fn add_filter_options(opts: &mut Options) {
     opts.optopt(
         "k",
         "keys",
@@ -54,97 +152,920 @@ pub fn main1() {
         "Type to show. Can be specified multiple times",
         "TYPE",
     );
-    opts.optflag("h", "help", "print this help menu");
+    opts.optopt(
+        "e",
+        "filter",
+        "Keys to show, as an expression like: db in (0,3) and type = hash and not key ~ \"tmp\"",
+        "EXPR",
+    );
+    opts.optmulti(
+        "g",
+        "glob",
+        "Keys to show, as a Redis glob pattern. Can be specified multiple times",
+        "PATTERN",
+    );
+    opts.optopt(
+        "",
+        "prefix-file",
+        "Only show keys starting with one of the prefixes in this file, one per line",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "sample",
+        "Only show a fraction of the keys, picked by a hash of the key, like 0.01 or 1%",
+        "FRACTION",
+    );
+    opts.optopt(
+        "",
+        "seed",
+        "Seed for the keys picked by --sample and --reservoir",
+        "SEED",
+    );
+}
 
-    let matches = match opts.parse(args) {
-        Ok(m) => m,
-        Err(e) => {
-            println!("{}\n", e);
-            print_usage(&program, opts);
-            return;
+fn build_filter(cli: &Cli, matches: &Matches) -> Result<AllOf, i32> {
+    let mut simple = rdb::filter::Simple::new();
+
+    for db in &matches.opt_strs("d") {
+        match db.parse() {
+            Ok(db) => simple.add_database(db),
+            Err(_) => return Err(cli.error(format!("Invalid database: {}", db))),
         }
-    };
+    }
+
+    for t in &matches.opt_strs("t") {
+        match rdb::Type::from_name(t) {
+            Some(typ) => simple.add_type(typ),
+            None => return Err(cli.error(format!("Unknown type: {}", t))),
+        }
+    }
 
-    if matches.opt_present("h") {
-        print_usage(&program, opts);
-        return;
+    if let Some(k) = matches.opt_str("k") {
+        let re = Regex::new(&k).map_err(|err| cli.error(format!("Incorrect regexp: {:?}", err)))?;
+        simple.add_keys(re);
     }
 
-    let mut filter = rdb::filter::Simple::new();
+    let mut filter = AllOf::new();
+    filter.add(simple);
 
-    for db in &matches.opt_strs("d") {
-        filter.add_database(db.parse().unwrap());
+    let mut glob = rdb::filter::Glob::new();
+    for pattern in &matches.opt_strs("g") {
+        glob.add_pattern(pattern.as_bytes());
     }
+    filter.add(glob);
 
-    for t in &matches.opt_strs("t") {
-        let typ = match &t[..] {
-            "string" => rdb::Type::String,
-            "list" => rdb::Type::List,
-            "set" => rdb::Type::Set,
-            "sortedset" | "sorted-set" | "sorted_set" => rdb::Type::SortedSet,
-            "hash" => rdb::Type::Hash,
-            _ => {
-                println!("Unknown type: {}\n", t);
-                print_usage(&program, opts);
-                return;
+    if let Some(path) = matches.opt_str("prefix-file") {
+        let prefixes = std::fs::read(&path)
+            .map_err(|err| cli.error(format!("Can't read {}: {}", path, err)))?;
+        let mut trie = rdb::filter::Prefixes::new();
+        for prefix in prefixes.split(|&b| b == b'\n') {
+            let prefix = prefix.strip_suffix(b"\r").unwrap_or(prefix);
+            if !prefix.is_empty() {
+                trie.add_prefix(prefix);
             }
-        };
-        filter.add_type(typ);
+        }
+        filter.add(trie);
     }
 
-    if let Some(k) = matches.opt_str("k") {
-        let re = match Regex::new(&k) {
-            Ok(re) => re,
-            Err(err) => {
-                println!("Incorrect regexp: {:?}\n", err);
-                print_usage(&program, opts);
-                return;
-            }
+    if let Some(expr) = matches.opt_str("e") {
+        let expr = rdb::filter::parse_expr(&expr)
+            .map_err(|err| cli.error(format!("Incorrect filter: {}", err)))?;
+        filter.add(expr);
+    }
+
+    if let Some(fraction) = matches.opt_str("sample") {
+        let parsed = match fraction.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>().map(|p| p / 100.0),
+            None => fraction.parse::<f64>(),
         };
-        filter.add_keys(re);
+        match parsed {
+            Ok(parsed) if (0.0..=1.0).contains(&parsed) => {
+                let mut sample = rdb::filter::Sample::new(parsed);
+                sample.set_seed(cli.number(matches, "seed", 0)?);
+                filter.add(sample);
+            }
+            _ => return Err(cli.error(format!("Invalid sample fraction: {}", fraction))),
+        }
+    }
+
+    Ok(filter)
+}
+
+fn add_reservoir_option(opts: &mut Options) {
+    opts.optopt(
+        "",
+        "reservoir",
+        "Only show this many randomly picked keys of every type. Reads the dump twice",
+        "KEYS",
+    );
+}
+
+// Opens a dump to parse with the filter. With `--reservoir` the keys are
+// picked in a first pass skipping all of them, and the dump opened again.
+fn open_filtered(
+    cli: &Cli,
+    matches: &Matches,
+    path: &str,
+    filter: AllOf,
+) -> Result<(BufReader<File>, Box<dyn Filter>), i32> {
+    let mut reader = open(path)?;
+    if !matches.opt_present("reservoir") {
+        return Ok((reader, Box::new(filter)));
+    }
+
+    let mut reservoir = rdb::filter::Reservoir::new(filter, cli.number(matches, "reservoir", 0)?);
+    reservoir.set_seed(cli.number(matches, "seed", 0)?);
+    rdb::parse(&mut reader, rdb::formatter::Nil::new(), &reservoir)
+        .map_err(|e| failure(format!("Parsing failed: {}", e)))?;
+    Ok((open(path)?, Box::new(reservoir.into_keys())))
+}
+
+fn add_transform_options(opts: &mut Options) {
+    opts.optmulti(
+        "",
+        "renumber",
+        "Move the keys of a database to another. Can be specified multiple times",
+        "FROM:TO",
+    );
+    opts.optopt(
+        "",
+        "strip-prefix",
+        "Remove this prefix from the keys starting with it",
+        "PREFIX",
+    );
+    opts.optmulti(
+        "",
+        "rename-keys",
+        "Replace the first match of a regular expression in keys, like ^user:(.*)=account:$1. Can be specified multiple times",
+        "REGEX=REPLACEMENT",
+    );
+    opts.optopt(
+        "",
+        "add-prefix",
+        "Put this prefix in front of every key",
+        "PREFIX",
+    );
+}
+
+fn build_transform(cli: &Cli, matches: &Matches) -> Result<Transform, i32> {
+    let mut transform = Transform::new();
+    for renumber in &matches.opt_strs("renumber") {
+        let databases = renumber
+            .split_once(':')
+            .and_then(|(from, to)| Some((from.parse().ok()?, to.parse().ok()?)));
+        match databases {
+            Some((from, to)) => transform.map_database(from, to),
+            None => return Err(cli.error(format!("Invalid database renumbering: {}", renumber))),
+        }
+    }
+    if let Some(prefix) = matches.opt_str("strip-prefix") {
+        transform.strip_prefix(prefix.as_bytes());
+    }
+    for rename in &matches.opt_strs("rename-keys") {
+        let re = rename
+            .split_once('=')
+            .and_then(|(re, replacement)| Some((Regex::new(re).ok()?, replacement)));
+        match re {
+            Some((re, replacement)) => transform.replace_keys(re, replacement),
+            None => return Err(cli.error(format!("Invalid key renaming: {}", rename))),
+        }
+    }
+    if let Some(prefix) = matches.opt_str("add-prefix") {
+        transform.add_prefix(prefix.as_bytes());
     }
+    Ok(transform)
+}
+
+fn add_protocol_options(opts: &mut Options) {
+    opts.optflag(
+        "",
+        "restore",
+        "With the protocol format, load every key with a single RESTORE",
+    );
+    opts.optopt(
+        "",
+        "batch",
+        "With the protocol format, write up to this many elements per command",
+        "ELEMENTS",
+    );
+    opts.optflag(
+        "",
+        "replace",
+        "With the protocol format, delete every key before writing it",
+    );
+    opts.optflag(
+        "",
+        "drop-expired",
+        "With the protocol format, leave out keys which already expired",
+    );
+    opts.optflag(
+        "",
+        "relative-expiry",
+        "With the protocol format, write expiries relative to now with PEXPIRE",
+    );
+    opts.optflag(
+        "",
+        "pxat",
+        "With the protocol format, write the expiry of strings with SET ... PXAT",
+    );
+}
+
+fn build_protocol<W: Write>(
+    cli: &Cli,
+    matches: &Matches,
+    out: W,
+) -> Result<rdb::formatter::Protocol<W>, i32> {
+    let mut protocol = rdb::formatter::Protocol::with_output(out);
+    protocol.set_restore_mode(matches.opt_present("restore"));
+    protocol.set_replace(matches.opt_present("replace"));
+    protocol.set_drop_expired(matches.opt_present("drop-expired"));
+    protocol.set_relative_expiry(matches.opt_present("relative-expiry"));
+    protocol.set_string_pxat(matches.opt_present("pxat"));
+    if matches.opt_present("batch") {
+        protocol.set_batch_size(cli.number(matches, "batch", 0)?, 1024 * 1024);
+    }
+    Ok(protocol)
+}
+
+fn add_stats_options(opts: &mut Options) {
+    opts.optopt(
+        "",
+        "top",
+        "Only report this many of the biggest keys",
+        "KEYS",
+    );
+    opts.optopt(
+        "",
+        "delimiter",
+        "Count keys per namespace, the part before this delimiter",
+        "DELIMITER",
+    );
+}
+
+fn build_stats(cli: &Cli, matches: &Matches) -> Result<rdb::formatter::Stats, i32> {
+    let mut stats = rdb::formatter::Stats::new();
+    if let Some(delimiter) = matches.opt_str("delimiter") {
+        stats.set_namespace_delimiter(delimiter.as_bytes());
+    }
+    if matches.opt_present("top") {
+        stats.set_top(cli.number(matches, "top", 0)?);
+    }
+    Ok(stats)
+}
+
+fn parse(program: &str, args: Vec<String>) -> Result<(), i32> {
+    let mut cli = Cli::new(program, "parse", "dump.rdb");
+    cli.opts.optopt(
+        "f",
+        "format",
+        "Format to output. Valid: json, ndjson, plain, memory, stats, stats-json, nil, protocol",
+        "FORMAT",
+    );
+    add_filter_options(&mut cli.opts);
+    add_reservoir_option(&mut cli.opts);
+    add_transform_options(&mut cli.opts);
+    add_protocol_options(&mut cli.opts);
+    add_stats_options(&mut cli.opts);
+    cli.opts.optopt(
+        "",
+        "bytes",
+        "With the json formats, how to write binary data. Valid: escape, base64, hex",
+        "ENCODING",
+    );
+    cli.opts.optopt(
+        "",
+        "chunk-size",
+        "With the ndjson format, split values bigger than this many bytes over several lines",
+        "BYTES",
+    );
+    cli.opts.optopt(
+        "o",
+        "output",
+        "Write the matching keys to a new dump instead of formatting them",
+        "FILE",
+    );
+    cli.opts.optflag(
+        "",
+        "strip-expired",
+        "Leave out expired keys when writing a new dump",
+    );
+
+    let matches = cli.parse(args, 1, false)?;
+    let filter = build_filter(&cli, &matches)?;
+    let transform = build_transform(&cli, &matches)?;
 
-    if matches.free.is_empty() {
-        print_usage(&program, opts);
-        return;
+    let bytes = match matches.opt_str("bytes").as_deref() {
+        None | Some("escape") => ByteEncoding::Escape,
+        Some("base64") => ByteEncoding::Base64,
+        Some("hex") => ByteEncoding::Hex,
+        Some(other) => return Err(cli.error(format!("Unknown byte encoding: {}", other))),
+    };
+    let format = matches.opt_str("f").unwrap_or_else(|| "json".to_string());
+    let known = [
+        "json",
+        "ndjson",
+        "plain",
+        "memory",
+        "stats",
+        "stats-json",
+        "nil",
+        "protocol",
+    ];
+    if !known.contains(&&format[..]) {
+        return Err(cli.error(format!("Unknown format: {}", format)));
     }
 
-    let path = matches.free[0].clone();
-    let file = File::open(&Path::new(&*path)).unwrap();
-    let mut reader = BufReader::new(file);
-    let mut res = Ok(());
+    let (mut reader, filter) = open_filtered(&cli, &matches, &matches.free[0], filter)?;
+
+    let res = if let Some(output) = matches.opt_str("o") {
+        let mut rewrite = rdb::rewrite::Rewrite::new(filter);
+        rewrite.set_transform(transform);
+        if matches.opt_present("strip-expired") {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            rewrite.strip_expired(now.as_millis() as u64);
+        }
 
-    if let Some(f) = matches.opt_str("f") {
-        match &f[..] {
+        File::create(Path::new(&output))
+            .map(BufWriter::new)
+            .and_then(|out| rewrite.run(reader, out))
+            .and_then(|mut out| out.flush())
+    } else {
+        match &format[..] {
             "json" => {
-                res = rdb::parse(&mut reader, rdb::formatter::JSON::new(), filter);
+                let mut json = rdb::formatter::JSON::new();
+                json.set_byte_encoding(bytes);
+                rdb::parse(&mut reader, Transforming::new(json, transform), filter)
             }
-            "plain" => {
-                res = rdb::parse(&mut reader, rdb::formatter::Plain::new(), filter);
+            "ndjson" => {
+                let mut ndjson = rdb::formatter::NDJSON::new();
+                ndjson.set_byte_encoding(bytes);
+                let mut records = rdb::formatter::Records::new(ndjson);
+                if matches.opt_present("chunk-size") {
+                    records.set_max_size(cli.number(&matches, "chunk-size", 0)?);
+                }
+                rdb::parse(&mut reader, Transforming::new(records, transform), filter)
             }
-            "nil" => {
-                res = rdb::parse(&mut reader, rdb::formatter::Nil::new(), filter);
+            "plain" => rdb::parse(
+                &mut reader,
+                Transforming::new(rdb::formatter::Plain::new(), transform),
+                filter,
+            ),
+            "memory" => {
+                let mut memory = rdb::formatter::Memory::new();
+                if matches.opt_present("top") {
+                    memory.set_top(cli.number(&matches, "top", 0)?);
+                }
+                rdb::parse(&mut reader, Transforming::new(memory, transform), filter)
             }
-            "protocol" => {
-                res = rdb::parse(&mut reader, rdb::formatter::Protocol::new(), filter);
+            "stats" | "stats-json" => {
+                let mut stats = build_stats(&cli, &matches)?;
+                if format == "stats-json" {
+                    stats.set_format(rdb::formatter::StatsFormat::JSON);
+                }
+                rdb::parse(&mut reader, Transforming::new(stats, transform), filter)
             }
+            "nil" => rdb::parse(
+                &mut reader,
+                Transforming::new(rdb::formatter::Nil::new(), transform),
+                filter,
+            ),
             _ => {
-                println!("Unknown format: {}\n", f);
-                print_usage(&program, opts);
+                let protocol = build_protocol(&cli, &matches, io::stdout())?;
+                rdb::parse(&mut reader, Transforming::new(protocol, transform), filter)
             }
         }
+    };
+    res.map_err(|e| failure(format!("Parsing failed: {}", e)))
+}
+
+fn stats(program: &str, args: Vec<String>) -> Result<(), i32> {
+    let mut cli = Cli::new(program, "stats", "dump.rdb");
+    add_filter_options(&mut cli.opts);
+    add_reservoir_option(&mut cli.opts);
+    add_stats_options(&mut cli.opts);
+    cli.opts.optflag("", "json", "Report as JSON");
+
+    let matches = cli.parse(args, 1, false)?;
+    let filter = build_filter(&cli, &matches)?;
+    let mut stats = build_stats(&cli, &matches)?;
+    if matches.opt_present("json") {
+        stats.set_format(rdb::formatter::StatsFormat::JSON);
+    }
+
+    let (mut reader, filter) = open_filtered(&cli, &matches, &matches.free[0], filter)?;
+    rdb::parse(&mut reader, stats, filter).map_err(|e| failure(format!("Parsing failed: {}", e)))
+}
+
+// The options of `sync` a config file takes the place of.
+const SYNC_OPTIONS: &[&str] = &[
+    "master",
+    "password",
+    "format",
+    "target",
+    "allow-command",
+    "deny-command",
+    "keys",
+    "databases",
+    "type",
+    "filter",
+    "glob",
+    "prefix-file",
+    "sample",
+    "seed",
+    "renumber",
+    "strip-prefix",
+    "rename-keys",
+    "add-prefix",
+];
+
+fn sync(program: &str, args: Vec<String>) -> Result<(), i32> {
+    let mut cli = Cli::new(program, "sync", "");
+    cli.opts.optopt(
+        "c",
+        "config",
+        "Take the settings from a TOML or YAML file instead of the other options. Send SIGHUP to reload its filter and transform",
+        "FILE",
+    );
+    cli.opts
+        .optopt("m", "master", "The master to replicate from", "HOST:PORT");
+    cli.opts
+        .optopt("a", "password", "Password of the master", "PASSWORD");
+    cli.opts.optopt(
+        "f",
+        "format",
+        "Format to output. Valid: plain, protocol",
+        "FORMAT",
+    );
+    cli.opts.optopt(
+        "",
+        "target",
+        "Send everything as commands to this server instead of writing it out",
+        "HOST:PORT",
+    );
+    cli.opts.optmulti(
+        "",
+        "allow-command",
+        "Only pass on this command of the stream, and the others allowed. Can be specified multiple times",
+        "COMMAND",
+    );
+    cli.opts.optmulti(
+        "",
+        "deny-command",
+        "Drop this command of the stream. Can be specified multiple times",
+        "COMMAND",
+    );
+    add_filter_options(&mut cli.opts);
+    add_transform_options(&mut cli.opts);
+
+    let matches = cli.parse(args, 0, false)?;
+    if let Some(path) = matches.opt_str("config") {
+        let others: Vec<String> = SYNC_OPTIONS.iter().map(|name| name.to_string()).collect();
+        if matches.opts_present(&others) {
+            return Err(cli.error("--config can't be combined with other options"));
+        }
+        return sync_with_config(Path::new(&path));
+    }
+    let master = match matches.opt_str("m") {
+        Some(master) => master,
+        None => return Err(cli.error("Either --master or --config is required")),
+    };
+    let filter = build_filter(&cli, &matches)?;
+    let transform = build_transform(&cli, &matches)?;
+    let target = matches.opt_str("target");
+    let format = match (matches.opt_str("f").as_deref(), &target) {
+        (None, Some(_)) | (Some("protocol"), _) => rdb::OutputFormat::Protocol,
+        (None, None) | (Some("plain"), None) => rdb::OutputFormat::Plain,
+        (Some("plain"), Some(_)) => return Err(cli.error("A target takes the protocol format")),
+        (Some(other), _) => return Err(cli.error(format!("Unknown format: {}", other))),
+    };
+
+    let output: Box<dyn Write> = match target {
+        Some(addr) => Box::new(
+            Target::connect(&addr)
+                .map_err(|e| failure(format!("Can't connect to {}: {}", addr, e)))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+
+    let password = matches.opt_str("a").unwrap_or_default();
+    let mut canal = rdb::Canal::new(master.clone(), 0, -1, password)
+        .map_err(|e| failure(format!("Can't connect to {}: {}", master, e)))?;
+    print_port(&canal);
+    canal.filter = CommandFilter::new(Box::new(filter));
+    for name in matches.opt_strs("allow-command") {
+        canal.filter.allow_command(&name);
+    }
+    for name in matches.opt_strs("deny-command") {
+        canal.filter.deny_command(&name);
+    }
+    canal.transform = transform;
+    canal.format = format;
+    canal.output = output;
+    canal
+        .dump_and_parse()
+        .map_err(|e| failure(format!("Replication failed: {}", e)))
+}
+
+fn sync_with_config(path: &Path) -> Result<(), i32> {
+    let config = Config::load(path).map_err(failure)?;
+    let sink = &config.sink;
+    let mut output: Option<Box<dyn Write>> = Some(match sink.kind {
+        SinkType::Stdout => Box::new(io::stdout()),
+        SinkType::File => {
+            let path = sink.path.as_ref().unwrap();
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| failure(format!("Can't open {}: {}", path.display(), e)))?;
+            Box::new(BufWriter::new(file))
+        }
+        SinkType::Redis => {
+            let addr = sink.address.as_ref().unwrap();
+            Box::new(
+                Target::connect(addr)
+                    .map_err(|e| failure(format!("Can't connect to {}: {}", addr, e)))?,
+            )
+        }
+    });
+
+    let mut canal: Option<rdb::Canal> = None;
+    let mut failures = 0;
+    loop {
+        let offset = canal
+            .as_ref()
+            .map(|canal| canal.offset.load(Ordering::Relaxed));
+        let error = match connect(&config, path, &mut canal, &mut output) {
+            Ok(canal) => match canal.dump_and_parse() {
+                Ok(()) => return Ok(()),
+                Err(e) => format!("Replication failed: {}", e),
+            },
+            Err(e) => e,
+        };
+        // A connection which got anywhere starts the count over.
+        if canal
+            .as_ref()
+            .map(|canal| canal.offset.load(Ordering::Relaxed))
+            != offset
+        {
+            failures = 0;
+        }
+        failures += 1;
+        if failures > config.retry.attempts {
+            return Err(failure(error));
+        }
+        let delay = config.retry.delay(failures);
+        eprintln!("{}, retrying in {:?}", error, delay);
+        thread::sleep(delay);
+    }
+}
+
+// Connects to the master, setting up the sync on the first connection.
+fn connect<'a>(
+    config: &Config,
+    path: &Path,
+    canal: &'a mut Option<rdb::Canal>,
+    output: &mut Option<Box<dyn Write>>,
+) -> Result<&'a mut rdb::Canal, String> {
+    let master = config
+        .source
+        .resolve()
+        .map_err(|e| format!("Can't find the master: {}", e))?;
+    if let Some(canal) = canal {
+        canal
+            .reconnect(&master)
+            .map_err(|e| format!("Can't connect to {}: {}", master, e))?;
+        print_port(canal);
+        return Ok(canal);
+    }
+
+    let password = config.source.password.clone().unwrap_or_default();
+    let tls = config.source.tls().map_err(|e| e.to_string())?;
+    let new = match tls {
+        Some(tls) => rdb::Canal::with_tls(master.clone(), tls, 0, -1, password),
+        None => rdb::Canal::new(master.clone(), 0, -1, password),
+    };
+    let mut new = new.map_err(|e| format!("Can't connect to {}: {}", master, e))?;
+    print_port(&new);
+    // Validated when loading the config.
+    new.filter = config.filter.build().map_err(|e| e.to_string())?;
+    new.transform = config.transform.build().map_err(|e| e.to_string())?;
+    new.format = config.sink.format();
+    new.output = output.take().unwrap();
+    if let Some(batch) = config.sink.batch {
+        new.set_batch_size(batch, config.sink.batch_bytes.unwrap_or(usize::MAX));
+    }
+    if let Some(checkpoint) = &config.checkpoint {
+        let interval = Duration::from_millis(checkpoint.interval_ms);
+        new.set_checkpoint(&checkpoint.path, interval)
+            .map_err(|e| format!("Can't read {}: {}", checkpoint.path.display(), e))?;
+    }
+    new.reload =
+        Some(Reload::on_sighup(path).map_err(|e| format!("Can't watch for SIGHUP: {}", e))?);
+    new.on_reload = Some(Box::new(|reloaded| match reloaded {
+        Ok(()) => eprintln!("Reloaded the filter and transform"),
+        Err(e) => eprintln!("Keeping the filter and transform: {}", e),
+    }));
+    Ok(canal.insert(new))
+}
+
+// The master knows the replica by the port it connected from.
+fn print_port(canal: &rdb::Canal) {
+    if let Ok(addr) = canal.conn.local_addr() {
+        eprintln!("Replicating from local port {}", addr.port());
+    }
+}
+
+fn replay(program: &str, args: Vec<String>) -> Result<(), i32> {
+    let mut cli = Cli::new(program, "replay", "dump.rdb");
+    cli.opts.reqopt(
+        "",
+        "target",
+        "The server to load the keys into",
+        "HOST:PORT",
+    );
+    add_filter_options(&mut cli.opts);
+    add_reservoir_option(&mut cli.opts);
+    add_transform_options(&mut cli.opts);
+    add_protocol_options(&mut cli.opts);
+
+    let matches = cli.parse(args, 1, false)?;
+    let filter = build_filter(&cli, &matches)?;
+    let transform = build_transform(&cli, &matches)?;
+    let (mut reader, filter) = open_filtered(&cli, &matches, &matches.free[0], filter)?;
+
+    let addr = matches.opt_str("target").unwrap();
+    let mut target =
+        Target::connect(&addr).map_err(|e| failure(format!("Can't connect to {}: {}", addr, e)))?;
+    let protocol = build_protocol(&cli, &matches, &mut target)?;
+    rdb::parse(&mut reader, Transforming::new(protocol, transform), filter)
+        .map_err(|e| failure(format!("Parsing failed: {}", e)))?;
+
+    match target.finish() {
+        Ok(0) => Ok(()),
+        Ok(errors) => Err(failure(format!("{} commands failed", errors))),
+        Err(e) => Err(failure(format!("Replay failed: {}", e))),
+    }
+}
+
+fn diff(program: &str, args: Vec<String>) -> Result<(), i32> {
+    let mut cli = Cli::new(program, "diff", "first.rdb second.rdb");
+    add_filter_options(&mut cli.opts);
+
+    let matches = cli.parse(args, 2, false)?;
+    let filter = build_filter(&cli, &matches)?;
+    // Exits with 2 for trouble, as 1 means there are differences.
+    let first = open(&matches.free[0]).map_err(|_| USAGE_ERROR)?;
+    let second = open(&matches.free[1]).map_err(|_| USAGE_ERROR)?;
+
+    let differences = rdb::diff::diff(first, second, &filter).map_err(|e| {
+        eprintln!("Parsing failed: {}", e);
+        USAGE_ERROR
+    })?;
+    for difference in &differences {
+        let change = match difference.change {
+            Change::Removed => '-',
+            Change::Added => '+',
+            Change::Changed => '~',
+        };
+        println!(
+            "{} db={} {}",
+            change,
+            difference.db,
+            ByteEncoding::Escape.encode(&difference.key)
+        );
+    }
+    if differences.is_empty() {
+        Ok(())
     } else {
-        res = rdb::parse(&mut reader, rdb::formatter::JSON::new(), filter);
+        Err(FAILURE)
+    }
+}
+
+fn verify(program: &str, args: Vec<String>) -> Result<(), i32> {
+    let cli = Cli::new(program, "verify", "dump.rdb...");
+    let matches = cli.parse(args, 1, true)?;
+
+    let mut result = Ok(());
+    for path in &matches.free {
+        let verification = open(path).and_then(|reader| {
+            rdb::verify::verify(reader).map_err(|e| failure(format!("{}: {}", path, e)))
+        });
+        match verification {
+            Ok(verification) => {
+                let checksum = match verification.checksum {
+                    Checksum::Valid => "valid",
+                    Checksum::Disabled => "disabled",
+                    Checksum::Absent => "absent",
+                };
+                println!(
+                    "{}: OK, version {}, {} databases, {} keys, checksum {}",
+                    path, verification.version, verification.databases, verification.keys, checksum
+                );
+            }
+            Err(code) => result = Err(code),
+        }
+    }
+    result
+}
+
+fn aof(program: &str, args: Vec<String>) -> Result<(), i32> {
+    let mut cli = Cli::new(program, "aof", "appendonly.aof");
+    cli.opts.optopt(
+        "f",
+        "format",
+        "Format to output. Valid: plain, protocol",
+        "FORMAT",
+    );
+    cli.opts.optmulti(
+        "",
+        "allow-command",
+        "Only pass on this command, and the others allowed. Can be specified multiple times",
+        "COMMAND",
+    );
+    cli.opts.optmulti(
+        "",
+        "deny-command",
+        "Drop this command. Can be specified multiple times",
+        "COMMAND",
+    );
+    cli.opts.optflag(
+        "",
+        "fix",
+        "Cut an incomplete command off the end of the file, like redis-check-aof --fix",
+    );
+    add_filter_options(&mut cli.opts);
+    add_transform_options(&mut cli.opts);
+
+    let matches = cli.parse(args, 1, false)?;
+    let filter = build_filter(&cli, &matches)?;
+    let transform = build_transform(&cli, &matches)?;
+    let format = match matches.opt_str("f").as_deref() {
+        None | Some("plain") => rdb::OutputFormat::Plain,
+        Some("protocol") => rdb::OutputFormat::Protocol,
+        Some(other) => return Err(cli.error(format!("Unknown format: {}", other))),
+    };
+
+    let path = &matches.free[0];
+    let reader = open(path)?;
+    // The preamble and the commands both go to stdout, in order.
+    let truncated = match format {
+        rdb::OutputFormat::Plain => {
+            let visitor = Transforming::new(rdb::formatter::Plain::new(), transform.clone());
+            print_aof(
+                AofParser::new(reader, visitor, filter),
+                &matches,
+                &transform,
+                format,
+            )
+        }
+        rdb::OutputFormat::Protocol => {
+            let visitor = Transforming::new(rdb::formatter::Protocol::new(), transform.clone());
+            print_aof(
+                AofParser::new(reader, visitor, filter),
+                &matches,
+                &transform,
+                format,
+            )
+        }
+    }
+    .map_err(|e| failure(format!("Parsing failed: {}", e)))?;
+
+    if let Some(length) = truncated {
+        if matches.opt_present("fix") {
+            OpenOptions::new()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_len(length))
+                .map_err(|e| failure(format!("Can't truncate {}: {}", path, e)))?;
+            eprintln!("Truncated {} to {} bytes", path, length);
+        } else {
+            eprintln!(
+                "{} ends with an incomplete command at offset {}, ignored. Run with --fix to cut it off",
+                path, length
+            );
+        }
     }
+    Ok(())
+}
+
+// Returns how much of the file is whole if it ends in an incomplete command.
+fn print_aof<F: rdb::formatter::Visitor>(
+    mut parser: AofParser<BufReader<File>, F, AllOf>,
+    matches: &Matches,
+    transform: &Transform,
+    format: rdb::OutputFormat,
+) -> io::Result<Option<u64>> {
+    for name in matches.opt_strs("allow-command") {
+        parser.filter_mut().allow_command(&name);
+    }
+    for name in matches.opt_strs("deny-command") {
+        parser.filter_mut().deny_command(&name);
+    }
+    let mut out = io::stdout();
+    for command in &mut parser {
+        let command = command?;
+        let args = transform.apply_command(command.args);
+        rdb::write_command(&mut out, format, transform.db(command.db), &args)?;
+    }
+    out.flush()?;
+    Ok(parser.truncated())
+}
+
+// Sent after everything else, its reply marks the end of the replies.
+const END_MARKER: &[u8] = b"redis-canal-rs: end of replay";
+
+// A server commands are sent to. Its replies are read on another thread, so
+// sending never waits for them, and errors among them reported.
+struct Target {
+    conn: BufWriter<TcpStream>,
+    replies: JoinHandle<io::Result<u64>>,
+}
+
+impl Target {
+    fn connect(addr: &str) -> io::Result<Target> {
+        let conn = TcpStream::connect(addr)?;
+        let input = BufReader::new(conn.try_clone()?);
+        Ok(Target {
+            conn: BufWriter::new(conn),
+            replies: thread::spawn(move || read_replies(input)),
+        })
+    }
+
+    // Waits for the replies to everything sent, returning how many were
+    // errors.
+    fn finish(mut self) -> io::Result<u64> {
+        write!(self.conn, "*2\r\n$4\r\nECHO\r\n${}\r\n", END_MARKER.len())?;
+        self.conn.write_all(END_MARKER)?;
+        self.conn.write_all(b"\r\n")?;
+        self.conn.flush()?;
+        self.replies
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("Reading the replies failed")))
+    }
+}
 
-    match res {
-        Ok(()) => {}
-        Err(e) => {
-            println!("");
-            let mut stderr = std::io::stderr();
+impl Write for Target {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.conn.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.flush()
+    }
+}
 
-            let out = format!("Parsing failed: {}\n", e);
-            stderr.write(out.as_bytes()).unwrap();
+enum Reply {
+    Error(String),
+    Bulk(Vec<u8>),
+    Other,
+}
+
+fn read_reply<R: BufRead>(input: &mut R) -> io::Result<Reply> {
+    let mut line = Vec::new();
+    if input.read_until(b'\n', &mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "The server closed the connection",
+        ));
+    }
+    while line.last().is_some_and(|&b| b == b'\n' || b == b'\r') {
+        line.pop();
+    }
+    let number: i64 = std::str::from_utf8(line.get(1..).unwrap_or_default())
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(-1);
+
+    Ok(match line.first() {
+        Some(b'-') => Reply::Error(String::from_utf8_lossy(&line[1..]).into_owned()),
+        Some(b'$') if number >= 0 => {
+            let mut bulk = vec![0; number as usize + 2];
+            input.read_exact(&mut bulk)?;
+            bulk.truncate(number as usize);
+            Reply::Bulk(bulk)
+        }
+        Some(b'*') => {
+            for _ in 0..number.max(0) {
+                read_reply(input)?;
+            }
+            Reply::Other
+        }
+        _ => Reply::Other,
+    })
+}
+
+fn read_replies<R: BufRead>(mut input: R) -> io::Result<u64> {
+    let mut errors = 0;
+    loop {
+        match read_reply(&mut input)? {
+            Reply::Error(message) => {
+                errors += 1;
+                eprintln!("Command failed: {}", message);
+            }
+            Reply::Bulk(bulk) if bulk == END_MARKER => return Ok(errors),
+            _ => {}
         }
     }
 }
@@ -1,97 +1,112 @@
-use byteorder::ByteOrder;
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
-use bytes::{BufMut, BytesMut};
-use lzf;
 use std::io::Error as IoError;
 use std::io::ErrorKind as IoErrorKind;
-use std::io::{Cursor, Read};
+use std::io::{self, Cursor, Read};
 use std::{f64, str};
 
-use crate::filter::Filter;
-use crate::formatter::Formatter;
+use crate::dump;
+use crate::filter::{Filter, KeyInfo, ValueInfo};
+use crate::formatter::{Control, Visitor};
 use crate::helper;
-use helper::read_exact;
+use helper::{other_error, read_exact};
 
 #[doc(hidden)]
 use crate::constants::{constant, encoding, encoding_type, module, op_code, version};
 
 #[doc(hidden)]
 pub use crate::types::{
-    EncodingType, /* error and result types */
+    EncodingType, KeyMetadata, /* error and result types */
     RdbError, RdbOk, RdbResult, Type, ZiplistEntry,
 };
 
-pub struct RdbParser<R: Read, F: Formatter, L: Filter> {
-    input: R,
+pub struct RdbParser<R: Read, F: Visitor, L: Filter> {
+    input: Input<R>,
     formatter: F,
     filter: L,
-    last_expiretime: Option<u64>,
+    last_metadata: KeyMetadata,
+    last_database: u64,
+    started_database: Option<u64>,
+    skipping_key: bool,
+    stopped: bool,
+    // Written into the payloads of streams and module values.
+    version: u32,
 }
 
-struct StreamGroupPendingEntry {
-    id: [u8; 16],
-    d_time: u64,
-    d_count: u64,
+// Reads the dump, first replaying a value which was scanned for the filter,
+// and records what is read while a value is scanned.
+struct Input<R: Read> {
+    inner: R,
+    replay: Cursor<Vec<u8>>,
+    recording: Option<Vec<u8>>,
 }
 
-impl Clone for StreamGroupPendingEntry {
-    fn clone(&self) -> Self {
-        Self {
-            id: self.id.clone(),
-            d_time: self.d_time,
-            d_count: self.d_count,
+impl<R: Read> Read for Input<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = if (self.replay.position() as usize) < self.replay.get_ref().len() {
+            self.replay.read(buf)?
+        } else {
+            self.inner.read(buf)?
+        };
+        if let Some(ref mut recording) = self.recording {
+            recording.extend_from_slice(&buf[..n]);
         }
+        Ok(n)
     }
 }
 
-struct ConsumerGroup {
-    name: Vec<u8>,
-    last_entry_id: String,
-    pending: Vec<StreamGroupPendingEntry>,
-    consumers: Vec<StreamConsumer>,
+// LZF emits at most 264 bytes for a 3 byte back reference, so anything
+// claiming a bigger expansion than this is corrupt.
+const LZF_MAX_EXPANSION: u64 = 90;
+
+// zlbytes, zltail and zllen.
+const ZIPLIST_HEADER_SIZE: usize = 10;
+// The encoding and the number of members.
+const INTSET_HEADER_SIZE: usize = 8;
+
+fn parse_score(score: &[u8]) -> RdbResult<f64> {
+    str::from_utf8(score)
+        .ok()
+        .and_then(|score| score.parse::<f64>().ok())
+        .ok_or_else(|| other_error("Invalid sorted set score"))
 }
 
-struct StreamConsumer {
-    name: Vec<u8>,
-    seen_time: u64,
-    pending: Vec<Vec<u8>>,
+// A module ID packs the 9 character type name in its upper 54 bits, 6 bits
+// per character, and the encoding version in the lower 10.
+fn module_name(module_id: u64) -> String {
+    (0..9)
+        .map(|i| {
+            let index = (module_id >> (58 - 6 * i)) & 0x3F;
+            module::MODULE_NAME_CHARSET[index as usize] as char
+        })
+        .collect()
 }
 
 #[inline]
-fn other_error(desc: &'static str) -> IoError {
-    IoError::new(IoErrorKind::Other, desc)
+fn is_value_type(enc_type: u8) -> bool {
+    matches!(
+        enc_type,
+        encoding_type::STRING..=encoding_type::MODULE2
+            | encoding_type::HASH_ZIPMAP..=encoding_type::STEAMLISTPACKS
+    )
 }
 
-// fn read_length_with_encoding(&)
 pub fn read_length_with_encoding<T: Read>(input: &mut T) -> Result<(u64, bool), IoError> {
-    let mut length = 0u64;
-    let mut is_encoded = false;
-
     let enc_type = input.read_u8()?;
 
-    match (enc_type & 0xC0) >> 6 {
-        constant::RDB_ENCVAL => {
-            is_encoded = true;
-            length = (enc_type & 0x3F) as u64;
-        }
-        constant::RDB_6BITLEN => {
-            length = (enc_type & 0x3F) as u64;
-        }
+    let result = match (enc_type & 0xC0) >> 6 {
+        constant::RDB_ENCVAL => ((enc_type & 0x3F) as u64, true),
+        constant::RDB_6BITLEN => ((enc_type & 0x3F) as u64, false),
         constant::RDB_14BITLEN => {
             let next_byte = input.read_u8()?;
-            length = (((enc_type & 0x3F) as u64) << 8) | next_byte as u64;
-        }
-        constant::RDB_32BITLEN => {
-            length = input.read_u32::<LittleEndian>()? as u64;
+            ((((enc_type & 0x3F) as u64) << 8) | next_byte as u64, false)
         }
-        constant::RDB_64BITLEN => {
-            length = input.read_u64::<LittleEndian>()?;
-        }
-        _ => {
-            length = input.read_u32::<BigEndian>()? as u64;
-        }
-    }
-    Ok((length, is_encoded))
+        _ => match enc_type {
+            constant::RDB_32BITLEN => (input.read_u32::<BigEndian>()? as u64, false),
+            constant::RDB_64BITLEN => (input.read_u64::<BigEndian>()?, false),
+            _ => return Err(other_error("Unknown length encoding")),
+        },
+    };
+    Ok(result)
 }
 
 pub fn read_length<R: Read>(input: &mut R) -> RdbResult<u64> {
@@ -100,24 +115,14 @@ pub fn read_length<R: Read>(input: &mut R) -> RdbResult<u64> {
 }
 
 pub fn verify_magic<R: Read>(input: &mut R) -> RdbOk {
-    let mut r = String::from("");
-    loop {
-        let buf = input.read_u8()? as char;
-        if buf == 'R' {
-            r = buf.to_string();
-            break;
-        }
-    }
+    // A replication stream prefixes the payload with its bulk length, so skip
+    // ahead to the start of the magic string.
+    while input.read_u8()? != constant::RDB_MAGIC.as_bytes()[0] {}
 
     let mut magic = [0; 4];
-    match input.read(&mut magic) {
-        Ok(4) => (),
-        Ok(_) => return Err(other_error("Could not read enough bytes for the magic")),
-        Err(e) => return Err(e),
-    };
-    let res = str::from_utf8(&magic).unwrap().to_owned();
-    r.push_str(&res);
-    if r == constant::RDB_MAGIC {
+    input.read_exact(&mut magic)?;
+
+    if magic == constant::RDB_MAGIC.as_bytes()[1..] {
         Ok(())
     } else {
         Err(other_error("Invalid magic string"))
@@ -125,22 +130,25 @@ pub fn verify_magic<R: Read>(input: &mut R) -> RdbOk {
 }
 
 pub fn verify_version<R: Read>(input: &mut R) -> RdbOk {
+    read_version(input).map(|_| ())
+}
+
+fn read_version<R: Read>(input: &mut R) -> RdbResult<u32> {
     let mut version = [0; 4];
-    match input.read(&mut version) {
-        Ok(4) => (),
-        Ok(_) => return Err(other_error("Could not read enough bytes for the version")),
-        Err(e) => return Err(e),
-    };
+    input.read_exact(&mut version)?;
+
+    if !version.iter().all(u8::is_ascii_digit) {
+        return Err(other_error("Invalid version number"));
+    }
 
-    let version = (version[0] - 48) as u32 * 1000
-        + (version[1] - 48) as u32 * 100
-        + (version[2] - 48) as u32 * 10
-        + (version[3] - 48) as u32;
+    let version = version
+        .iter()
+        .fold(0, |acc, &digit| acc * 10 + (digit - b'0') as u32);
 
-    let is_ok = version >= version::SUPPORTED_MINIMUM && version <= version::SUPPORTED_MAXIMUM;
+    let is_ok = (version::SUPPORTED_MINIMUM..=version::SUPPORTED_MAXIMUM).contains(&version);
 
     if is_ok {
-        Ok(())
+        Ok(version)
     } else {
         Err(other_error("Version not supported"))
     }
@@ -153,14 +161,18 @@ pub fn read_blob<R: Read>(input: &mut R) -> RdbResult<Vec<u8>> {
         let result = match length {
             encoding::INT8 => helper::int_to_vec(input.read_i8()? as i32),
             encoding::INT16 => helper::int_to_vec(input.read_i16::<LittleEndian>()? as i32),
-            encoding::INT32 => helper::int_to_vec(input.read_i32::<LittleEndian>()? as i32),
+            encoding::INT32 => helper::int_to_vec(input.read_i32::<LittleEndian>()?),
             encoding::LZF => {
                 let compressed_length = read_length(input)?;
                 let real_length = read_length(input)?;
+                if real_length > compressed_length.saturating_mul(LZF_MAX_EXPANSION) {
+                    return Err(other_error("Invalid LZF lengths"));
+                }
                 let data = read_exact(input, compressed_length as usize)?;
-                lzf::decompress(&data, real_length as usize).unwrap()
+                lzf::decompress(&data, real_length as usize)
+                    .map_err(|_| other_error("Could not decompress LZF data"))?
             }
-            _ => panic!("Unknown encoding: {}", length),
+            _ => return Err(other_error("Unknown string encoding")),
         };
 
         Ok(result)
@@ -169,6 +181,40 @@ pub fn read_blob<R: Read>(input: &mut R) -> RdbResult<Vec<u8>> {
     }
 }
 
+// Decompresses LZF data only until `length` bytes came out, or the data
+// ends, leaving the rest of it unread.
+fn lzf_decompress_head<R: Read>(input: &mut R, length: usize) -> RdbResult<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(length);
+    while out.len() < length {
+        let ctrl = match input.read_u8() {
+            Ok(ctrl) => ctrl as usize,
+            Err(ref e) if e.kind() == IoErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        if ctrl < 1 << 5 {
+            // A run of ctrl + 1 literal bytes.
+            let literal = read_exact(input, ctrl + 1)?;
+            out.extend_from_slice(&literal);
+            continue;
+        }
+
+        // A back reference, copied a byte at a time as it may overlap
+        // what it copies.
+        let mut len = ctrl >> 5;
+        if len == 7 {
+            len += input.read_u8()? as usize;
+        }
+        let distance = ((ctrl & 0x1f) << 8) + input.read_u8()? as usize + 1;
+        if distance > out.len() {
+            return Err(other_error("Could not decompress LZF data"));
+        }
+        for _ in 0..len + 2 {
+            out.push(out[out.len() - distance]);
+        }
+    }
+    Ok(out)
+}
+
 fn read_ziplist_metadata<R: Read>(input: &mut R) -> RdbResult<(u32, u32, u16)> {
     let zlbytes = input.read_u32::<LittleEndian>()?;
     let zltail = input.read_u32::<LittleEndian>()?;
@@ -177,89 +223,266 @@ fn read_ziplist_metadata<R: Read>(input: &mut R) -> RdbResult<(u32, u32, u16)> {
     Ok((zlbytes, zltail, zllen))
 }
 
-impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
+pub fn read_ziplist_entry<T: Read>(ziplist: &mut T) -> RdbResult<ZiplistEntry> {
+    // 1. 1 or 5 bytes length of previous entry
+    let byte = ziplist.read_u8()?;
+    if byte == 254 {
+        let mut bytes = [0; 4];
+        ziplist.read_exact(&mut bytes)?;
+    }
+
+    // 2. Read flag or number value
+    let flag = ziplist.read_u8()?;
+
+    let length = match (flag & 0xC0) >> 6 {
+        0 => (flag & 0x3F) as u64,
+        1 => {
+            let next_byte = ziplist.read_u8()?;
+            (((flag & 0x3F) as u64) << 8) | next_byte as u64
+        }
+        2 => ziplist.read_u32::<BigEndian>()? as u64,
+        _ => {
+            let number_value = match (flag & 0xF0) >> 4 {
+                0xC => ziplist.read_i16::<LittleEndian>()? as i64,
+                0xD => ziplist.read_i32::<LittleEndian>()? as i64,
+                0xE => ziplist.read_i64::<LittleEndian>()?,
+                _ => match flag & 0xF {
+                    0 => {
+                        let mut bytes = [0; 3];
+                        ziplist.read_exact(&mut bytes)?;
+
+                        let number: i32 = (((bytes[2] as i32) << 24)
+                            ^ ((bytes[1] as i32) << 16)
+                            ^ ((bytes[0] as i32) << 8)
+                            ^ 48)
+                            >> 8;
+
+                        number as i64
+                    }
+                    0xE => ziplist.read_i8()? as i64,
+                    0xF => return Err(other_error("Unexpected end of ziplist")),
+                    _ => (flag & 0xF) as i64 - 1,
+                },
+            };
+
+            return Ok(ZiplistEntry::Number(number_value));
+        }
+    };
+
+    // 3. Read value
+    let rawval = read_exact(ziplist, length as usize)?;
+    Ok(ZiplistEntry::String(rawval))
+}
+
+impl<R: Read, F: Visitor, L: Filter> RdbParser<R, F, L> {
     pub fn new(input: R, formatter: F, filter: L) -> RdbParser<R, F, L> {
         RdbParser {
-            input: input,
-            formatter: formatter,
-            filter: filter,
-            last_expiretime: None,
+            input: Input {
+                inner: input,
+                replay: Cursor::new(Vec::new()),
+                recording: None,
+            },
+            formatter,
+            filter,
+            last_metadata: KeyMetadata::default(),
+            last_database: 0,
+            started_database: None,
+            skipping_key: false,
+            stopped: false,
+            version: dump::PAYLOAD_VERSION as u32,
         }
     }
 
     pub fn parse(&mut self) -> RdbOk {
+        self.parse_header()?;
+        while self.parse_next()? {}
+        Ok(())
+    }
+
+    /// Verifies the magic string and version and starts the dump.
+    pub fn parse_header(&mut self) -> RdbOk {
         verify_magic(&mut self.input)?;
-        verify_version(&mut self.input)?;
-        self.formatter.start_rdb();
+        self.version = read_version(&mut self.input)?;
+        self.visit(|f, _| f.start_rdb())?;
+        Ok(())
+    }
 
-        let mut last_database: u64 = 0;
-        loop {
-            let next_op = self.input.read_u8()?;
+    /// Reads the next opcode and everything belonging to it, a whole key
+    /// for value types. Returns false once the end of the dump was read or
+    /// the visitor stopped the parse.
+    pub fn parse_next(&mut self) -> RdbResult<bool> {
+        if self.stopped {
+            return Ok(false);
+        }
+        self.skipping_key = false;
+        let next_op = self.input.read_u8()?;
 
-            match next_op {
-                op_code::SELECTDB => {
-                    last_database = read_length(&mut self.input)?;
-                    if self.filter.matches_db(last_database) {
-                        self.formatter.start_database(last_database);
-                    }
-                }
-                op_code::EOF => {
-                    self.formatter.end_database(last_database);
-                    self.formatter.end_rdb();
-                    self.read_eof()?;
-                    break;
-                }
-                op_code::EXPIRETIME_MS => {
-                    let expiretime_ms = self.input.read_u64::<LittleEndian>()?;
-                    self.last_expiretime = Some(expiretime_ms);
-                }
-                op_code::EXPIRETIME => {
-                    let expiretime = self.input.read_u32::<BigEndian>()?;
-                    self.last_expiretime = Some(expiretime as u64 * 1000);
+        match next_op {
+            op_code::SELECTDB => {
+                if let Some(db) = self.started_database.take() {
+                    self.visit(|f, _| f.end_database(db))?;
                 }
-                op_code::RESIZEDB => {
-                    let db_size = read_length(&mut self.input)?;
-                    let expires_size = read_length(&mut self.input)?;
 
-                    self.formatter.resizedb(db_size, expires_size);
+                let db = read_length(&mut self.input)?;
+                self.last_database = db;
+                if self.filter.matches_db(db) {
+                    self.visit(|f, _| f.start_database(db))?;
+                    self.started_database = Some(db);
                 }
-                op_code::AUX => {
-                    let auxkey = read_blob(&mut self.input)?;
-                    let auxval = read_blob(&mut self.input)?;
-                    self.formatter.aux_field(&auxkey, &auxval);
-                }
-                op_code::Idle => {
-                    read_length(&mut self.input)?;
+            }
+            op_code::EOF => {
+                if let Some(db) = self.started_database.take() {
+                    self.visit(|f, _| f.end_database(db))?;
                 }
-                op_code::Freq => {
-                    self.input.read_u8()?;
+                self.visit(|f, _| f.end_rdb())?;
+                self.read_eof()?;
+                return Ok(false);
+            }
+            op_code::EXPIRETIME_MS => {
+                let expiretime_ms = self.input.read_u64::<LittleEndian>()?;
+                self.last_metadata.expiry = Some(expiretime_ms);
+            }
+            op_code::EXPIRETIME => {
+                let expiretime = self.input.read_u32::<LittleEndian>()?;
+                self.last_metadata.expiry = Some(expiretime as u64 * 1000);
+            }
+            op_code::RESIZEDB => {
+                let db_size = read_length(&mut self.input)?;
+                let expires_size = read_length(&mut self.input)?;
+
+                self.visit(|f, _| f.resizedb(db_size, expires_size))?;
+            }
+            op_code::AUX => {
+                let auxkey = read_blob(&mut self.input)?;
+                let auxval = read_blob(&mut self.input)?;
+                self.visit(|f, _| f.aux_field(&auxkey, &auxval))?;
+            }
+            op_code::IDLE => {
+                let idle = read_length(&mut self.input)?;
+                self.last_metadata.idle = Some(idle);
+            }
+            op_code::FREQ => {
+                let freq = self.input.read_u8()?;
+                self.last_metadata.freq = Some(freq);
+            }
+            op_code::MODULE_AUX => {
+                self.skip_module_aux()?;
+            }
+            _ => {
+                if !is_value_type(next_op) {
+                    return Err(other_error("Unknown value type"));
                 }
-                _ => {
-                    if self.filter.matches_db(last_database) {
-                        let key = read_blob(&mut self.input)?;
 
-                        if self.filter.matches_type(next_op) && self.filter.matches_key(&key) {
+                let expiry = self.last_metadata.expiry;
+                if self.filter.matches_db(self.last_database)
+                    && self.filter.matches_type(next_op)
+                    && self.filter.matches_expiry(expiry)
+                {
+                    let key = read_blob(&mut self.input)?;
+                    let mut info = KeyInfo {
+                        db: self.last_database,
+                        enc_type: next_op,
+                        key: &key,
+                        expiry,
+                        value: None,
+                    };
+
+                    if !self.filter.matches(&info) {
+                        self.skip_object(next_op)?
+                    } else if !self.filter.inspects_values() {
+                        self.read_type(&key, next_op)?
+                    } else {
+                        self.input.recording = Some(Vec::new());
+                        let scanned = self.scan_object(next_op);
+                        let raw = self.input.recording.take().unwrap_or_default();
+                        let (value, rest) = scanned?;
+
+                        info.value = Some(&value);
+                        if self.filter.matches(&info) {
+                            self.input.replay = Cursor::new(raw);
                             self.read_type(&key, next_op)?
                         } else {
-                            self.skip_object(next_op)?
+                            self.skip(rest)?
                         }
-                    } else {
-                        self.skip_key_and_object(next_op)?
                     }
-
-                    self.last_expiretime = None;
+                } else {
+                    self.skip_key_and_object(next_op)?
                 }
+
+                self.last_metadata = KeyMetadata::default();
             }
         }
 
+        Ok(!self.stopped)
+    }
+
+    /// Reads a single value of the given type, as stored after its key.
+    pub fn parse_value(&mut self, key: &[u8], value_type: u8) -> RdbOk {
+        if !is_value_type(value_type) {
+            return Err(other_error("Unknown value type"));
+        }
+        self.skipping_key = false;
+        self.read_type(key, value_type)
+    }
+
+    pub fn formatter(&mut self) -> &mut F {
+        &mut self.formatter
+    }
+
+    pub fn filter(&self) -> &L {
+        &self.filter
+    }
+
+    pub fn into_formatter(self) -> F {
+        self.formatter
+    }
+
+    // Makes a visitor callback unless the current key is skipped or the
+    // parse was stopped.
+    fn visit<C>(&mut self, callback: C) -> RdbOk
+    where
+        C: FnOnce(&mut F, &KeyMetadata) -> RdbResult<Control>,
+    {
+        if self.skipping_key || self.stopped {
+            return Ok(());
+        }
+        match callback(&mut self.formatter, &self.last_metadata)? {
+            Control::Continue => {}
+            Control::SkipKey => self.skipping_key = true,
+            Control::Stop => self.stopped = true,
+        }
         Ok(())
     }
 
+    // Whether a callback asked to skip the current key or to stop.
+    fn interrupted(&self) -> bool {
+        self.skipping_key || self.stopped
+    }
+
+    // Ends reading a value once a callback interrupted it. The rest of a
+    // skipped key is skipped with `skip`, without decoding it, while
+    // nothing more is read once the parse was stopped.
+    fn skip_rest<S>(&mut self, skip: S) -> RdbResult<bool>
+    where
+        S: FnOnce(&mut Self) -> RdbOk,
+    {
+        if self.stopped {
+            return Ok(true);
+        }
+        if self.skipping_key {
+            skip(self)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // Reads no further than the checksum, as commands follow it in an
+    // append-only file.
     fn read_eof(&mut self) -> RdbOk {
-        let mut buf = [0; 8];
-        let len = self.input.read(&mut buf)?;
-        if len > 0 {
-            self.formatter.checksum(&buf);
+        let mut buf = Vec::with_capacity(8);
+        (&mut self.input).take(8).read_to_end(&mut buf)?;
+        if !buf.is_empty() {
+            self.visit(|f, _| f.checksum(&buf))?;
         }
         Ok(())
     }
@@ -269,25 +492,29 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
 
         match typ {
             Type::List => {
-                self.formatter
-                    .start_list(key, len, self.last_expiretime, EncodingType::LinkedList);
+                self.visit(|f, meta| f.start_list(key, len, meta, EncodingType::LinkedList))?;
             }
             Type::Set => {
-                self.formatter
-                    .start_set(key, len, self.last_expiretime, EncodingType::LinkedList);
+                self.visit(|f, meta| f.start_set(key, len, meta, EncodingType::Hashtable))?;
             }
             _ => panic!("Unknown encoding type for linked list"),
         }
 
         while len > 0 {
+            if self.skip_rest(|p| p.skip_blobs(len))? {
+                return Ok(());
+            }
             let blob = read_blob(&mut self.input)?;
-            self.formatter.list_element(key, &blob);
+            match typ {
+                Type::List => self.visit(|f, _| f.list_element(key, &blob))?,
+                _ => self.visit(|f, _| f.set_element(key, &blob))?,
+            }
             len -= 1;
         }
 
         match typ {
-            Type::List => self.formatter.end_list(key),
-            Type::Set => self.formatter.end_set(key),
+            Type::List => self.visit(|f, _| f.end_list(key))?,
+            Type::Set => self.visit(|f, _| f.end_set(key))?,
             _ => panic!("Unknown encoding type for linked list"),
         }
 
@@ -309,14 +536,12 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
     fn read_sorted_set(&mut self, key: &[u8], entyp: EncodingType) -> RdbOk {
         let mut set_items = read_length(&mut self.input)?;
 
-        self.formatter.start_sorted_set(
-            key,
-            set_items,
-            self.last_expiretime,
-            EncodingType::Hashtable,
-        );
+        self.visit(|f, meta| f.start_sorted_set(key, set_items, meta, EncodingType::Skiplist))?;
 
         while set_items > 0 {
+            if self.skip_rest(|p| p.skip_sorted_set_items(set_items, entyp))? {
+                return Ok(());
+            }
             match entyp {
                 EncodingType::ZSET => {
                     let val = read_blob(&mut self.input)?;
@@ -327,22 +552,18 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
                         255 => f64::NEG_INFINITY,
                         _ => {
                             let tmp = read_exact(&mut self.input, score_length as usize)?;
-                            unsafe { str::from_utf8_unchecked(&tmp) }
-                                .parse::<f64>()
-                                .unwrap()
+                            parse_score(&tmp)?
                         }
                     };
-                    self.formatter.sorted_set_element(key, score, &val);
+                    self.visit(|f, _| f.sorted_set_element(key, score, &val))?;
 
                     set_items -= 1;
                 }
                 EncodingType::ZSET2 => {
                     let val = read_blob(&mut self.input)?;
-                    let mut buf = [0; 8];
-                    self.input.read(&mut buf)?;
-                    let score = LittleEndian::read_f64(&buf);
+                    let score = self.input.read_f64::<LittleEndian>()?;
 
-                    self.formatter.sorted_set_element(key, score, &val);
+                    self.visit(|f, _| f.sorted_set_element(key, score, &val))?;
 
                     set_items -= 1;
                 }
@@ -350,7 +571,7 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
             };
         }
 
-        self.formatter.end_sorted_set(key);
+        self.visit(|f, _| f.end_sorted_set(key))?;
 
         Ok(())
     }
@@ -358,107 +579,27 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
     fn read_hash(&mut self, key: &[u8]) -> RdbOk {
         let mut hash_items = read_length(&mut self.input)?;
 
-        self.formatter.start_hash(
-            key,
-            hash_items,
-            self.last_expiretime,
-            EncodingType::Hashtable,
-        );
+        self.visit(|f, meta| f.start_hash(key, hash_items, meta, EncodingType::Hashtable))?;
 
         while hash_items > 0 {
+            if self.skip_rest(|p| p.skip_blobs(hash_items.saturating_mul(2)))? {
+                return Ok(());
+            }
             let field = read_blob(&mut self.input)?;
             let val = read_blob(&mut self.input)?;
 
-            self.formatter.hash_element(key, &field, &val);
+            self.visit(|f, _| f.hash_element(key, &field, &val))?;
 
             hash_items -= 1;
         }
 
-        self.formatter.end_hash(key);
+        self.visit(|f, _| f.end_hash(key))?;
 
         Ok(())
     }
 
-    fn read_ziplist_entry<T: Read>(&mut self, ziplist: &mut T) -> RdbResult<ZiplistEntry> {
-        // 1. 1 or 5 bytes length of previous entry
-        let byte = ziplist.read_u8()?;
-        if byte == 254 {
-            let mut bytes = [0; 4];
-            match ziplist.read(&mut bytes) {
-                Ok(4) => (),
-                Ok(_) => {
-                    return Err(other_error(
-                        "Could not read 4 bytes to skip after ziplist length",
-                    ))
-                }
-                Err(e) => return Err(e),
-            };
-        }
-
-        let length: u64;
-        let number_value: i64;
-
-        // 2. Read flag or number value
-        let flag = ziplist.read_u8()?;
-
-        match (flag & 0xC0) >> 6 {
-            0 => length = (flag & 0x3F) as u64,
-            1 => {
-                let next_byte = ziplist.read_u8()?;
-                length = (((flag & 0x3F) as u64) << 8) | next_byte as u64;
-            }
-            2 => {
-                length = ziplist.read_u32::<BigEndian>()? as u64;
-            }
-            _ => {
-                match (flag & 0xF0) >> 4 {
-                    0xC => number_value = ziplist.read_i16::<LittleEndian>()? as i64,
-                    0xD => number_value = ziplist.read_i32::<LittleEndian>()? as i64,
-                    0xE => number_value = ziplist.read_i64::<LittleEndian>()? as i64,
-                    0xF => match flag & 0xF {
-                        0 => {
-                            let mut bytes = [0; 3];
-                            match ziplist.read(&mut bytes) {
-                                Ok(3) => (),
-                                Ok(_) => {
-                                    return Err(other_error(
-                                        "Could not read enough bytes for 24bit number",
-                                    ))
-                                }
-                                Err(e) => return Err(e),
-                            };
-
-                            let number: i32 = (((bytes[2] as i32) << 24)
-                                ^ ((bytes[1] as i32) << 16)
-                                ^ ((bytes[0] as i32) << 8)
-                                ^ 48)
-                                >> 8;
-
-                            number_value = number as i64;
-                        }
-                        0xE => {
-                            number_value = ziplist.read_i8()? as i64;
-                        }
-                        _ => {
-                            number_value = (flag & 0xF) as i64 - 1;
-                        }
-                    },
-                    _ => {
-                        panic!("Flag not handled: {}", flag);
-                    }
-                }
-
-                return Ok(ZiplistEntry::Number(number_value));
-            }
-        }
-
-        // 3. Read value
-        let rawval = read_exact(ziplist, length as usize)?;
-        Ok(ZiplistEntry::String(rawval))
-    }
-
     fn read_ziplist_entry_string<T: Read>(&mut self, reader: &mut T) -> RdbResult<Vec<u8>> {
-        let entry = self.read_ziplist_entry(reader)?;
+        let entry = read_ziplist_entry(reader)?;
         match entry {
             ZiplistEntry::String(val) => Ok(val),
             ZiplistEntry::Number(val) => Ok(val.to_string().into_bytes()),
@@ -472,16 +613,16 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
         let mut reader = Cursor::new(ziplist);
         let (_zlbytes, _zltail, zllen) = read_ziplist_metadata(&mut reader)?;
 
-        self.formatter.start_list(
-            key,
-            zllen as u64,
-            self.last_expiretime,
-            EncodingType::Ziplist(raw_length),
-        );
+        self.visit(|f, meta| {
+            f.start_list(key, zllen as u64, meta, EncodingType::Ziplist(raw_length))
+        })?;
 
         for _ in 0..zllen {
+            if self.interrupted() {
+                return Ok(());
+            }
             let entry = self.read_ziplist_entry_string(&mut reader)?;
-            self.formatter.list_element(key, &entry);
+            self.visit(|f, _| f.list_element(key, &entry))?;
         }
 
         let last_byte = reader.read_u8()?;
@@ -489,7 +630,7 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
             return Err(other_error("Invalid end byte of ziplist"));
         }
 
-        self.formatter.end_list(key);
+        self.visit(|f, _| f.end_list(key))?;
 
         Ok(())
     }
@@ -501,20 +642,22 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
         let mut reader = Cursor::new(ziplist);
         let (_zlbytes, _zltail, zllen) = read_ziplist_metadata(&mut reader)?;
 
-        assert!(zllen % 2 == 0);
+        if zllen % 2 != 0 {
+            return Err(other_error("Odd number of entries in hash ziplist"));
+        }
         let zllen = zllen / 2;
 
-        self.formatter.start_hash(
-            key,
-            zllen as u64,
-            self.last_expiretime,
-            EncodingType::Ziplist(raw_length),
-        );
+        self.visit(|f, meta| {
+            f.start_hash(key, zllen as u64, meta, EncodingType::Ziplist(raw_length))
+        })?;
 
         for _ in 0..zllen {
+            if self.interrupted() {
+                return Ok(());
+            }
             let field = self.read_ziplist_entry_string(&mut reader)?;
             let value = self.read_ziplist_entry_string(&mut reader)?;
-            self.formatter.hash_element(key, &field, &value);
+            self.visit(|f, _| f.hash_element(key, &field, &value))?;
         }
 
         let last_byte = reader.read_u8()?;
@@ -522,7 +665,7 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
             return Err(other_error("Invalid end byte of ziplist"));
         }
 
-        self.formatter.end_hash(key);
+        self.visit(|f, _| f.end_hash(key))?;
 
         Ok(())
     }
@@ -534,21 +677,23 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
         let mut reader = Cursor::new(ziplist);
         let (_zlbytes, _zltail, zllen) = read_ziplist_metadata(&mut reader)?;
 
-        self.formatter.start_sorted_set(
-            key,
-            zllen as u64,
-            self.last_expiretime,
-            EncodingType::Ziplist(raw_length),
-        );
-
-        assert!(zllen % 2 == 0);
+        if zllen % 2 != 0 {
+            return Err(other_error("Odd number of entries in sorted set ziplist"));
+        }
         let zllen = zllen / 2;
 
+        self.visit(|f, meta| {
+            f.start_sorted_set(key, zllen as u64, meta, EncodingType::Ziplist(raw_length))
+        })?;
+
         for _ in 0..zllen {
+            if self.interrupted() {
+                return Ok(());
+            }
             let entry = self.read_ziplist_entry_string(&mut reader)?;
             let score = self.read_ziplist_entry_string(&mut reader)?;
-            let score = str::from_utf8(&score).unwrap().parse::<f64>().unwrap();
-            self.formatter.sorted_set_element(key, score, &entry);
+            let score = parse_score(&score)?;
+            self.visit(|f, _| f.sorted_set_element(key, score, &entry))?;
         }
 
         let last_byte = reader.read_u8()?;
@@ -556,7 +701,7 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
             return Err(other_error("Invalid end byte of ziplist"));
         }
 
-        self.formatter.end_sorted_set(key);
+        self.visit(|f, _| f.end_sorted_set(key))?;
 
         Ok(())
     }
@@ -568,8 +713,11 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
         let (_zlbytes, _zltail, zllen) = read_ziplist_metadata(&mut reader)?;
 
         for _ in 0..zllen {
+            if self.interrupted() {
+                return Ok(());
+            }
             let entry = self.read_ziplist_entry_string(&mut reader)?;
-            self.formatter.list_element(key, &entry);
+            self.visit(|f, _| f.list_element(key, &entry))?;
         }
 
         let last_byte = reader.read_u8()?;
@@ -581,12 +729,11 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
     }
 
     fn read_zipmap_entry<T: Read>(&mut self, next_byte: u8, zipmap: &mut T) -> RdbResult<Vec<u8>> {
-        let elem_len;
-        match next_byte {
-            253 => elem_len = zipmap.read_u32::<LittleEndian>().unwrap(),
-            254 | 255 => panic!("Invalid length value in zipmap: {}", next_byte),
-            _ => elem_len = next_byte as u32,
-        }
+        let elem_len = match next_byte {
+            253 => zipmap.read_u32::<LittleEndian>()?,
+            254 | 255 => return Err(other_error("Invalid length value in zipmap")),
+            _ => next_byte as u32,
+        };
 
         read_exact(zipmap, elem_len as usize)
     }
@@ -609,14 +756,14 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
             size = 0;
         }
 
-        self.formatter.start_hash(
-            key,
-            size as u64,
-            self.last_expiretime,
-            EncodingType::Zipmap(raw_length),
-        );
+        self.visit(|f, meta| {
+            f.start_hash(key, size as u64, meta, EncodingType::Zipmap(raw_length))
+        })?;
 
         loop {
+            if self.interrupted() {
+                return Ok(());
+            }
             let next_byte = reader.read_u8()?;
 
             if next_byte == 0xFF {
@@ -629,7 +776,7 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
             let _free = reader.read_u8()?;
             let value = self.read_zipmap_entry(next_byte, &mut reader)?;
 
-            self.formatter.hash_element(key, &field, &value);
+            self.visit(|f, _| f.hash_element(key, &field, &value))?;
 
             if length > 0 {
                 length -= 1;
@@ -645,7 +792,7 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
             }
         }
 
-        self.formatter.end_hash(key);
+        self.visit(|f, _| f.end_hash(key))?;
 
         Ok(())
     }
@@ -658,25 +805,28 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
         let byte_size = reader.read_u32::<LittleEndian>()?;
         let intset_length = reader.read_u32::<LittleEndian>()? as u64;
 
-        self.formatter.start_set(
-            key,
-            intset_length,
-            self.last_expiretime,
-            EncodingType::Intset(raw_length),
-        );
+        if byte_size != 2 && byte_size != 4 && byte_size != 8 {
+            return Err(other_error("Invalid integer size in intset"));
+        }
+
+        self.visit(|f, meta| {
+            f.start_set(key, intset_length, meta, EncodingType::Intset(raw_length))
+        })?;
 
         for _ in 0..intset_length {
+            if self.interrupted() {
+                return Ok(());
+            }
             let val = match byte_size {
                 2 => reader.read_i16::<LittleEndian>()? as i64,
                 4 => reader.read_i32::<LittleEndian>()? as i64,
-                8 => reader.read_i64::<LittleEndian>()?,
-                _ => panic!("unhandled byte size in intset: {}", byte_size),
+                _ => reader.read_i64::<LittleEndian>()?,
             };
 
-            self.formatter.set_element(key, val.to_string().as_bytes());
+            self.visit(|f, _| f.set_element(key, val.to_string().as_bytes()))?;
         }
 
-        self.formatter.end_set(key);
+        self.visit(|f, _| f.end_set(key))?;
 
         Ok(())
     }
@@ -684,12 +834,14 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
     fn read_quicklist(&mut self, key: &[u8]) -> RdbOk {
         let len = read_length(&mut self.input)?;
 
-        self.formatter
-            .start_set(key, 0, self.last_expiretime, EncodingType::Quicklist);
-        for _ in 0..len {
+        self.visit(|f, meta| f.start_list(key, 0, meta, EncodingType::Quicklist))?;
+        for node in 0..len {
+            if self.skip_rest(|p| p.skip_blobs(len - node))? {
+                return Ok(());
+            }
             self.read_quicklist_ziplist(key)?;
         }
-        self.formatter.end_set(key);
+        self.visit(|f, _| f.end_list(key))?;
 
         Ok(())
     }
@@ -698,137 +850,152 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
         match value_type {
             encoding_type::STRING => {
                 let val = read_blob(&mut self.input)?;
-                self.formatter.set(key, &val, self.last_expiretime);
+                self.visit(|f, meta| f.set(key, &val, meta))?;
             }
             encoding_type::LIST => self.read_linked_list(key, Type::List)?,
             encoding_type::SET => self.read_linked_list(key, Type::Set)?,
             encoding_type::ZSET => self.read_sorted_set(key, EncodingType::ZSET)?,
             encoding_type::HASH => self.read_hash(key)?,
             encoding_type::ZSET2 => self.read_sorted_set(key, EncodingType::ZSET2)?,
-            // encoding_type::MODULE => panic!("Not support value type: {}", value_type), //not support it
-            encoding_type::MODULE2 => self.read_moudle()?,
+            encoding_type::MODULE2 => {
+                self.record_payload();
+                let module_id = self.skip_module()?;
+                let name = module_name(module_id);
+                self.visit(|f, meta| f.module(key, &name, meta))?;
+                self.visit_payload(key, value_type)?;
+            }
             encoding_type::HASH_ZIPMAP => self.read_hash_zipmap(key)?,
             encoding_type::LIST_ZIPLIST => self.read_list_ziplist(key)?,
             encoding_type::SET_INTSET => self.read_set_intset(key)?,
             encoding_type::ZSET_ZIPLIST => self.read_sortedset_ziplist(key)?,
             encoding_type::HASH_ZIPLIST => self.read_hash_ziplist(key)?,
             encoding_type::LIST_QUICKLIST => self.read_quicklist(key)?,
-            encoding_type::STEAMLISTPACKS => self.skip_steam()?,
+            encoding_type::STEAMLISTPACKS => {
+                self.record_payload();
+                let length = self.skip_stream()?;
+                self.visit(|f, meta| f.stream(key, length, meta))?;
+                self.visit_payload(key, value_type)?;
+            }
 
-            _ => panic!("Value Type not implemented: {}", value_type),
+            _ => return Err(other_error("Value type not supported")),
         };
 
         Ok(())
     }
 
-    fn skip_steam(&mut self) -> RdbOk {
-        let mut listpacks = read_length(&mut self.input)?;
-        while listpacks > 0 {
-            read_blob(&mut self.input)?;
-            read_blob(&mut self.input)?;
-            listpacks -= 1;
+    // Streams and module values are only skipped, so their bytes are kept
+    // for a formatter wanting them.
+    fn record_payload(&mut self) {
+        if self.formatter.wants_payloads() && !self.interrupted() {
+            self.input.recording = Some(Vec::new());
         }
+    }
+
+    fn visit_payload(&mut self, key: &[u8], value_type: u8) -> RdbOk {
+        if let Some(value) = self.input.recording.take() {
+            let payload = dump::raw_payload(value_type, &value, self.version as u16);
+            self.visit(|f, meta| f.payload(key, &payload, meta))?;
+        }
+        Ok(())
+    }
+
+    fn skip_stream(&mut self) -> RdbResult<u64> {
+        let listpacks = read_length(&mut self.input)?;
+        for _ in 0..listpacks {
+            // The master entry ID followed by the listpack itself.
+            self.skip_blob()?;
+            self.skip_blob()?;
+        }
+
+        // Number of elements and the last entry ID.
+        let length = read_length(&mut self.input)?;
         read_length(&mut self.input)?;
         read_length(&mut self.input)?;
-        read_length(&mut self.input)?;
-        let mut cgroups = read_length(&mut self.input)?;
-        let entrys: &mut Vec<StreamGroupPendingEntry> = &mut Vec::new();
-        let mut consumer_groups: Vec<ConsumerGroup> = Vec::new();
-        while cgroups > 0 {
-            let cgname = read_blob(&mut self.input)?;
-            let entry1 = read_length(&mut self.input)?;
-            let entry2 = read_length(&mut self.input)?;
-            let laste_cg_entry_id = format!("{}-{}", entry1, entry2);
+
+        let cgroups = read_length(&mut self.input)?;
+        for _ in 0..cgroups {
+            self.skip_blob()?;
+            read_length(&mut self.input)?;
+            read_length(&mut self.input)?;
+
             let pending = read_length(&mut self.input)?;
             for _ in 0..pending {
-                let mut buf = [0; 16];
-                self.input.read(&mut buf)?;
-                let mut dtime_buf = [0; 8];
-                self.input.read(&mut dtime_buf)?;
-                let dtime = LittleEndian::read_u64(&dtime_buf);
-                let dcount = read_length(&mut self.input)?;
-                entrys.push(StreamGroupPendingEntry {
-                    d_count: dcount,
-                    d_time: dtime,
-                    id: buf,
-                });
+                // Entry ID, delivery time and delivery count.
+                self.skip(16 + 8)?;
+                read_length(&mut self.input)?;
             }
+
             let consumers = read_length(&mut self.input)?;
-            let mut centrys: Vec<StreamConsumer> = Vec::new();
             for _ in 0..consumers {
-                let cname = read_blob(&mut self.input)?;
-                let mut buf = [0; 8];
-                self.input.read(&mut buf)?;
-                let stime = LittleEndian::read_u64(&buf);
-                let cpending = read_length(&mut self.input)?;
-                let mut pending_ids: Vec<Vec<u8>> = Vec::new();
-                for _ in 0..cpending {
-                    let mut eid = vec![0; 16];
-                    self.input.read(&mut eid)?;
-                    pending_ids.push(eid);
-                }
-                centrys.push(StreamConsumer {
-                    name: cname,
-                    seen_time: stime,
-                    pending: pending_ids,
-                });
+                self.skip_blob()?;
+                self.skip(8)?;
+                let pending = read_length(&mut self.input)?;
+                self.skip_n(pending, 16)?;
             }
-
-            consumer_groups.push(ConsumerGroup {
-                name: cgname,
-                pending: entrys.to_vec(),
-                consumers: centrys,
-                last_entry_id: laste_cg_entry_id,
-            });
-            cgroups -= 1;
         }
-        Ok(())
+
+        Ok(length)
+    }
+
+    fn skip_module(&mut self) -> RdbResult<u64> {
+        // The 64 bit module ID, then opcode tagged values up to the EOF marker.
+        let module_id = read_length(&mut self.input)?;
+        self.skip_module_values()?;
+        Ok(module_id)
     }
 
-    fn read_moudle(&mut self) -> RdbOk {
+    fn skip_module_aux(&mut self) -> RdbOk {
+        // Module ID and the "when" opcode/value pair.
         read_length(&mut self.input)?;
-        self.skip_moudle()?;
-        Ok(())
+        read_length(&mut self.input)?;
+        read_length(&mut self.input)?;
+        self.skip_module_values()
     }
 
-    fn skip_moudle(&mut self) -> RdbOk {
-        let mut optcode = read_length(&mut self.input)?;
-        while optcode != module::ModuleOpcodeEOF as u64 {
-            match optcode {
-                optcode if optcode == module::ModuleOpcodeSInt as u64 => {
-                    read_length(&mut self.input)?;
-                }
-                optcode if optcode == module::ModuleOpcodeUInt as u64 => {
-                    read_length(&mut self.input)?;
-                }
-                optcode if optcode == module::ModuleOpcodeFloat as u64 => {
-                    read_length(&mut self.input)?;
-                }
-                optcode if optcode == module::ModuleOpcodeString as u64 => {
-                    read_blob(&mut self.input)?;
-                }
-                optcode if optcode == module::ModuleOpcodeDouble as u64 => {
+    fn skip_module_values(&mut self) -> RdbOk {
+        loop {
+            let opcode = read_length(&mut self.input)?;
+            match opcode {
+                opcode if opcode == module::MODULE_OPCODE_EOF as u64 => break,
+                opcode
+                    if opcode == module::MODULE_OPCODE_SINT as u64
+                        || opcode == module::MODULE_OPCODE_UINT as u64 =>
+                {
                     read_length(&mut self.input)?;
                 }
-                _ => println!("Unknown module opcode: {}", optcode),
+                opcode if opcode == module::MODULE_OPCODE_FLOAT as u64 => self.skip(4)?,
+                opcode if opcode == module::MODULE_OPCODE_DOUBLE as u64 => self.skip(8)?,
+                opcode if opcode == module::MODULE_OPCODE_STRING as u64 => self.skip_blob()?,
+                _ => return Err(other_error("Unknown module opcode")),
             }
-            optcode = read_length(&mut self.input)?;
         }
 
         Ok(())
     }
 
-    fn skip(&mut self, skip_bytes: usize) -> RdbResult<()> {
-        let mut buf = vec![0; skip_bytes];
-        self.input.read_exact(&mut buf)
+    fn skip(&mut self, skip_bytes: u64) -> RdbResult<()> {
+        let skipped = io::copy(&mut (&mut self.input).take(skip_bytes), &mut io::sink())?;
+        if skipped != skip_bytes {
+            return Err(IoError::new(
+                IoErrorKind::UnexpectedEof,
+                "failed to skip whole object",
+            ));
+        }
+        Ok(())
+    }
+
+    fn skip_n(&mut self, count: u64, size: u64) -> RdbResult<()> {
+        let skip_bytes = count
+            .checked_mul(size)
+            .ok_or_else(|| other_error("Object too large to skip"))?;
+        self.skip(skip_bytes)
     }
 
     fn skip_blob(&mut self) -> RdbResult<()> {
         let (len, is_encoded) = read_length_with_encoding(&mut self.input)?;
-        let skip_bytes;
 
-        if is_encoded {
-            skip_bytes = match len {
+        let skip_bytes = if is_encoded {
+            match len {
                 encoding::INT8 => 1,
                 encoding::INT16 => 2,
                 encoding::INT32 => 4,
@@ -837,13 +1004,35 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
                     let _real_length = read_length(&mut self.input)?;
                     compressed_length
                 }
-                _ => panic!("Unknown encoding: {}", len),
+                _ => return Err(other_error("Unknown string encoding")),
             }
         } else {
-            skip_bytes = len;
+            len
+        };
+
+        self.skip(skip_bytes)
+    }
+
+    fn skip_blobs(&mut self, count: u64) -> RdbOk {
+        for _ in 0..count {
+            self.skip_blob()?;
         }
+        Ok(())
+    }
 
-        self.skip(skip_bytes as usize)
+    fn skip_sorted_set_items(&mut self, count: u64, entyp: EncodingType) -> RdbOk {
+        for _ in 0..count {
+            self.skip_blob()?;
+            if entyp == EncodingType::ZSET2 {
+                self.skip(8)?;
+            } else {
+                let score_length = self.input.read_u8()?;
+                if score_length < 253 {
+                    self.skip(score_length as u64)?;
+                }
+            }
+        }
+        Ok(())
     }
 
     fn skip_object(&mut self, enc_type: u8) -> RdbResult<()> {
@@ -857,15 +1046,201 @@ impl<R: Read, F: Formatter, L: Filter> RdbParser<R, F, L> {
             encoding_type::LIST | encoding_type::SET | encoding_type::LIST_QUICKLIST => {
                 read_length(&mut self.input)?
             }
-            encoding_type::ZSET | encoding_type::HASH => read_length(&mut self.input)? * 2,
-            _ => panic!("Unknown encoding type: {}", enc_type),
+            encoding_type::HASH => read_length(&mut self.input)?.saturating_mul(2),
+            encoding_type::ZSET => {
+                let set_items = read_length(&mut self.input)?;
+                self.skip_sorted_set_items(set_items, EncodingType::ZSET)?;
+                0
+            }
+            encoding_type::ZSET2 => {
+                let set_items = read_length(&mut self.input)?;
+                self.skip_sorted_set_items(set_items, EncodingType::ZSET2)?;
+                0
+            }
+            encoding_type::MODULE2 => {
+                self.skip_module()?;
+                0
+            }
+            encoding_type::STEAMLISTPACKS => {
+                self.skip_stream()?;
+                0
+            }
+            _ => return Err(other_error("Value type not supported")),
+        };
+
+        self.skip_blobs(blobs_to_skip)
+    }
+
+    // Reads as little of a value as a filter needs to know about it: the
+    // length prefixes, and the headers of the strings holding encoded
+    // values. Returns what it found and how many bytes of the value are
+    // left, which are only read if the key matches. Values made of many
+    // strings are read whole, their size is known no other way.
+    fn scan_object(&mut self, enc_type: u8) -> RdbResult<(ValueInfo, u64)> {
+        let (encoding, len, rest) = match enc_type {
+            encoding_type::STRING => {
+                let (len, rest) = self.scan_string()?;
+                (EncodingType::String, len, rest)
+            }
+            encoding_type::LIST | encoding_type::SET | encoding_type::HASH => {
+                let len = read_length(&mut self.input)?;
+                let blobs = if enc_type == encoding_type::HASH {
+                    len.saturating_mul(2)
+                } else {
+                    len
+                };
+                for _ in 0..blobs {
+                    self.skip_blob()?;
+                }
+                let encoding = if enc_type == encoding_type::LIST {
+                    EncodingType::LinkedList
+                } else {
+                    EncodingType::Hashtable
+                };
+                (encoding, len, 0)
+            }
+            encoding_type::ZSET | encoding_type::ZSET2 => {
+                let len = read_length(&mut self.input)?;
+                for _ in 0..len {
+                    self.skip_blob()?;
+                    if enc_type == encoding_type::ZSET2 {
+                        self.skip(8)?;
+                    } else {
+                        let score_length = self.input.read_u8()?;
+                        if score_length < 253 {
+                            self.skip(score_length as u64)?;
+                        }
+                    }
+                }
+                (EncodingType::Skiplist, len, 0)
+            }
+            encoding_type::LIST_ZIPLIST
+            | encoding_type::ZSET_ZIPLIST
+            | encoding_type::HASH_ZIPLIST => {
+                let (size, head, rest) = self.scan_blob_head(ZIPLIST_HEADER_SIZE)?;
+                let (_zlbytes, _zltail, zllen) = read_ziplist_metadata(&mut Cursor::new(&head))?;
+                let len = if enc_type == encoding_type::LIST_ZIPLIST {
+                    zllen as u64
+                } else {
+                    zllen as u64 / 2
+                };
+                (EncodingType::Ziplist(size), len, rest)
+            }
+            encoding_type::SET_INTSET => {
+                let (size, head, rest) = self.scan_blob_head(INTSET_HEADER_SIZE)?;
+                let mut reader = Cursor::new(&head);
+                reader.read_u32::<LittleEndian>()?;
+                let len = reader.read_u32::<LittleEndian>()? as u64;
+                (EncodingType::Intset(size), len, rest)
+            }
+            encoding_type::HASH_ZIPMAP => {
+                // Zipmaps with too many entries for the header to count
+                // them have to be walked, they are small and long gone
+                // anyway, so they are always read whole.
+                let zipmap = read_blob(&mut self.input)?;
+                let mut reader = Cursor::new(&zipmap);
+                let zmlen = reader.read_u8()?;
+                let len = if zmlen <= 253 {
+                    zmlen as u64
+                } else {
+                    let mut len = 0;
+                    loop {
+                        let next_byte = reader.read_u8()?;
+                        if next_byte == 0xFF {
+                            break;
+                        }
+                        self.read_zipmap_entry(next_byte, &mut reader)?;
+                        let next_byte = reader.read_u8()?;
+                        let _free = reader.read_u8()?;
+                        self.read_zipmap_entry(next_byte, &mut reader)?;
+                        len += 1;
+                    }
+                    len
+                };
+                (EncodingType::Zipmap(zipmap.len() as u64), len, 0)
+            }
+            encoding_type::LIST_QUICKLIST => {
+                let nodes = read_length(&mut self.input)?;
+                let mut len = 0;
+                for _ in 0..nodes {
+                    let (_, head, rest) = self.scan_blob_head(ZIPLIST_HEADER_SIZE)?;
+                    let (_zlbytes, _zltail, zllen) =
+                        read_ziplist_metadata(&mut Cursor::new(&head))?;
+                    len += zllen as u64;
+                    self.skip(rest)?;
+                }
+                (EncodingType::Quicklist, len, 0)
+            }
+            encoding_type::MODULE2 => {
+                self.skip_module()?;
+                (EncodingType::Moudle2, 0, 0)
+            }
+            encoding_type::STEAMLISTPACKS => {
+                (EncodingType::StreamListpacks, self.skip_stream()?, 0)
+            }
+            _ => return Err(other_error("Value type not supported")),
+        };
+
+        let scanned = self
+            .input
+            .recording
+            .as_ref()
+            .map_or(0, |raw| raw.len() as u64);
+        let value = ValueInfo {
+            encoding,
+            len,
+            size: scanned.saturating_add(rest),
+        };
+        Ok((value, rest))
+    }
+
+    // Reads the length prefix of a string, returning its length, as
+    // `STRLEN` reports it, and how many bytes of it are left.
+    fn scan_string(&mut self) -> RdbResult<(u64, u64)> {
+        let (len, is_encoded) = read_length_with_encoding(&mut self.input)?;
+        if !is_encoded {
+            return Ok((len, len));
+        }
+
+        let number = match len {
+            encoding::INT8 => self.input.read_i8()? as i32,
+            encoding::INT16 => self.input.read_i16::<LittleEndian>()? as i32,
+            encoding::INT32 => self.input.read_i32::<LittleEndian>()?,
+            encoding::LZF => {
+                let compressed_length = read_length(&mut self.input)?;
+                let real_length = read_length(&mut self.input)?;
+                return Ok((real_length, compressed_length));
+            }
+            _ => return Err(other_error("Unknown string encoding")),
         };
+        Ok((helper::int_to_vec(number).len() as u64, 0))
+    }
 
-        for _ in 0..blobs_to_skip {
-            self.skip_blob()?
+    // Reads the first bytes of a string holding an encoded value, enough
+    // for its header. Returns the string's length, the bytes read and how
+    // many bytes of the string are left. A compressed string is only
+    // decompressed as far as the header.
+    fn scan_blob_head(&mut self, head_length: usize) -> RdbResult<(u64, Vec<u8>, u64)> {
+        let (len, is_encoded) = read_length_with_encoding(&mut self.input)?;
+        if !is_encoded {
+            let head = read_exact(&mut self.input, head_length.min(len as usize))?;
+            let rest = len - head.len() as u64;
+            return Ok((len, head, rest));
         }
 
-        Ok(())
+        match len {
+            encoding::LZF => {
+                let compressed_length = read_length(&mut self.input)?;
+                let real_length = read_length(&mut self.input)?;
+                if real_length > compressed_length.saturating_mul(LZF_MAX_EXPANSION) {
+                    return Err(other_error("Invalid LZF lengths"));
+                }
+                let mut compressed = (&mut self.input).take(compressed_length);
+                let head = lzf_decompress_head(&mut compressed, head_length)?;
+                Ok((real_length, head, compressed.limit()))
+            }
+            _ => Err(other_error("Unexpected string encoding")),
+        }
     }
 
     fn skip_key_and_object(&mut self, enc_type: u8) -> RdbResult<()> {
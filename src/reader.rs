@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+use std::io::Read;
+
+use crate::filter::{Filter, Simple};
+use crate::formatter::{RecordHandler, Records};
+use crate::parser::RdbParser;
+use crate::types::{KeyRecord, RdbOk, RdbResult};
+
+/// One item of a dump, as returned by `RdbReader`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    AuxField {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    SelectDb(u64),
+    ResizeDb {
+        db_size: u64,
+        expires_size: u64,
+    },
+    Key(KeyRecord),
+    /// Part of a key bigger than the maximum size set on the reader.
+    Chunk {
+        record: KeyRecord,
+        last: bool,
+    },
+}
+
+/// Pull based access to a dump: every call to `next` parses just enough of
+/// the input to return the following entry.
+///
+/// ```no_run
+/// # use redis_canal_rs::reader::{Entry, RdbReader};
+/// # use std::fs::File;
+/// # use std::io::BufReader;
+/// let file = BufReader::new(File::open("dump.rdb").unwrap());
+/// let keys = RdbReader::new(file)
+///     .filter_map(|entry| match entry {
+///         Ok(Entry::Key(key)) => Some(key.key),
+///         _ => None,
+///     })
+///     .take(10);
+/// ```
+pub struct RdbReader<R: Read, L: Filter = Simple> {
+    parser: RdbParser<R, Records<Queue>, L>,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> RdbReader<R> {
+    pub fn new(input: R) -> RdbReader<R> {
+        RdbReader::with_filter(input, Simple::new())
+    }
+}
+
+impl<R: Read, L: Filter> RdbReader<R, L> {
+    pub fn with_filter(input: R, filter: L) -> RdbReader<R, L> {
+        RdbReader {
+            parser: RdbParser::new(input, Records::new(Queue::default()), filter),
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Hands out values bigger than `max_size` bytes in several chunks.
+    ///
+    /// Only the entries are split: a key is parsed completely before the
+    /// first of its chunks is returned, so all of them are queued in memory
+    /// together.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.parser.formatter().set_max_size(max_size);
+    }
+
+    fn read_entry(&mut self) -> RdbResult<Option<Entry>> {
+        if !self.started {
+            self.started = true;
+            self.parser.parse_header()?;
+        }
+
+        loop {
+            if let Some(entry) = self.parser.formatter().handler().entries.pop_front() {
+                return Ok(Some(entry));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            self.finished = !self.parser.parse_next()?;
+        }
+    }
+}
+
+impl<R: Read, L: Filter> Iterator for RdbReader<R, L> {
+    type Item = RdbResult<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_entry() {
+            Ok(entry) => entry.map(Ok),
+            Err(err) => {
+                // The input is in an unknown state after an error.
+                self.finished = true;
+                self.parser.formatter().handler().entries.clear();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+// Queues everything the record adapter hands out as entries.
+#[derive(Default)]
+struct Queue {
+    entries: VecDeque<Entry>,
+}
+
+impl RecordHandler for Queue {
+    fn record(&mut self, record: KeyRecord) -> RdbOk {
+        self.entries.push_back(Entry::Key(record));
+        Ok(())
+    }
+
+    fn chunk(&mut self, record: KeyRecord, last: bool) -> RdbOk {
+        self.entries.push_back(Entry::Chunk { record, last });
+        Ok(())
+    }
+
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        self.entries.push_back(Entry::AuxField {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn start_database(&mut self, db_index: u64) -> RdbOk {
+        self.entries.push_back(Entry::SelectDb(db_index));
+        Ok(())
+    }
+
+    fn resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbOk {
+        self.entries.push_back(Entry::ResizeDb {
+            db_size,
+            expires_size,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::io::{Cursor, Read, Result as IoResult, Write};
+use std::rc::Rc;
+
+use crate::constants::op_code;
+use crate::dump::{write_blob, write_length};
+use crate::filter::{Filter, KeyInfo};
+use crate::formatter::Nil;
+use crate::parser::{read_blob, read_length, RdbParser};
+use crate::transform::Transform;
+use crate::types::RdbResult;
+use crate::writer::RdbWriter;
+
+/// Writes the databases, types and keys of a dump that match a filter to a
+/// new dump.
+///
+/// Keys are not decoded: the parser skips over them and the bytes it read
+/// are copied to the output unchanged. Databases can be renumbered and keys
+/// that already expired can be left out on the way, and a `Transform`
+/// applied, which only needs the keys it renames decoded.
+pub struct Rewrite<L: Filter> {
+    filter: L,
+    transform: Transform,
+    expired_before: Option<u64>,
+}
+
+impl<L: Filter> Rewrite<L> {
+    pub fn new(filter: L) -> Rewrite<L> {
+        Rewrite {
+            filter,
+            transform: Transform::new(),
+            expired_before: None,
+        }
+    }
+
+    /// Writes the keys of database `from` to database `to`.
+    pub fn renumber_database(&mut self, from: u64, to: u64) {
+        self.transform.map_database(from, to);
+    }
+
+    /// Renames keys and moves databases, replacing any renumbering.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    /// Leaves out keys expiring before `time`, a unix time in milliseconds.
+    pub fn strip_expired(&mut self, time: u64) {
+        self.expired_before = Some(time);
+    }
+
+    pub fn run<R: Read, W: Write>(self, input: R, output: W) -> RdbResult<W> {
+        let consumed = Rc::new(RefCell::new(Vec::new()));
+        let input = Tee {
+            input,
+            consumed: consumed.clone(),
+        };
+        let filter = Selecting {
+            filter: self.filter,
+            matched: Cell::new(false),
+        };
+        let mut parser = RdbParser::new(input, Nil::new(), filter);
+        let mut writer = RdbWriter::new(output);
+
+        parser.parse_header()?;
+        {
+            // The version makes up the last 4 bytes of the header.
+            let header = consumed.borrow();
+            let version = &header[header.len() - 4..];
+            writer.set_version(
+                version
+                    .iter()
+                    .fold(0, |acc, &d| acc * 10 + (d - b'0') as u32),
+            )?;
+        }
+
+        let mut metadata = Vec::new();
+        let mut expiry = None;
+        loop {
+            consumed.borrow_mut().clear();
+            parser.filter().matched.set(false);
+            let more = parser.parse_next()?;
+            let entry = consumed.borrow();
+
+            match entry[0] {
+                op_code::SELECTDB => {
+                    let db = read_length(&mut &entry[1..])?;
+                    if parser.filter().filter.matches_db(db) {
+                        let db = self.transform.db(db);
+                        let mut select = vec![op_code::SELECTDB];
+                        write_length(&mut select, db)?;
+                        writer.write_encoded(&select)?;
+                    }
+                }
+                // Only a hint, and wrong for the filtered dump.
+                op_code::RESIZEDB => {}
+                op_code::AUX | op_code::MODULE_AUX => writer.write_encoded(&entry)?,
+                op_code::EXPIRETIME_MS | op_code::EXPIRETIME | op_code::IDLE | op_code::FREQ => {
+                    let mut value = Cursor::new(&entry[1..]);
+                    match entry[0] {
+                        op_code::EXPIRETIME_MS => {
+                            expiry = Some(value.read_u64::<LittleEndian>()?);
+                        }
+                        op_code::EXPIRETIME => {
+                            expiry = Some(value.read_u32::<LittleEndian>()? as u64 * 1000);
+                        }
+                        _ => {}
+                    }
+                    metadata.extend_from_slice(&entry);
+                }
+                op_code::EOF => {}
+                _ => {
+                    let expired = match (expiry, self.expired_before) {
+                        (Some(expiry), Some(time)) => expiry < time,
+                        _ => false,
+                    };
+                    if parser.filter().matched.get() && !expired {
+                        writer.write_encoded(&metadata)?;
+                        writer.write_encoded(&renamed(&self.transform, &entry)?)?;
+                    }
+                    metadata.clear();
+                    expiry = None;
+                }
+            }
+
+            if !more {
+                break;
+            }
+        }
+
+        writer.finish()
+    }
+}
+
+// An entry of a value type with its key renamed, or as it is.
+fn renamed<'a>(transform: &Transform, entry: &'a [u8]) -> RdbResult<Cow<'a, [u8]>> {
+    if !transform.renames_keys() {
+        return Ok(Cow::Borrowed(entry));
+    }
+    let mut rest = &entry[1..];
+    let key = read_blob(&mut rest)?;
+    Ok(match transform.key(&key) {
+        Cow::Borrowed(_) => Cow::Borrowed(entry),
+        Cow::Owned(key) => {
+            let mut renamed = vec![entry[0]];
+            write_blob(&mut renamed, &key)?;
+            renamed.extend_from_slice(rest);
+            Cow::Owned(renamed)
+        }
+    })
+}
+
+// Keeps a copy of everything read from the input.
+struct Tee<R: Read> {
+    input: R,
+    consumed: Rc<RefCell<Vec<u8>>>,
+}
+
+impl<R: Read> Read for Tee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let n = self.input.read(buf)?;
+        self.consumed.borrow_mut().extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// Remembers whether the last key matched the filter but has the parser skip
+// it either way, as only its raw bytes are needed.
+struct Selecting<L: Filter> {
+    filter: L,
+    matched: Cell<bool>,
+}
+
+impl<L: Filter> Filter for Selecting<L> {
+    fn matches_db(&self, db: u64) -> bool {
+        self.filter.matches_db(db)
+    }
+
+    fn selects_databases(&self) -> bool {
+        self.filter.selects_databases()
+    }
+
+    fn matches_type(&self, enc_type: u8) -> bool {
+        self.filter.matches_type(enc_type)
+    }
+
+    fn matches_expiry(&self, expiry: Option<u64>) -> bool {
+        self.filter.matches_expiry(expiry)
+    }
+
+    fn inspects_values(&self) -> bool {
+        self.filter.inspects_values()
+    }
+
+    fn matches(&self, key: &KeyInfo) -> bool {
+        let matched = self.filter.matches(key);
+        if key.value.is_none() && self.filter.inspects_values() {
+            // Has the parser scan the value to finish the decision.
+            return matched;
+        }
+        self.matched.set(matched);
+        false
+    }
+}
@@ -0,0 +1,143 @@
+//! Connections to the master and the sentinels, with or without TLS.
+
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+/// What a TLS connection trusts and how it authenticates itself.
+#[derive(Clone)]
+pub struct Tls {
+    config: Arc<ClientConfig>,
+}
+
+impl Tls {
+    /// Trusts the certificates in the PEM file `ca_file`, or the usual
+    /// public authorities without one. With `cert_file` and `key_file` the
+    /// client presents that certificate, as a server with
+    /// `tls-auth-clients` wants.
+    pub fn new(
+        ca_file: Option<&Path>,
+        cert_file: Option<&Path>,
+        key_file: Option<&Path>,
+    ) -> io::Result<Tls> {
+        let mut roots = RootCertStore::empty();
+        match ca_file {
+            Some(path) => {
+                for cert in CertificateDer::pem_slice_iter(&fs::read(path)?) {
+                    roots.add(cert.map_err(pem_error)?).map_err(tls_error)?;
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+
+        let builder =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(tls_error)?
+                .with_root_certificates(roots);
+        let config = match (cert_file, key_file) {
+            (Some(cert_file), Some(key_file)) => {
+                let certs = CertificateDer::pem_slice_iter(&fs::read(cert_file)?)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(pem_error)?;
+                let key = PrivateKeyDer::from_pem_slice(&fs::read(key_file)?).map_err(pem_error)?;
+                builder
+                    .with_client_auth_cert(certs, key)
+                    .map_err(tls_error)?
+            }
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "A client certificate needs its key and the other way round",
+                ))
+            }
+        };
+        Ok(Tls {
+            config: Arc::new(config),
+        })
+    }
+}
+
+fn pem_error(e: rustls::pki_types::pem::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+fn tls_error(e: rustls::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+/// A connection to a server, which clones share like those of a
+/// `TcpStream` do.
+#[derive(Clone)]
+pub enum Stream {
+    Tcp(Rc<TcpStream>),
+    Tls(Rc<RefCell<StreamOwned<ClientConnection, TcpStream>>>),
+}
+
+impl Stream {
+    /// Connects to `addr`, given as host:port, over TLS with `tls`. The
+    /// server's certificate has to be for that host.
+    pub fn connect(addr: &str, tls: Option<&Tls>) -> io::Result<Stream> {
+        let tcp = TcpStream::connect(addr)?;
+        let tls = match tls {
+            Some(tls) => tls,
+            None => return Ok(Stream::Tcp(Rc::new(tcp))),
+        };
+
+        let host = match addr.rsplit_once(':') {
+            Some((host, _)) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => addr,
+        };
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+        let conn = ClientConnection::new(tls.config.clone(), name).map_err(tls_error)?;
+        let mut stream = StreamOwned::new(conn, tcp);
+        // Fails here rather than on the first command if the handshake does.
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+        Ok(Stream::Tls(Rc::new(RefCell::new(stream))))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Stream::Tcp(tcp) => tcp.local_addr(),
+            Stream::Tls(tls) => tls.borrow().sock.local_addr(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(tcp) => (&**tcp).read(buf),
+            Stream::Tls(tls) => tls.borrow_mut().read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(tcp) => (&**tcp).write(buf),
+            Stream::Tls(tls) => tls.borrow_mut().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(tcp) => (&**tcp).flush(),
+            Stream::Tls(tls) => tls.borrow_mut().flush(),
+        }
+    }
+}
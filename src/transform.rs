@@ -0,0 +1,307 @@
+//! Renaming keys and moving databases on the way from a dump or the command
+//! stream to the output.
+//!
+//! Filters see the original names, a `Transform` is applied to what they
+//! let through.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use regex::bytes::Regex;
+
+use crate::command::CommandSpec;
+use crate::formatter::{Control, Visitor};
+use crate::types::{EncodingType, KeyMetadata, KeyRecord, RdbResult};
+
+/// Renames keys and databases.
+///
+/// A key first has the prefix to strip removed if it starts with it, then
+/// every replacement applied in turn, then the prefix to add put in front.
+/// Databases without a mapping keep their number.
+#[derive(Clone, Default)]
+pub struct Transform {
+    strip_prefix: Option<Vec<u8>>,
+    replacements: Vec<(Regex, Vec<u8>)>,
+    add_prefix: Vec<u8>,
+    databases: HashMap<u64, u64>,
+}
+
+impl Transform {
+    pub fn new() -> Self {
+        Transform::default()
+    }
+
+    /// Removes `prefix` from keys starting with it, other keys are left as
+    /// they are.
+    pub fn strip_prefix(&mut self, prefix: &[u8]) {
+        self.strip_prefix = Some(prefix.to_vec());
+    }
+
+    /// Replaces the first match of `re` in keys, expanding `$1` or `$name`
+    /// in the replacement like `Regex::replace` does. Can be called multiple
+    /// times.
+    pub fn replace_keys(&mut self, re: Regex, replacement: &str) {
+        self.replacements
+            .push((re, replacement.as_bytes().to_vec()));
+    }
+
+    /// Puts `prefix` in front of every key.
+    pub fn add_prefix(&mut self, prefix: &[u8]) {
+        self.add_prefix = prefix.to_vec();
+    }
+
+    /// Moves the keys of database `from` to database `to`.
+    pub fn map_database(&mut self, from: u64, to: u64) {
+        self.databases.insert(from, to);
+    }
+
+    /// Whether any key can get a new name.
+    pub fn renames_keys(&self) -> bool {
+        self.strip_prefix.is_some() || !self.replacements.is_empty() || !self.add_prefix.is_empty()
+    }
+
+    /// Whether the transform leaves everything as it is.
+    pub fn is_identity(&self) -> bool {
+        !self.renames_keys() && self.databases.iter().all(|(from, to)| from == to)
+    }
+
+    /// The new name of a key, borrowed if it doesn't change.
+    pub fn key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+        let key = match &self.strip_prefix {
+            Some(prefix) => key.strip_prefix(&prefix[..]).unwrap_or(key),
+            None => key,
+        };
+        let mut key = Cow::Borrowed(key);
+        for (re, replacement) in &self.replacements {
+            let replaced = match re.replace(&key, &replacement[..]) {
+                Cow::Owned(replaced) => Some(replaced),
+                Cow::Borrowed(_) => None,
+            };
+            if let Some(replaced) = replaced {
+                key = Cow::Owned(replaced);
+            }
+        }
+        if !self.add_prefix.is_empty() {
+            let mut prefixed = self.add_prefix.clone();
+            prefixed.extend_from_slice(&key);
+            key = Cow::Owned(prefixed);
+        }
+        key
+    }
+
+    /// The new number of a database.
+    pub fn db(&self, db: u64) -> u64 {
+        self.databases.get(&db).copied().unwrap_or(db)
+    }
+
+    /// Renames the key of a record and moves it to its new database.
+    pub fn apply_record(&self, record: &mut KeyRecord) {
+        if let Cow::Owned(key) = self.key(&record.key) {
+            record.key = key;
+        }
+        record.db = self.db(record.db);
+    }
+
+    /// Renames every key argument of a command of the replication stream,
+    /// found from the command key-spec table, and moves the databases
+    /// `SELECT`, `SWAPDB`, `MOVE` and `COPY ... DB` name. Keys a script
+    /// builds itself can't be renamed.
+    pub fn apply_command(&self, mut args: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        let name = match args.first() {
+            Some(name) => name.to_ascii_lowercase(),
+            None => return args,
+        };
+
+        let databases: Vec<usize> = match &name[..] {
+            b"select" => vec![1],
+            b"swapdb" => vec![1, 2],
+            b"move" => vec![2],
+            b"copy" => args
+                .iter()
+                .skip(3)
+                .position(|arg| arg.eq_ignore_ascii_case(b"db"))
+                .map(|i| i + 4)
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        };
+        for i in databases {
+            let db = args
+                .get(i)
+                .and_then(|db| std::str::from_utf8(db).ok())
+                .and_then(|db| db.parse().ok());
+            if let Some(db) = db {
+                args[i] = self.db(db).to_string().into_bytes();
+            }
+        }
+
+        if let Some(spec) = CommandSpec::find(&name) {
+            for i in spec.key_positions(&args) {
+                if let Cow::Owned(key) = self.key(&args[i]) {
+                    args[i] = key;
+                }
+            }
+        }
+        args
+    }
+}
+
+/// Applies a `Transform` to the keys and databases of the callbacks before
+/// passing them on to another visitor.
+pub struct Transforming<F: Visitor> {
+    inner: F,
+    transform: Transform,
+    // The last key renamed and its new name, as the callbacks for the
+    // elements of a value all repeat the key.
+    renamed: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<F: Visitor> Transforming<F> {
+    pub fn new(inner: F, transform: Transform) -> Self {
+        Transforming {
+            inner,
+            transform,
+            renamed: None,
+        }
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+fn rename<'a>(
+    transform: &Transform,
+    renamed: &'a mut Option<(Vec<u8>, Vec<u8>)>,
+    key: &[u8],
+) -> &'a [u8] {
+    match renamed {
+        Some((from, _)) if from == key => {}
+        _ => *renamed = Some((key.to_vec(), transform.key(key).into_owned())),
+    }
+    &renamed.as_ref().unwrap().1
+}
+
+impl<F: Visitor> Visitor for Transforming<F> {
+    fn start_rdb(&mut self) -> RdbResult<Control> {
+        self.inner.start_rdb()
+    }
+    fn end_rdb(&mut self) -> RdbResult<Control> {
+        self.inner.end_rdb()
+    }
+    fn checksum(&mut self, checksum: &[u8]) -> RdbResult<Control> {
+        self.inner.checksum(checksum)
+    }
+
+    fn start_database(&mut self, db_index: u64) -> RdbResult<Control> {
+        self.inner.start_database(self.transform.db(db_index))
+    }
+    fn end_database(&mut self, db_index: u64) -> RdbResult<Control> {
+        self.inner.end_database(self.transform.db(db_index))
+    }
+
+    fn resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbResult<Control> {
+        self.inner.resizedb(db_size, expires_size)
+    }
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbResult<Control> {
+        self.inner.aux_field(key, value)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8], meta: &KeyMetadata) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.set(key, value, meta)
+    }
+
+    fn start_hash(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.start_hash(key, length, meta, info)
+    }
+    fn end_hash(&mut self, key: &[u8]) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.end_hash(key)
+    }
+    fn hash_element(&mut self, key: &[u8], field: &[u8], value: &[u8]) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.hash_element(key, field, value)
+    }
+
+    fn start_set(
+        &mut self,
+        key: &[u8],
+        cardinality: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.start_set(key, cardinality, meta, info)
+    }
+    fn end_set(&mut self, key: &[u8]) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.end_set(key)
+    }
+    fn set_element(&mut self, key: &[u8], member: &[u8]) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.set_element(key, member)
+    }
+
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.start_list(key, length, meta, info)
+    }
+    fn end_list(&mut self, key: &[u8]) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.end_list(key)
+    }
+    fn list_element(&mut self, key: &[u8], value: &[u8]) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.list_element(key, value)
+    }
+
+    fn start_sorted_set(
+        &mut self,
+        key: &[u8],
+        length: u64,
+        meta: &KeyMetadata,
+        info: EncodingType,
+    ) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.start_sorted_set(key, length, meta, info)
+    }
+    fn end_sorted_set(&mut self, key: &[u8]) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.end_sorted_set(key)
+    }
+    fn sorted_set_element(&mut self, key: &[u8], score: f64, member: &[u8]) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.sorted_set_element(key, score, member)
+    }
+
+    fn stream(&mut self, key: &[u8], length: u64, meta: &KeyMetadata) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.stream(key, length, meta)
+    }
+    fn module(&mut self, key: &[u8], name: &str, meta: &KeyMetadata) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.module(key, name, meta)
+    }
+
+    fn wants_payloads(&self) -> bool {
+        self.inner.wants_payloads()
+    }
+    fn payload(&mut self, key: &[u8], payload: &[u8], meta: &KeyMetadata) -> RdbResult<Control> {
+        let key = rename(&self.transform, &mut self.renamed, key);
+        self.inner.payload(key, payload, meta)
+    }
+}
@@ -14,13 +14,24 @@ pub type RdbResult<T> = Result<T, RdbError>;
 
 pub type RdbOk = RdbResult<()>;
 
-#[derive(Debug, PartialEq)]
+/// Metadata Redis stores in front of a key: its absolute expiry in
+/// milliseconds, the LRU idle time in seconds and the LFU counter.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct KeyMetadata {
+    pub expiry: Option<u64>,
+    pub idle: Option<u64>,
+    pub freq: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Type {
     String,
     List,
     Set,
     SortedSet,
     Hash,
+    Stream,
+    Module,
 }
 
 pub enum Module {
@@ -39,14 +50,36 @@ impl Type {
             encoding_type::HASH | encoding_type::HASH_ZIPMAP | encoding_type::HASH_ZIPLIST => {
                 Type::Hash
             }
-            encoding_type::LIST | encoding_type::LIST_ZIPLIST => Type::List,
+            encoding_type::LIST | encoding_type::LIST_ZIPLIST | encoding_type::LIST_QUICKLIST => {
+                Type::List
+            }
             encoding_type::SET | encoding_type::SET_INTSET => Type::Set,
-            encoding_type::ZSET | encoding_type::ZSET_ZIPLIST => Type::SortedSet,
+            encoding_type::ZSET | encoding_type::ZSET2 | encoding_type::ZSET_ZIPLIST => {
+                Type::SortedSet
+            }
+            encoding_type::STEAMLISTPACKS => Type::Stream,
+            encoding_type::MODULE | encoding_type::MODULE2 => Type::Module,
             _ => panic!("Unknown encoding type: {}", enc_type),
         }
     }
+
+    /// Parses the names the command line accepts for a type, which include
+    /// the names Redis' `TYPE` uses.
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "string" => Some(Type::String),
+            "list" => Some(Type::List),
+            "set" => Some(Type::Set),
+            "zset" | "sortedset" | "sorted-set" | "sorted_set" => Some(Type::SortedSet),
+            "hash" => Some(Type::Hash),
+            "stream" => Some(Type::Stream),
+            "module" => Some(Type::Module),
+            _ => None,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EncodingType {
     String,
     LinkedList,
@@ -60,4 +93,102 @@ pub enum EncodingType {
     Ziplist(u64),
     Zipmap(u64),
     Quicklist,
+    StreamListpacks,
+}
+
+impl EncodingType {
+    /// The encoding values of an RDB type are stored with, with the sizes
+    /// left at 0. Strings all have the `String` encoding.
+    pub fn from_enc_type(enc_type: u8) -> Option<EncodingType> {
+        let encoding = match enc_type {
+            encoding_type::STRING => EncodingType::String,
+            encoding_type::LIST => EncodingType::LinkedList,
+            encoding_type::SET | encoding_type::HASH => EncodingType::Hashtable,
+            encoding_type::ZSET => EncodingType::ZSET,
+            encoding_type::ZSET2 => EncodingType::ZSET2,
+            encoding_type::MODULE => EncodingType::Moudle,
+            encoding_type::MODULE2 => EncodingType::Moudle2,
+            encoding_type::HASH_ZIPMAP => EncodingType::Zipmap(0),
+            encoding_type::LIST_ZIPLIST
+            | encoding_type::ZSET_ZIPLIST
+            | encoding_type::HASH_ZIPLIST => EncodingType::Ziplist(0),
+            encoding_type::SET_INTSET => EncodingType::Intset(0),
+            encoding_type::LIST_QUICKLIST => EncodingType::Quicklist,
+            encoding_type::STEAMLISTPACKS => EncodingType::StreamListpacks,
+            _ => return None,
+        };
+        Some(encoding)
+    }
+
+    /// The name Redis' `OBJECT ENCODING` uses for the encoding.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EncodingType::String => "string",
+            EncodingType::LinkedList => "linkedlist",
+            EncodingType::Hashtable => "hashtable",
+            EncodingType::Skiplist | EncodingType::ZSET | EncodingType::ZSET2 => "skiplist",
+            EncodingType::Moudle | EncodingType::Moudle2 => "module",
+            EncodingType::Intset(_) => "intset",
+            EncodingType::Ziplist(_) => "ziplist",
+            EncodingType::Zipmap(_) => "zipmap",
+            EncodingType::Quicklist => "quicklist",
+            EncodingType::StreamListpacks => "listpacks",
+        }
+    }
+}
+
+/// A whole value, as put together from the formatter callbacks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisValue {
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    SortedSet(Vec<(Vec<u8>, f64)>),
+    Hash(Vec<(Vec<u8>, Vec<u8>)>),
+    /// Stream entries are not decoded, only their number is known.
+    Stream {
+        length: u64,
+    },
+    /// Module values are opaque, only the module type name is known.
+    Module {
+        name: String,
+    },
+}
+
+impl RedisValue {
+    /// The number of elements, 1 for values without elements.
+    pub fn len(&self) -> usize {
+        match self {
+            RedisValue::List(values) | RedisValue::Set(values) => values.len(),
+            RedisValue::SortedSet(members) => members.len(),
+            RedisValue::Hash(fields) => fields.len(),
+            _ => 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The name Redis' `TYPE` uses for the value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RedisValue::String(_) => "string",
+            RedisValue::List(_) => "list",
+            RedisValue::Set(_) => "set",
+            RedisValue::SortedSet(_) => "zset",
+            RedisValue::Hash(_) => "hash",
+            RedisValue::Stream { .. } => "stream",
+            RedisValue::Module { .. } => "module",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRecord {
+    pub db: u64,
+    pub key: Vec<u8>,
+    pub meta: KeyMetadata,
+    pub value: RedisValue,
+    pub encoding: EncodingType,
 }
@@ -0,0 +1,170 @@
+//! Checking a dump for corruption without writing anything out.
+
+use std::io::{self, Read};
+
+use crate::crc64::crc64;
+use crate::filter::Simple;
+use crate::formatter::{Control, Visitor};
+use crate::parser::RdbParser;
+use crate::types::{EncodingType, KeyMetadata, RdbResult};
+
+/// What the checksum at the end of a dump said.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// The checksum matches the dump.
+    Valid,
+    /// Redis wrote a zero checksum, with `rdbchecksum no`.
+    Disabled,
+    /// Dumps before version 5 have no checksum.
+    Absent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    pub version: u32,
+    pub databases: u64,
+    pub keys: u64,
+    pub checksum: Checksum,
+}
+
+/// Parses a whole dump, every value decoded, and compares its checksum.
+/// Returns an error for a dump that doesn't parse or whose checksum doesn't
+/// match.
+pub fn verify<R: Read>(input: R) -> RdbResult<Verification> {
+    let mut input = Checksummed {
+        input,
+        header: Vec::new(),
+        crc: 0,
+        tail: Vec::new(),
+    };
+    let mut parser = RdbParser::new(&mut input, Count::default(), Simple::new());
+    parser.parse()?;
+    let count = parser.into_formatter();
+
+    let version = std::str::from_utf8(&input.header[5..])
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(0);
+    let checksum = if version < 5 {
+        Checksum::Absent
+    } else {
+        let mut expected = [0; 8];
+        expected.copy_from_slice(&input.tail);
+        match u64::from_le_bytes(expected) {
+            0 => Checksum::Disabled,
+            expected if expected == input.crc => Checksum::Valid,
+            expected => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Checksum mismatch: the dump says {:016x}, its content is {:016x}",
+                        expected, input.crc
+                    ),
+                ))
+            }
+        }
+    };
+
+    Ok(Verification {
+        version,
+        databases: count.databases,
+        keys: count.keys,
+        checksum,
+    })
+}
+
+// Computes the checksum of everything read but the last 8 bytes, which are
+// the checksum itself once the parser is done.
+struct Checksummed<R: Read> {
+    input: R,
+    header: Vec<u8>,
+    crc: u64,
+    tail: Vec<u8>,
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.input.read(buf)?;
+        let missing = 9 - self.header.len();
+        self.header.extend_from_slice(&buf[..n.min(missing)]);
+        self.tail.extend_from_slice(&buf[..n]);
+        if self.tail.len() > 8 {
+            let done = self.tail.len() - 8;
+            self.crc = crc64(self.crc, &self.tail[..done]);
+            self.tail.drain(..done);
+        }
+        Ok(n)
+    }
+}
+
+#[derive(Default)]
+struct Count {
+    databases: u64,
+    keys: u64,
+}
+
+impl Count {
+    fn key(&mut self) -> RdbResult<Control> {
+        self.keys += 1;
+        Ok(Control::Continue)
+    }
+}
+
+impl Visitor for Count {
+    fn start_database(&mut self, _db_index: u64) -> RdbResult<Control> {
+        self.databases += 1;
+        Ok(Control::Continue)
+    }
+
+    fn set(&mut self, _key: &[u8], _value: &[u8], _meta: &KeyMetadata) -> RdbResult<Control> {
+        self.key()
+    }
+
+    fn start_hash(
+        &mut self,
+        _key: &[u8],
+        _length: u64,
+        _meta: &KeyMetadata,
+        _info: EncodingType,
+    ) -> RdbResult<Control> {
+        self.key()
+    }
+
+    fn start_set(
+        &mut self,
+        _key: &[u8],
+        _cardinality: u64,
+        _meta: &KeyMetadata,
+        _info: EncodingType,
+    ) -> RdbResult<Control> {
+        self.key()
+    }
+
+    fn start_list(
+        &mut self,
+        _key: &[u8],
+        _length: u64,
+        _meta: &KeyMetadata,
+        _info: EncodingType,
+    ) -> RdbResult<Control> {
+        self.key()
+    }
+
+    fn start_sorted_set(
+        &mut self,
+        _key: &[u8],
+        _length: u64,
+        _meta: &KeyMetadata,
+        _info: EncodingType,
+    ) -> RdbResult<Control> {
+        self.key()
+    }
+
+    fn stream(&mut self, _key: &[u8], _length: u64, _meta: &KeyMetadata) -> RdbResult<Control> {
+        self.key()
+    }
+
+    fn module(&mut self, _key: &[u8], _name: &str, _meta: &KeyMetadata) -> RdbResult<Control> {
+        self.key()
+    }
+}
@@ -0,0 +1,564 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::Write;
+
+use crate::constants::{constant, encoding, encoding_type, op_code, version};
+use crate::crc64::crc64;
+use crate::dump::{write_blob, write_length};
+use crate::formatter::RecordHandler;
+use crate::helper::{as_integer, other_error};
+use crate::types::{KeyMetadata, KeyRecord, RdbOk, RdbResult, RedisValue};
+
+/// How collections are laid out in the written dump.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// Every element as a separate string, what Redis uses for big values.
+    Plain,
+    /// Ziplists, quicklists and intsets for values small enough, with the
+    /// limits of a default Redis configuration. Hashes are zipmaps before
+    /// version 6.
+    Compact,
+}
+
+// Redis' default hash-max-ziplist-entries, hash-max-ziplist-value and
+// set-max-intset-entries, also used for lists and sorted sets.
+const ZIPLIST_MAX_ENTRIES: usize = 128;
+const ZIPLIST_MAX_VALUE: usize = 64;
+const INTSET_MAX_ENTRIES: usize = 512;
+
+// Redis doesn't try to compress strings this short.
+const LZF_MIN_LENGTH: usize = 20;
+
+/// Writes a dump Redis can load.
+///
+/// Keys are given as `KeyRecord`s, either directly or by wrapping the
+/// writer in a `Records` adapter and using it as a formatter. The header is
+/// written together with the first entry, `finish` completes the dump.
+/// Streams and module values can't be written as their contents are never
+/// read, they are left out.
+pub struct RdbWriter<W: Write> {
+    out: W,
+    version: u32,
+    encoding: Encoding,
+    compress: bool,
+    crc: u64,
+    started: bool,
+    db: Option<u64>,
+    chunks: Option<KeyRecord>,
+}
+
+// Versions before 6 only know the 16, 32 and 64 bit integer entries, the
+// 8 and 24 bit ones and the integers held in the header came with it.
+fn ziplist<'a, I>(entries: I, version: u32) -> Vec<u8>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut body = Vec::new();
+    let mut count = 0;
+    let mut prev_len = 0;
+    let mut tail = 0;
+
+    for entry in entries {
+        let start = body.len();
+        tail = start;
+
+        if prev_len < 254 {
+            body.push(prev_len as u8);
+        } else {
+            body.push(254);
+            body.write_u32::<LittleEndian>(prev_len as u32).unwrap();
+        }
+
+        let small_integers = version >= 6;
+        match as_integer(entry) {
+            Some(number @ 0..=12) if small_integers => body.push(0xF1 + number as u8),
+            Some(number) if small_integers && number as i8 as i64 == number => {
+                body.push(0xFE);
+                body.push(number as u8);
+            }
+            Some(number) if number as i16 as i64 == number => {
+                body.push(0xC0);
+                body.write_i16::<LittleEndian>(number as i16).unwrap();
+            }
+            Some(number) if small_integers && (-(1 << 23)..1 << 23).contains(&number) => {
+                body.push(0xF0);
+                body.extend_from_slice(&(number as i32).to_le_bytes()[..3]);
+            }
+            Some(number) if number as i32 as i64 == number => {
+                body.push(0xD0);
+                body.write_i32::<LittleEndian>(number as i32).unwrap();
+            }
+            Some(number) => {
+                body.push(0xE0);
+                body.write_i64::<LittleEndian>(number).unwrap();
+            }
+            None => {
+                let len = entry.len();
+                if len < 1 << 6 {
+                    body.push(len as u8);
+                } else if len < 1 << 14 {
+                    body.push(0x40 | (len >> 8) as u8);
+                    body.push(len as u8);
+                } else {
+                    body.push(0x80);
+                    body.extend_from_slice(&(len as u32).to_be_bytes());
+                }
+                body.extend_from_slice(entry);
+            }
+        }
+
+        prev_len = body.len() - start;
+        count += 1;
+    }
+
+    // zlbytes, zltail and zllen come first, the end marker last.
+    let header_len = 4 + 4 + 2;
+    let mut ziplist = Vec::with_capacity(header_len + body.len() + 1);
+    ziplist
+        .write_u32::<LittleEndian>((header_len + body.len() + 1) as u32)
+        .unwrap();
+    ziplist
+        .write_u32::<LittleEndian>((header_len + tail) as u32)
+        .unwrap();
+    ziplist
+        .write_u16::<LittleEndian>(count.min(u16::MAX as usize) as u16)
+        .unwrap();
+    ziplist.extend_from_slice(&body);
+    ziplist.push(0xFF);
+    ziplist
+}
+
+// Fields and values are at most ZIPLIST_MAX_VALUE bytes long, so their
+// lengths always fit in a single byte.
+fn zipmap(fields: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+    let mut zipmap = vec![fields.len().min(254) as u8];
+    for (field, value) in fields {
+        zipmap.push(field.len() as u8);
+        zipmap.extend_from_slice(field);
+        // The value's length is followed by the unused bytes after it.
+        zipmap.push(value.len() as u8);
+        zipmap.push(0);
+        zipmap.extend_from_slice(value);
+    }
+    zipmap.push(0xFF);
+    zipmap
+}
+
+fn intset(members: &[i64]) -> Vec<u8> {
+    let mut members = members.to_vec();
+    members.sort_unstable();
+    members.dedup();
+
+    let size = if members.iter().all(|&m| m as i16 as i64 == m) {
+        2
+    } else if members.iter().all(|&m| m as i32 as i64 == m) {
+        4
+    } else {
+        8
+    };
+
+    let mut intset = Vec::with_capacity(8 + members.len() * size);
+    intset.write_u32::<LittleEndian>(size as u32).unwrap();
+    intset
+        .write_u32::<LittleEndian>(members.len() as u32)
+        .unwrap();
+    for member in members {
+        match size {
+            2 => intset.write_i16::<LittleEndian>(member as i16),
+            4 => intset.write_i32::<LittleEndian>(member as i32),
+            _ => intset.write_i64::<LittleEndian>(member),
+        }
+        .unwrap();
+    }
+    intset
+}
+
+fn fits_ziplist<'a, I>(len: usize, elements: I) -> bool
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    len <= ZIPLIST_MAX_ENTRIES
+        && elements
+            .into_iter()
+            .all(|element| element.len() <= ZIPLIST_MAX_VALUE)
+}
+
+// Scores are written like Redis' %.17g: the shortest digits reading back
+// as the same score, with an exponent once it is below -4 or above 16.
+fn score_to_string(score: f64) -> String {
+    if score.is_infinite() {
+        return if score > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let scientific = format!("{:e}", score);
+    let exponent = scientific
+        .split('e')
+        .nth(1)
+        .and_then(|exponent| exponent.parse::<i32>().ok());
+    match exponent {
+        Some(exponent) if !(-4..17).contains(&exponent) => scientific,
+        _ => score.to_string(),
+    }
+}
+
+impl<W: Write> RdbWriter<W> {
+    pub fn new(out: W) -> RdbWriter<W> {
+        RdbWriter {
+            out,
+            version: version::SUPPORTED_MAXIMUM,
+            encoding: Encoding::Compact,
+            compress: true,
+            crc: 0,
+            started: false,
+            db: None,
+            chunks: None,
+        }
+    }
+
+    pub fn set_version(&mut self, version: u32) -> RdbOk {
+        if !(version::SUPPORTED_MINIMUM..=version::SUPPORTED_MAXIMUM).contains(&version) {
+            return Err(other_error("Version not supported"));
+        }
+        self.version = version;
+        Ok(())
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Enables or disables LZF compression of strings, enabled by default.
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    pub fn write_aux(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        self.start()?;
+        // Aux fields were added in version 7, older dumps can't hold them.
+        if self.version >= 7 {
+            self.write_u8(op_code::AUX)?;
+            self.write_string(key)?;
+            self.write_string(value)?;
+        }
+        Ok(())
+    }
+
+    pub fn select_db(&mut self, db: u64) -> RdbOk {
+        self.start()?;
+        if self.db != Some(db) {
+            self.write_u8(op_code::SELECTDB)?;
+            self.write_length(db)?;
+            self.db = Some(db);
+        }
+        Ok(())
+    }
+
+    pub fn write_resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbOk {
+        self.start()?;
+        if self.version >= 7 {
+            self.write_u8(op_code::RESIZEDB)?;
+            self.write_length(db_size)?;
+            self.write_length(expires_size)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_record(&mut self, record: &KeyRecord) -> RdbOk {
+        if let RedisValue::Stream { .. } | RedisValue::Module { .. } = record.value {
+            return Ok(());
+        }
+
+        self.select_db(record.db)?;
+        self.write_metadata(&record.meta)?;
+
+        match &record.value {
+            RedisValue::String(value) => {
+                self.write_u8(encoding_type::STRING)?;
+                self.write_string(&record.key)?;
+                self.write_string(value)
+            }
+            RedisValue::List(values) => self.write_list(&record.key, values),
+            RedisValue::Set(members) => self.write_set(&record.key, members),
+            RedisValue::SortedSet(members) => self.write_sorted_set(&record.key, members),
+            RedisValue::Hash(fields) => self.write_hash(&record.key, fields),
+            RedisValue::Stream { .. } | RedisValue::Module { .. } => unreachable!(),
+        }
+    }
+
+    /// Copies an already encoded entry, opcode included, to the dump.
+    pub fn write_encoded(&mut self, entry: &[u8]) -> RdbOk {
+        self.start()?;
+        self.write_raw(entry)
+    }
+
+    /// Ends the dump and returns the output.
+    pub fn finish(mut self) -> RdbResult<W> {
+        self.start()?;
+        self.write_u8(op_code::EOF)?;
+        // The checksum was introduced in version 5.
+        if self.version >= 5 {
+            let crc = self.crc;
+            self.out.write_u64::<LittleEndian>(crc)?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn start(&mut self) -> RdbOk {
+        if !self.started {
+            self.started = true;
+            let header = format!("{}{:04}", constant::RDB_MAGIC, self.version);
+            self.write_raw(header.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> RdbOk {
+        self.crc = crc64(self.crc, data);
+        self.out.write_all(data)
+    }
+
+    fn write_u8(&mut self, byte: u8) -> RdbOk {
+        self.write_raw(&[byte])
+    }
+
+    fn write_length(&mut self, length: u64) -> RdbOk {
+        let mut buf = Vec::with_capacity(9);
+        write_length(&mut buf, length)?;
+        self.write_raw(&buf)
+    }
+
+    fn write_string(&mut self, value: &[u8]) -> RdbOk {
+        let mut buf = Vec::new();
+        let enc_val = constant::RDB_ENCVAL << 6;
+
+        match as_integer(value) {
+            Some(number) if number as i8 as i64 == number => {
+                buf.push(enc_val | encoding::INT8 as u8);
+                buf.write_i8(number as i8)?;
+            }
+            Some(number) if number as i16 as i64 == number => {
+                buf.push(enc_val | encoding::INT16 as u8);
+                buf.write_i16::<LittleEndian>(number as i16)?;
+            }
+            Some(number) if number as i32 as i64 == number => {
+                buf.push(enc_val | encoding::INT32 as u8);
+                buf.write_i32::<LittleEndian>(number as i32)?;
+            }
+            _ => match self.compressed(value) {
+                Some(compressed) => {
+                    buf.push(enc_val | encoding::LZF as u8);
+                    write_length(&mut buf, compressed.len() as u64)?;
+                    write_length(&mut buf, value.len() as u64)?;
+                    buf.extend_from_slice(&compressed);
+                }
+                None => write_blob(&mut buf, value)?,
+            },
+        }
+
+        self.write_raw(&buf)
+    }
+
+    fn compressed(&self, value: &[u8]) -> Option<Vec<u8>> {
+        if !self.compress || value.len() <= LZF_MIN_LENGTH {
+            return None;
+        }
+        // Fails if the data doesn't get any smaller.
+        lzf::compress(value).ok()
+    }
+
+    fn write_metadata(&mut self, meta: &KeyMetadata) -> RdbOk {
+        if let Some(expiry) = meta.expiry {
+            if self.version >= 3 {
+                self.write_u8(op_code::EXPIRETIME_MS)?;
+                self.write_raw(&expiry.to_le_bytes())?;
+            } else {
+                self.write_u8(op_code::EXPIRETIME)?;
+                self.write_raw(&((expiry / 1000) as u32).to_le_bytes())?;
+            }
+        }
+
+        // LRU and LFU information came with version 9.
+        if self.version >= 9 {
+            if let Some(idle) = meta.idle {
+                self.write_u8(op_code::IDLE)?;
+                self.write_length(idle)?;
+            }
+            if let Some(freq) = meta.freq {
+                self.write_u8(op_code::FREQ)?;
+                self.write_u8(freq)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_list(&mut self, key: &[u8], values: &[Vec<u8>]) -> RdbOk {
+        let elements = values.iter().map(Vec::as_slice);
+        let compact = self.compact() && fits_ziplist(values.len(), elements);
+
+        if !compact {
+            self.write_u8(encoding_type::LIST)?;
+            self.write_string(key)?;
+            self.write_length(values.len() as u64)?;
+            return values.iter().try_for_each(|value| self.write_string(value));
+        }
+
+        let ziplist = ziplist(values.iter().map(Vec::as_slice), self.version);
+        if self.version >= 7 {
+            // A quicklist with a single node.
+            self.write_u8(encoding_type::LIST_QUICKLIST)?;
+            self.write_string(key)?;
+            self.write_length(1)?;
+        } else {
+            self.write_u8(encoding_type::LIST_ZIPLIST)?;
+            self.write_string(key)?;
+        }
+        self.write_string(&ziplist)
+    }
+
+    fn write_set(&mut self, key: &[u8], members: &[Vec<u8>]) -> RdbOk {
+        let integers: Option<Vec<i64>> = members.iter().map(|m| as_integer(m)).collect();
+
+        match integers {
+            Some(integers) if self.compact() && integers.len() <= INTSET_MAX_ENTRIES => {
+                self.write_u8(encoding_type::SET_INTSET)?;
+                self.write_string(key)?;
+                self.write_string(&intset(&integers))
+            }
+            _ => {
+                self.write_u8(encoding_type::SET)?;
+                self.write_string(key)?;
+                self.write_length(members.len() as u64)?;
+                members
+                    .iter()
+                    .try_for_each(|member| self.write_string(member))
+            }
+        }
+    }
+
+    fn write_sorted_set(&mut self, key: &[u8], members: &[(Vec<u8>, f64)]) -> RdbOk {
+        let elements = members.iter().map(|(member, _)| member.as_slice());
+        let compact = self.compact() && fits_ziplist(members.len(), elements);
+
+        if compact {
+            let scores: Vec<Vec<u8>> = members
+                .iter()
+                .map(|(_, score)| score_to_string(*score).into_bytes())
+                .collect();
+            let entries = members
+                .iter()
+                .zip(&scores)
+                .flat_map(|((member, _), score)| vec![member.as_slice(), score.as_slice()]);
+
+            self.write_u8(encoding_type::ZSET_ZIPLIST)?;
+            self.write_string(key)?;
+            return self.write_string(&ziplist(entries, self.version));
+        }
+
+        // Binary scores are supported since version 8.
+        let binary = self.version >= 8;
+        self.write_u8(if binary {
+            encoding_type::ZSET2
+        } else {
+            encoding_type::ZSET
+        })?;
+        self.write_string(key)?;
+        self.write_length(members.len() as u64)?;
+
+        for (member, score) in members {
+            self.write_string(member)?;
+            if binary {
+                self.write_raw(&score.to_le_bytes())?;
+            } else if score.is_nan() {
+                self.write_u8(253)?;
+            } else if score.is_infinite() {
+                self.write_u8(if *score > 0.0 { 254 } else { 255 })?;
+            } else {
+                // The lengths above 252 mark NaN and the infinities.
+                let score = score_to_string(*score);
+                if score.len() > 252 {
+                    return Err(other_error("Sorted set score too long to write"));
+                }
+                self.write_u8(score.len() as u8)?;
+                self.write_raw(score.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_hash(&mut self, key: &[u8], fields: &[(Vec<u8>, Vec<u8>)]) -> RdbOk {
+        let entries = fields
+            .iter()
+            .flat_map(|(field, value)| vec![field.as_slice(), value.as_slice()]);
+        let compact = self.compact() && fits_ziplist(fields.len(), entries);
+
+        // Ziplist hashes came with version 6, zipmaps were used before.
+        if compact && self.version >= 6 {
+            let entries = fields
+                .iter()
+                .flat_map(|(field, value)| vec![field.as_slice(), value.as_slice()]);
+            self.write_u8(encoding_type::HASH_ZIPLIST)?;
+            self.write_string(key)?;
+            return self.write_string(&ziplist(entries, self.version));
+        }
+        if compact {
+            self.write_u8(encoding_type::HASH_ZIPMAP)?;
+            self.write_string(key)?;
+            return self.write_string(&zipmap(fields));
+        }
+
+        self.write_u8(encoding_type::HASH)?;
+        self.write_string(key)?;
+        self.write_length(fields.len() as u64)?;
+        for (field, value) in fields {
+            self.write_string(field)?;
+            self.write_string(value)?;
+        }
+        Ok(())
+    }
+
+    // Ziplists, zipmaps and intsets appeared with version 2.
+    fn compact(&self) -> bool {
+        self.encoding == Encoding::Compact && self.version >= 2
+    }
+}
+
+impl<W: Write> RecordHandler for RdbWriter<W> {
+    fn record(&mut self, record: KeyRecord) -> RdbOk {
+        self.write_record(&record)
+    }
+
+    // A key has to be written in one go, so chunks are put back together.
+    fn chunk(&mut self, record: KeyRecord, last: bool) -> RdbOk {
+        let record = match self.chunks.take() {
+            None => record,
+            Some(mut pending) => {
+                match (&mut pending.value, record.value) {
+                    (RedisValue::List(a), RedisValue::List(b))
+                    | (RedisValue::Set(a), RedisValue::Set(b)) => a.extend(b),
+                    (RedisValue::SortedSet(a), RedisValue::SortedSet(b)) => a.extend(b),
+                    (RedisValue::Hash(a), RedisValue::Hash(b)) => a.extend(b),
+                    _ => {}
+                }
+                pending
+            }
+        };
+
+        if last {
+            self.record(record)
+        } else {
+            self.chunks = Some(record);
+            Ok(())
+        }
+    }
+
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) -> RdbOk {
+        self.write_aux(key, value)
+    }
+
+    fn start_database(&mut self, db_index: u64) -> RdbOk {
+        self.select_db(db_index)
+    }
+
+    fn resizedb(&mut self, db_size: u64, expires_size: u64) -> RdbOk {
+        self.write_resizedb(db_size, expires_size)
+    }
+}
@@ -0,0 +1,136 @@
+extern crate redis_canal_rs as rdb;
+mod common;
+use common::args;
+use rdb::aof::AofParser;
+use rdb::command::Command;
+use rdb::filter::Simple;
+use rdb::formatter::{Control, Nil, Visitor};
+use rdb::RdbResult;
+use std::fs;
+use std::io::Cursor;
+
+fn resp(command: &str) -> Vec<u8> {
+    let args: Vec<&str> = command.split(' ').collect();
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend(format!("${}\r\n{}\r\n", arg.len(), arg).bytes());
+    }
+    out
+}
+
+// The file holding the commands and the offset of each.
+fn aof(commands: &[&str]) -> (Vec<u8>, Vec<u64>) {
+    let mut data = Vec::new();
+    let mut offsets = Vec::new();
+    for command in commands {
+        offsets.push(data.len() as u64);
+        data.extend(resp(command));
+    }
+    (data, offsets)
+}
+
+fn parse(data: &[u8]) -> (RdbResult<Vec<Command>>, Option<u64>) {
+    let mut parser = AofParser::new(Cursor::new(data), Nil::new(), Simple::new());
+    let commands = (&mut parser).collect();
+    (commands, parser.truncated())
+}
+
+#[test]
+fn test_commands() {
+    let commands = ["SELECT 0", "SET a 1", "SELECT 2", "DEL a b", "SET b 2"];
+    let (data, offsets) = aof(&commands);
+    let (parsed, truncated) = parse(&data);
+    assert_eq!(truncated, None);
+    let expected: Vec<Command> = commands
+        .iter()
+        .zip(&offsets)
+        .zip(&[0, 0, 2, 2, 2])
+        .map(|((command, &offset), &db)| Command {
+            offset,
+            db,
+            args: args(command),
+        })
+        .collect();
+    assert_eq!(parsed.unwrap(), expected);
+
+    // Filtered like the replication stream.
+    let mut filter = Simple::new();
+    filter.add_database(0);
+    let mut parser = AofParser::new(Cursor::new(&data), Nil::new(), filter);
+    parser.filter_mut().deny_command("del");
+    let parsed: Vec<Vec<Vec<u8>>> = parser.map(|command| command.unwrap().args).collect();
+    assert_eq!(
+        parsed,
+        vec![args("SELECT 0"), args("SET a 1"), args("SELECT 2")]
+    );
+}
+
+// Records the end of the dump and its checksum.
+#[derive(Default)]
+struct Preamble {
+    ended: bool,
+    checksum: Vec<u8>,
+}
+
+impl Visitor for Preamble {
+    fn end_rdb(&mut self) -> RdbResult<Control> {
+        self.ended = true;
+        Ok(Control::Continue)
+    }
+
+    fn checksum(&mut self, checksum: &[u8]) -> RdbResult<Control> {
+        self.checksum = checksum.to_vec();
+        Ok(Control::Continue)
+    }
+}
+
+#[test]
+fn test_preamble() {
+    let mut data = fs::read("tests/dumps/rdb_version_8_with_64b_length_and_scores.rdb").unwrap();
+    let preamble = data.len() as u64;
+    let (commands, offsets) = aof(&["SET a 1", "INCR a"]);
+    data.extend(commands);
+
+    let mut parser = AofParser::new(Cursor::new(&data), Preamble::default(), Simple::new());
+    let parsed: Vec<Command> = (&mut parser).map(Result::unwrap).collect();
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0].offset, preamble + offsets[0]);
+    assert_eq!(parsed[1].args, args("INCR a"));
+
+    let visitor = parser.into_visitor();
+    assert!(visitor.ended);
+    assert_eq!(
+        visitor.checksum,
+        &data[preamble as usize - 8..preamble as usize]
+    );
+}
+
+#[test]
+fn test_truncated() {
+    let (data, offsets) = aof(&["SET a 1", "SET b 2", "SET c 3"]);
+    let last = offsets[2];
+    for end in last + 1..data.len() as u64 {
+        let (parsed, truncated) = parse(&data[..end as usize]);
+        assert_eq!(parsed.unwrap().len(), 2, "cut at {}", end);
+        assert_eq!(truncated, Some(last), "cut at {}", end);
+    }
+
+    // A transaction is only applied whole.
+    let (data, offsets) = aof(&["SET a 1", "MULTI", "SET b 2", "EXEC", "MULTI", "SET c 3"]);
+    let (parsed, truncated) = parse(&data);
+    let names: Vec<Vec<u8>> = parsed
+        .unwrap()
+        .into_iter()
+        .map(|command| command.args[0].clone())
+        .collect();
+    assert_eq!(names, args("SET MULTI SET EXEC"));
+    assert_eq!(truncated, Some(offsets[4]));
+
+    // Anything but the end of the file missing is an error.
+    let mut data = resp("SET a 1");
+    let garbage = data.len();
+    data.extend(b"+OK\r\n");
+    data.extend(resp("SET b 2"));
+    let error = parse(&data).0.unwrap_err().to_string();
+    assert!(error.ends_with(&format!("offset {}", garbage)), "{}", error);
+}
@@ -0,0 +1,142 @@
+extern crate redis_canal_rs as rdb;
+mod common;
+use common::args;
+use rdb::command::{read_command, CommandSpec};
+use rdb::filter::{parse_expr, CommandFilter, Filter, Simple};
+use rdb::reader::{Entry, RdbReader};
+use rdb::RedisValue;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+fn positions(command: &str) -> Vec<usize> {
+    let args = args(command);
+    CommandSpec::find(&args[0]).unwrap().key_positions(&args)
+}
+
+fn apply<L: Filter>(filter: &mut CommandFilter<L>, command: &str) -> Option<String> {
+    filter.apply(args(command)).map(|args| {
+        args.iter()
+            .map(|arg| String::from_utf8_lossy(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    })
+}
+
+#[test]
+fn test_key_positions() {
+    let specs = CommandSpec::all();
+    assert!(specs.windows(2).all(|pair| pair[0].name < pair[1].name));
+
+    assert_eq!(positions("SET a 1"), [1]);
+    assert_eq!(positions("mset a 1 b 2 c 3"), [1, 3, 5]);
+    assert_eq!(positions("DEL a b c"), [1, 2, 3]);
+    assert_eq!(positions("RENAME a b"), [1, 2]);
+    assert_eq!(positions("SUNIONSTORE d a b"), [1, 2, 3]);
+    assert_eq!(positions("BITOP AND d a b"), [2, 3, 4]);
+    assert_eq!(positions("BLPOP a b 0"), [1, 2]);
+    assert_eq!(positions("ZUNIONSTORE d 2 a b WEIGHTS 1 2"), [1, 3, 4]);
+    assert_eq!(positions("EVAL script 1 a arg"), [3]);
+    assert_eq!(positions("LMPOP 2 a b LEFT"), [2, 3]);
+    assert_eq!(positions("SORT a BY w STORE d"), [1, 5]);
+    assert_eq!(positions("XGROUP CREATE s g $"), [2]);
+    assert_eq!(positions("XGROUP HELP"), Vec::<usize>::new());
+    assert!(CommandSpec::find(b"PING").is_none());
+}
+
+#[test]
+fn test_read_command() {
+    let stream = b"*1\r\n$4\r\nPING\r\n*3\r\n$3\r\nSET\r\n$1\r\na\r\n$3\r\n\r\n1\r\n";
+    let mut input = Cursor::new(&stream[..]);
+    assert_eq!(read_command(&mut input).unwrap(), (args("PING"), 14));
+    let (args, size) = read_command(&mut input).unwrap();
+    assert_eq!(args, [&b"SET"[..], b"a", b"\r\n1"]);
+    assert_eq!(size, stream.len() - 14);
+    assert!(read_command(&mut input).is_err());
+}
+
+#[test]
+fn test_command_filter() {
+    let mut filter = CommandFilter::new(parse_expr("db = 0 and key prefix user:").unwrap());
+    assert_eq!(
+        apply(&mut filter, "SET user:1 a").as_deref(),
+        Some("SET user:1 a")
+    );
+    assert_eq!(apply(&mut filter, "SET item:1 a"), None);
+    assert_eq!(
+        apply(&mut filter, "DEL user:1 item:1 user:2").as_deref(),
+        Some("DEL user:1 user:2")
+    );
+    assert_eq!(
+        apply(&mut filter, "MSET item:1 a user:1 b").as_deref(),
+        Some("MSET user:1 b")
+    );
+    assert_eq!(
+        apply(&mut filter, "RENAME user:1 item:1").as_deref(),
+        Some("RENAME user:1 item:1")
+    );
+    assert_eq!(apply(&mut filter, "FLUSHDB").as_deref(), Some("FLUSHDB"));
+    assert_eq!(apply(&mut filter, "FLUSHALL"), None);
+
+    assert_eq!(apply(&mut filter, "SELECT 1").as_deref(), Some("SELECT 1"));
+    assert_eq!(filter.db(), 1);
+    assert_eq!(apply(&mut filter, "SET user:1 a"), None);
+    assert_eq!(apply(&mut filter, "FLUSHDB"), None);
+    assert_eq!(apply(&mut filter, "PING").as_deref(), Some("PING"));
+
+    // DEL doesn't say which type its keys have, so can't be dropped.
+    let mut filter = CommandFilter::new(parse_expr("not type = hash").unwrap());
+    assert_eq!(apply(&mut filter, "HSET h f v"), None);
+    assert_eq!(apply(&mut filter, "SET s v").as_deref(), Some("SET s v"));
+    assert_eq!(apply(&mut filter, "DEL h").as_deref(), Some("DEL h"));
+    assert_eq!(apply(&mut filter, "FLUSHALL").as_deref(), Some("FLUSHALL"));
+
+    let mut filter = CommandFilter::new(parse_expr("not db = 3").unwrap());
+    assert_eq!(apply(&mut filter, "FLUSHALL"), None);
+
+    let mut filter = CommandFilter::new(Simple::new());
+    filter.allow_command("set");
+    filter.allow_command("DEL");
+    filter.deny_command("del");
+    assert!(apply(&mut filter, "set a 1").is_some());
+    assert!(apply(&mut filter, "DEL a").is_none());
+    assert!(apply(&mut filter, "LPUSH a 1").is_none());
+    assert!(apply(&mut filter, "SELECT 2").is_some());
+}
+
+#[test]
+fn test_same_keys_as_dump() {
+    let expr = r#"type in (hash, list) or key glob "s*""#;
+    let read = |filter| -> Vec<(Vec<u8>, RedisValue)> {
+        let file = File::open("tests/dumps/parser_filters.rdb").unwrap();
+        RdbReader::with_filter(BufReader::new(file), filter)
+            .filter_map(|entry| match entry.unwrap() {
+                Entry::Key(record) => Some((record.key, record.value)),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let mut filter = CommandFilter::new(parse_expr(expr).unwrap());
+    let replayed: Vec<Vec<u8>> = read(parse_expr("db = 0").unwrap())
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let name = match value {
+                RedisValue::String(_) => "SET",
+                RedisValue::List(_) => "RPUSH",
+                RedisValue::Set(_) => "SADD",
+                RedisValue::SortedSet(_) => "ZADD",
+                RedisValue::Hash(_) => "HSET",
+                _ => return None,
+            };
+            let args = vec![name.as_bytes().to_vec(), key, b"x".to_vec()];
+            filter.apply(args).map(|args| args[1].clone())
+        })
+        .collect();
+
+    let dumped: Vec<Vec<u8>> = read(parse_expr(expr).unwrap())
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    assert!(!dumped.is_empty());
+    assert_eq!(replayed, dumped);
+}
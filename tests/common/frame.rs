@@ -0,0 +1,22 @@
+//! Minimal dumps holding a single value, shared with the fuzz targets.
+
+use rdb::constants::op_code;
+
+/// Wraps a single value in a minimal RDB file, so a decoder can be driven
+/// through the public parser. `value` is written as is after the key.
+pub fn frame_value(enc_type: u8, value: &[u8]) -> Vec<u8> {
+    let mut rdb = b"REDIS0009".to_vec();
+    rdb.extend_from_slice(&[op_code::SELECTDB, 0, enc_type, 1, b'k']);
+    rdb.extend_from_slice(value);
+    rdb.push(op_code::EOF);
+    rdb
+}
+
+/// Like `frame_value`, but stores `value` as a plain string blob first, the
+/// way ziplists, zipmaps and intsets are embedded in a dump.
+pub fn frame_blob(enc_type: u8, value: &[u8]) -> Vec<u8> {
+    let mut blob = vec![0x80];
+    blob.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    blob.extend_from_slice(value);
+    frame_value(enc_type, &blob)
+}
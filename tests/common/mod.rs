@@ -0,0 +1,39 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+pub mod frame;
+
+use rdb::reader::{Entry, RdbReader};
+use rdb::KeyRecord;
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+/// The dumps in tests/dumps, sorted by name.
+pub fn dumps() -> Vec<PathBuf> {
+    let mut dumps: Vec<PathBuf> = fs::read_dir("tests/dumps")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rdb"))
+        .collect();
+    dumps.sort();
+    dumps
+}
+
+/// The arguments of a command given with spaces between them.
+pub fn args(command: &str) -> Vec<Vec<u8>> {
+    command
+        .split(' ')
+        .map(|arg| arg.as_bytes().to_vec())
+        .collect()
+}
+
+/// The keys of a dump, in the order they were written.
+pub fn keys(data: &[u8]) -> Vec<KeyRecord> {
+    RdbReader::new(Cursor::new(data))
+        .filter_map(|entry| match entry.unwrap() {
+            Entry::Key(record) => Some(record),
+            _ => None,
+        })
+        .collect()
+}
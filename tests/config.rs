@@ -0,0 +1,265 @@
+extern crate redis_canal_rs as rdb;
+use rdb::config::{Config, Reload, SinkType};
+use rdb::OutputFormat;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use std::env;
+use std::fs;
+use std::io::{BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Writes a config file of its own for each test, as they run in parallel.
+fn write_config(name: &str, text: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("redis-canal-rs-{}-{}", std::process::id(), name));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn load_error(name: &str, text: &str) -> String {
+    let path = write_config(name, text);
+    let error = Config::load(&path).unwrap_err().to_string();
+    fs::remove_file(&path).unwrap();
+    error
+}
+
+const TOML: &str = r#"
+[source]
+address = "localhost:6379"
+password = "secret"
+
+[filter]
+databases = [0]
+globs = ["user:*"]
+deny_commands = ["flushall"]
+
+[transform]
+add_prefix = "copy:"
+renumber = [{ from = 0, to = 3 }]
+
+[sink]
+type = "redis"
+address = "localhost:6380"
+batch = 100
+
+[checkpoint]
+path = "sync.checkpoint"
+
+[retry]
+attempts = 5
+delay_ms = 100
+max_delay_ms = 1000
+"#;
+
+const YAML: &str = r#"
+source:
+  address: localhost:6379
+  password: secret
+filter:
+  databases: [0]
+  globs: ["user:*"]
+  deny_commands: [flushall]
+transform:
+  add_prefix: "copy:"
+  renumber:
+    - from: 0
+      to: 3
+sink:
+  type: redis
+  address: localhost:6380
+  batch: 100
+checkpoint:
+  path: sync.checkpoint
+retry:
+  attempts: 5
+  delay_ms: 100
+  max_delay_ms: 1000
+"#;
+
+#[test]
+fn test_load() {
+    for (name, text) in [("load.toml", TOML), ("load.yaml", YAML)].iter() {
+        let path = write_config(name, text);
+        let config = Config::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.source.resolve().unwrap(), "localhost:6379");
+        assert_eq!(config.source.password.as_deref(), Some("secret"));
+        assert_eq!(config.sink.kind, SinkType::Redis);
+        assert_eq!(config.sink.format(), OutputFormat::Protocol);
+        assert_eq!(config.sink.batch, Some(100));
+        let checkpoint = config.checkpoint.as_ref().unwrap();
+        assert_eq!(checkpoint.path, PathBuf::from("sync.checkpoint"));
+        assert_eq!(checkpoint.interval_ms, 1000);
+
+        let mut filter = config.filter.build().unwrap();
+        let set = |key: &str| vec![b"SET".to_vec(), key.as_bytes().to_vec(), b"1".to_vec()];
+        assert_eq!(filter.apply(set("user:1")), Some(set("user:1")));
+        assert_eq!(filter.apply(set("item:1")), None);
+        assert_eq!(filter.apply(vec![b"FLUSHALL".to_vec()]), None);
+
+        let transform = config.transform.build().unwrap();
+        assert_eq!(&*transform.key(b"user:1"), b"copy:user:1");
+        assert_eq!(transform.db(0), 3);
+
+        assert_eq!(config.retry.delay(1), Duration::from_millis(100));
+        assert_eq!(config.retry.delay(3), Duration::from_millis(400));
+        assert_eq!(config.retry.delay(10), Duration::from_millis(1000));
+    }
+}
+
+#[test]
+fn test_validation() {
+    let cases = [
+        ("source.toml", "[source]\n", "source: An address or a sentinel"),
+        (
+            "types.toml",
+            "[source]\naddress = \"a:1\"\n[filter]\ntypes = [\"hash\", \"nope\"]\n",
+            "filter.types[1]: Unknown type: nope",
+        ),
+        (
+            "sample.yaml",
+            "source: {address: \"a:1\"}\nfilter: {sample: 2}\n",
+            "filter.sample: ",
+        ),
+        (
+            "rename.yaml",
+            "source: {address: \"a:1\"}\ntransform:\n  rename_keys: [{pattern: \"(\", replacement: x}]\n",
+            "transform.rename_keys[0].pattern: ",
+        ),
+        (
+            "sink.toml",
+            "[source]\naddress = \"a:1\"\n[sink]\ntype = \"file\"\n",
+            "sink.path: ",
+        ),
+        (
+            "format.yaml",
+            "source: {address: \"a:1\"}\nsink: {type: redis, address: \"b:2\", format: plain}\n",
+            "sink.format: ",
+        ),
+        (
+            "unknown.toml",
+            "[source]\naddress = \"a:1\"\n[filter]\nglobz = []\n",
+            "unknown field `globz`",
+        ),
+        (
+            "type.toml",
+            "[source]\naddress = \"a:1\"\n[retry]\nattempts = \"many\"\n",
+            "retry.attempts",
+        ),
+        (
+            "tls.toml",
+            "[source]\naddress = \"a:1\"\n[source.tls]\nca_file = \"missing.crt\"\n",
+            "source.tls: ",
+        ),
+        (
+            "client.yaml",
+            "source: {address: \"a:1\", tls: {cert_file: client.crt}}\n",
+            "source.tls: A client certificate needs its key",
+        ),
+        ("format.ini", "[source]\n", "Unknown format"),
+    ];
+    for (name, text, expected) in cases.iter() {
+        let error = load_error(name, text);
+        assert!(error.contains(expected), "{}: {}", name, error);
+        assert!(error.contains(name), "{}: {}", name, error);
+    }
+}
+
+#[test]
+fn test_reload() {
+    let path = write_config(
+        "reload.toml",
+        "[source]\naddress = \"a:1\"\n[transform]\nadd_prefix = \"one:\"\n",
+    );
+    let reload = Reload::on_sighup(&path).unwrap();
+    assert!(reload.poll().is_none());
+
+    fs::write(
+        &path,
+        "[source]\naddress = \"a:1\"\n[transform]\nadd_prefix = \"two:\"\n",
+    )
+    .unwrap();
+    signal_hook::low_level::raise(signal_hook::consts::SIGHUP).unwrap();
+    let (_, transform) = reload.poll().unwrap().unwrap();
+    assert_eq!(&*transform.key(b"k"), b"two:k");
+    assert!(reload.poll().is_none());
+
+    // A file which doesn't load is reported, the caller keeps what it has.
+    fs::write(
+        &path,
+        "[source]\naddress = \"a:1\"\n[filter]\nkeys = \"(\"\n",
+    )
+    .unwrap();
+    signal_hook::low_level::raise(signal_hook::consts::SIGHUP).unwrap();
+    let error = reload.poll().unwrap().err().unwrap().to_string();
+    assert!(error.contains("filter.keys"), "{}", error);
+    fs::remove_file(&path).unwrap();
+}
+
+// Answers a single SENTINEL get-master-addr-by-name over TLS, asking for
+// the client's certificate, and returns the command it got.
+fn tls_sentinel(listener: TcpListener) -> Vec<String> {
+    let tls = |name: &str| format!("{}/tests/tls/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_file(tls("ca.crt")).unwrap())
+        .unwrap();
+    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .unwrap();
+    let config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            vec![CertificateDer::from_pem_file(tls("server.crt")).unwrap()],
+            PrivateKeyDer::from_pem_file(tls("server.key")).unwrap(),
+        )
+        .unwrap();
+
+    let (tcp, _) = listener.accept().unwrap();
+    let conn = ServerConnection::new(Arc::new(config)).unwrap();
+    let mut stream = StreamOwned::new(conn, tcp);
+    let command = redis::Parser::new(BufReader::new(&mut stream))
+        .parse_value()
+        .unwrap();
+    stream
+        .write_all(b"*2\r\n$9\r\n127.0.0.1\r\n$4\r\n6380\r\n")
+        .unwrap();
+    stream.flush().unwrap();
+    redis::from_redis_value(&command).unwrap()
+}
+
+#[test]
+fn test_sentinel_over_tls() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let sentinel = thread::spawn(move || tls_sentinel(listener));
+
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls");
+    let path = write_config(
+        "tls-sentinel.toml",
+        &format!(
+            "[source.sentinel]\naddresses = [\"localhost:{}\"]\nmaster = \"mymaster\"\n\
+             [source.tls]\nca_file = \"{dir}/ca.crt\"\n\
+             cert_file = \"{dir}/server.crt\"\nkey_file = \"{dir}/server.key\"\n",
+            port,
+            dir = dir
+        ),
+    );
+    let config = Config::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.source.resolve().unwrap(), "127.0.0.1:6380");
+    assert_eq!(
+        sentinel.join().unwrap(),
+        ["SENTINEL", "get-master-addr-by-name", "mymaster"]
+    );
+}
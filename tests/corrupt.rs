@@ -0,0 +1,138 @@
+extern crate redis_canal_rs as rdb;
+mod common;
+use common::dumps;
+use common::frame::{frame_blob, frame_value};
+use rdb::constants::encoding_type;
+use rdb::filter::Simple;
+use rdb::formatter::Nil;
+use rdb::{RdbOk, Type};
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor};
+
+fn parse(data: &[u8]) -> RdbOk {
+    rdb::parse(&mut Cursor::new(data), Nil::new(), Simple::new())
+}
+
+fn parse_skipping(data: &[u8]) -> RdbOk {
+    let mut filter = Simple::new();
+    filter.add_type(Type::Module);
+    rdb::parse(&mut Cursor::new(data), Nil::new(), filter)
+}
+
+#[test]
+fn test_all_dumps_parse() {
+    for path in dumps() {
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        assert!(
+            rdb::parse(&mut reader, Nil::new(), Simple::new()).is_ok(),
+            "{:?}",
+            path
+        );
+
+        let data = fs::read(&path).unwrap();
+        assert!(parse_skipping(&data).is_ok(), "{:?} (skipped)", path);
+    }
+}
+
+#[test]
+fn test_invalid_version_digits() {
+    assert!(parse(b"REDIS\x00009\xFF").is_err());
+}
+
+#[test]
+fn test_truncated_dump() {
+    let data = fs::read("tests/dumps/regular_set.rdb").unwrap();
+    for len in 0..data.len() - 1 {
+        assert!(parse(&data[..len]).is_err());
+    }
+}
+
+#[test]
+fn test_unknown_value_type() {
+    assert!(parse(&frame_value(0x20, &[0])).is_err());
+    assert!(parse(&frame_value(encoding_type::MODULE, &[0])).is_err());
+}
+
+#[test]
+fn test_unknown_string_encoding() {
+    assert!(parse(&frame_value(encoding_type::STRING, &[0xC4, 0])).is_err());
+    assert!(parse_skipping(&frame_value(encoding_type::STRING, &[0xC4, 0])).is_err());
+}
+
+#[test]
+fn test_huge_string_length() {
+    let value = [0x81, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, b'a'];
+    assert!(parse(&frame_value(encoding_type::STRING, &value)).is_err());
+    assert!(parse_skipping(&frame_value(encoding_type::STRING, &value)).is_err());
+}
+
+#[test]
+fn test_invalid_lzf_data() {
+    // Claims an expansion no LZF stream can produce.
+    let value = [0xC3, 0x01, 0x81, 0, 0, 0, 0x10, 0, 0, 0, 0, 0];
+    assert!(parse(&frame_value(encoding_type::STRING, &value)).is_err());
+
+    // Back reference before the start of the output.
+    let value = [0xC3, 0x02, 0x0A, 0xE0, 0xFF];
+    assert!(parse(&frame_value(encoding_type::STRING, &value)).is_err());
+}
+
+#[test]
+fn test_invalid_zipmap_length() {
+    let zipmap = [0x01, 0xFE, b'a', 0x01, 0x00, b'b', 0xFF];
+    assert!(parse(&frame_blob(encoding_type::HASH_ZIPMAP, &zipmap)).is_err());
+}
+
+#[test]
+fn test_invalid_intset_size() {
+    let intset = [0x03, 0, 0, 0, 0x01, 0, 0, 0, 0x01, 0x02, 0x03];
+    assert!(parse(&frame_blob(encoding_type::SET_INTSET, &intset)).is_err());
+}
+
+#[test]
+fn test_odd_hash_ziplist() {
+    let ziplist = [14, 0, 0, 0, 12, 0, 0, 0, 1, 0, 0x00, 0x01, b'a', 0xFF];
+    assert!(parse(&frame_blob(encoding_type::HASH_ZIPLIST, &ziplist)).is_err());
+}
+
+#[test]
+fn test_invalid_sorted_set_scores() {
+    let ziplist = [
+        18, 0, 0, 0, 15, 0, 0, 0, 2, 0, 0x00, 0x01, b'a', 0x03, 0x01, b'x', 0xFF,
+    ];
+    assert!(parse(&frame_blob(encoding_type::ZSET_ZIPLIST, &ziplist)).is_err());
+
+    let zset = [0x01, 0x01, b'a', 0x01, b'x'];
+    assert!(parse(&frame_value(encoding_type::ZSET, &zset)).is_err());
+}
+
+#[test]
+fn test_unknown_module_opcode() {
+    let module = [0x01, 0x09, 0x00];
+    assert!(parse(&frame_value(encoding_type::MODULE2, &module)).is_err());
+    assert!(parse_skipping(&frame_value(encoding_type::MODULE2, &module)).is_err());
+}
+
+#[test]
+fn test_skip_all_value_types() {
+    let values: Vec<(u8, Vec<u8>)> = vec![
+        (encoding_type::ZSET, vec![0x01, 0x01, b'a', 0xFE]),
+        (
+            encoding_type::ZSET2,
+            vec![0x01, 0x01, b'a', 0, 0, 0, 0, 0, 0, 0xF0, 0x3F],
+        ),
+        (
+            encoding_type::MODULE2,
+            vec![0x01, 0x03, 0, 0, 0x80, 0x3F, 0x00],
+        ),
+        (encoding_type::LIST_QUICKLIST, vec![0x01, 0x01, b'a']),
+        (
+            encoding_type::STEAMLISTPACKS,
+            vec![0x00, 0x00, 0x00, 0x00, 0x00],
+        ),
+    ];
+
+    for (enc_type, value) in values {
+        assert!(parse_skipping(&frame_value(enc_type, &value)).is_ok());
+    }
+}
@@ -0,0 +1,103 @@
+extern crate redis_canal_rs as rdb;
+use rdb::diff::{diff, digest, Change, Difference};
+use rdb::filter::Simple;
+use rdb::types::EncodingType;
+use rdb::writer::RdbWriter;
+use rdb::{KeyMetadata, KeyRecord, RedisValue};
+use std::io::Cursor;
+
+fn dump(records: &[KeyRecord]) -> Vec<u8> {
+    let mut writer = RdbWriter::new(Vec::new());
+    writer.set_version(9).unwrap();
+    for record in records {
+        writer.write_record(record).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+// The writer picks the encoding itself.
+fn record(key: &str, value: RedisValue, expiry: Option<u64>) -> KeyRecord {
+    KeyRecord {
+        db: 0,
+        key: key.as_bytes().to_vec(),
+        meta: KeyMetadata {
+            expiry,
+            ..KeyMetadata::default()
+        },
+        value,
+        encoding: EncodingType::String,
+    }
+}
+
+fn members(members: &[&str]) -> Vec<Vec<u8>> {
+    members.iter().map(|m| m.as_bytes().to_vec()).collect()
+}
+
+#[test]
+fn test_digest() {
+    let set = |m: &[&str]| digest(&RedisValue::Set(members(m)));
+    assert_eq!(set(&["a", "b", "c"]), set(&["c", "a", "b"]));
+    assert_ne!(set(&["a", "b"]), set(&["ab"]));
+
+    let list = |m: &[&str]| digest(&RedisValue::List(members(m)));
+    assert_ne!(list(&["a", "b"]), list(&["b", "a"]));
+    assert_ne!(list(&["a"]), set(&["a"]));
+
+    let hash = |fields: &[(&str, &str)]| {
+        digest(&RedisValue::Hash(
+            fields
+                .iter()
+                .map(|(f, v)| (f.as_bytes().to_vec(), v.as_bytes().to_vec()))
+                .collect(),
+        ))
+    };
+    assert_eq!(
+        hash(&[("f", "1"), ("g", "2")]),
+        hash(&[("g", "2"), ("f", "1")])
+    );
+    assert_ne!(
+        hash(&[("f", "1"), ("g", "2")]),
+        hash(&[("f", "2"), ("g", "1")])
+    );
+}
+
+#[test]
+fn test_diff() {
+    let string = |value: &str| RedisValue::String(value.as_bytes().to_vec());
+    let first = dump(&[
+        record("same", string("1"), None),
+        record("removed", string("1"), None),
+        record("changed", string("1"), None),
+        record("expiry", string("1"), None),
+        record("reordered", RedisValue::Set(members(&["a", "b"])), None),
+    ]);
+    let second = dump(&[
+        record("reordered", RedisValue::Set(members(&["b", "a"])), None),
+        record("expiry", string("1"), Some(1_700_000_000_000)),
+        record("changed", string("2"), None),
+        record("added", string("1"), None),
+        record("same", string("1"), None),
+    ]);
+
+    let differences = diff(Cursor::new(&first), Cursor::new(&second), &Simple::new()).unwrap();
+    let expected: Vec<Difference> = [
+        ("added", Change::Added),
+        ("changed", Change::Changed),
+        ("expiry", Change::Changed),
+        ("removed", Change::Removed),
+    ]
+    .iter()
+    .map(|&(key, change)| Difference {
+        db: 0,
+        key: key.as_bytes().to_vec(),
+        change,
+    })
+    .collect();
+    assert_eq!(differences, expected);
+
+    assert!(
+        diff(Cursor::new(&first), Cursor::new(&first), &Simple::new())
+            .unwrap()
+            .is_empty()
+    );
+}
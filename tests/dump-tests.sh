@@ -17,9 +17,9 @@ fi
 cargo build $ARG
 
 if [ "$ARG" = "--release" ]; then
-  BIN=./target/release/rdb
+  BIN=./target/release/redis-canal-rs
 else
-  BIN=./target/debug/rdb
+  BIN=./target/debug/redis-canal-rs
 fi
 
 failure=0
@@ -27,7 +27,7 @@ for f in $FORMATS; do
   echo "Running $f tests..."
   for dump in $(find "$DUMP_DIRECTORY" -type f -name "*.rdb"); do
     echo "  with $dump"
-    $BIN --format $f $dump >/dev/null
+    $BIN parse --format $f $dump >/dev/null
 
     if [ $? -ne 0 ]; then
       echo "Failure with '$dump' (Format: $f)"
@@ -35,7 +35,7 @@ for f in $FORMATS; do
     fi
 
     echo "  with $dump, only database 1"
-    $BIN --format $f --databases 1 $dump >/dev/null
+    $BIN parse --format $f --databases 1 $dump >/dev/null
 
     if [ $? -ne 0 ]; then
       echo "Failure with '$dump' (Format: $f, only database 1)"
@@ -0,0 +1,77 @@
+extern crate redis_canal_rs as rdb;
+mod common;
+use common::dumps;
+use rdb::dump;
+use rdb::filter::Simple;
+use rdb::formatter::{Protocol, RecordHandler, Records};
+use rdb::parser::RdbParser;
+use rdb::reader::{Entry, RdbReader};
+use rdb::{KeyRecord, RdbOk, RedisValue};
+use std::fs::File;
+use std::io::{self, BufReader};
+
+#[derive(Default)]
+struct Keys(Vec<KeyRecord>);
+
+impl RecordHandler for Keys {
+    fn record(&mut self, record: KeyRecord) -> RdbOk {
+        self.0.push(record);
+        Ok(())
+    }
+}
+
+fn decode(payload: &[u8]) -> io::Result<RedisValue> {
+    let records = dump::decode(b"key", payload, Records::new(Keys::default()))?;
+    Ok(records.into_handler().0.remove(0).value)
+}
+
+#[test]
+fn test_decode_redis_payload() {
+    // DUMP of the string "10", from the Redis documentation.
+    let payload = b"\x00\xc0\x0a\x09\x00\xbe\x6d\x06\x89\x5a\x28\x00\x0a";
+    assert_eq!(decode(payload).unwrap(), RedisValue::String(b"10".to_vec()));
+
+    let mut corrupt = payload.to_vec();
+    corrupt[2] = 0x0b;
+    assert!(decode(&corrupt).is_err());
+    assert!(decode(&payload[..4]).is_err());
+}
+
+#[test]
+fn test_encode_decode() {
+    for path in dumps() {
+        for entry in RdbReader::new(BufReader::new(File::open(&path).unwrap())) {
+            let record = match entry.unwrap() {
+                Entry::Key(record) => record,
+                _ => continue,
+            };
+            match dump::encode(&record.value) {
+                Ok(payload) => assert_eq!(decode(&payload).unwrap(), record.value),
+                Err(_) => assert!(matches!(
+                    record.value,
+                    RedisValue::Stream { .. } | RedisValue::Module { .. }
+                )),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_protocol_restore_mode() {
+    let mut protocol = Protocol::with_output(Vec::new());
+    protocol.set_restore_mode(true);
+
+    let path = "tests/dumps/keys_with_expiry.rdb";
+    let input = BufReader::new(File::open(path).unwrap());
+    let mut parser = RdbParser::new(input, protocol, Simple::new());
+    parser.parse().unwrap();
+    let output = parser.into_formatter().into_output();
+
+    let keys = RdbReader::new(BufReader::new(File::open(path).unwrap()))
+        .filter(|entry| matches!(entry, Ok(Entry::Key(_))))
+        .count();
+    let output = String::from_utf8_lossy(&output).into_owned();
+    assert_eq!(output.matches("$7\r\nRESTORE\r\n").count(), keys);
+    assert_eq!(output.matches("$6\r\nABSTTL\r\n").count(), keys);
+    assert!(!output.contains("PEXPIREAT"));
+}
@@ -0,0 +1,146 @@
+#!/usr/bin/env python3
+"""Writes the hand made fixtures in this directory.
+
+They cover encodings and opcodes missing from the redis-rdb-tools dumps.
+Run it from anywhere; the files are written next to this script.
+"""
+
+import os
+import struct
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+def crc64(data):
+    # Jones polynomial, reflected, as used by Redis.
+    crc = 0
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = (crc >> 1) ^ (0x95AC9329AC4BC9B5 if crc & 1 else 0)
+    return crc
+
+
+def length(n):
+    if n < 1 << 6:
+        return bytes([n])
+    if n < 1 << 14:
+        return bytes([0x40 | (n >> 8), n & 0xFF])
+    if n < 1 << 32:
+        return b"\x80" + struct.pack(">I", n)
+    return b"\x81" + struct.pack(">Q", n)
+
+
+def string(s):
+    if isinstance(s, str):
+        s = s.encode()
+    return length(len(s)) + s
+
+
+def ziplist(entries):
+    body = b""
+    prev = 0
+    for entry in entries:
+        if isinstance(entry, int):
+            raw = bytes([prev]) + b"\xfe" + struct.pack("<b", entry)
+        elif len(entry) < 1 << 6:
+            raw = bytes([prev, len(entry)]) + entry.encode()
+        else:
+            raw = bytes([prev, 0x40 | (len(entry) >> 8), len(entry) & 0xFF]) + entry.encode()
+        body += raw
+        prev = len(raw)
+    tail = 10 + len(body) - prev
+    return struct.pack("<IIH", 10 + len(body) + 1, tail, len(entries)) + body + b"\xff"
+
+
+def header(version):
+    return b"REDIS%04d" % version
+
+
+def aux(key, value):
+    return b"\xfa" + string(key) + string(value)
+
+
+def finish(version, data):
+    data += b"\xff"
+    if version >= 5:
+        data += struct.pack("<Q", crc64(data))
+    return data
+
+
+def write(name, version, body):
+    with open(os.path.join(HERE, name), "wb") as f:
+        f.write(finish(version, header(version) + body))
+
+
+def rdb_version_1():
+    body = b"\xfe\x00"
+    body += b"\x00" + string("plain") + string("value")
+    body += b"\xfd" + struct.pack("<I", 1671963072) + b"\x00" + string("expires") + string("soon")
+    body += b"\x01" + string("list") + length(2) + string("a") + string("b")
+    body += b"\x02" + string("set") + length(2) + string("x") + string("y")
+    body += b"\x03" + string("zset") + length(3)
+    body += string("one") + b"\x01" + b"1"
+    body += string("pos") + b"\xfe"
+    body += string("neg") + b"\xff"
+    body += b"\x04" + string("hash") + length(1) + string("field") + string("value")
+    body += b"\xfe\x02"
+    body += b"\x00" + string("second_db") + string("value")
+    write("rdb_version_1_with_expiry.rdb", 1, body)
+
+
+def zset2():
+    body = aux("redis-ver", "4.0.14") + b"\xfe\x00\xfb\x01\x00"
+    body += b"\x05" + string("zset2") + length(3)
+    body += string("a") + struct.pack("<d", 1.5)
+    body += string("b") + struct.pack("<d", -2.25)
+    body += string("c") + struct.pack("<d", float("inf"))
+    write("zset2_scores.rdb", 8, body)
+
+
+def lru_idle():
+    body = aux("redis-ver", "5.0.7") + aux("maxmemory-policy", "allkeys-lru")
+    body += b"\xfe\x00\xfb\x02\x01"
+    body += b"\xf8" + length(3600) + b"\x00" + string("cold") + string("value")
+    body += b"\xfc" + struct.pack("<Q", 1671963072573)
+    body += b"\xf8" + length(5) + b"\x00" + string("warm") + string("value")
+    write("lru_idle.rdb", 9, body)
+
+
+def lfu_freq():
+    body = aux("redis-ver", "5.0.7") + aux("maxmemory-policy", "allkeys-lfu")
+    body += b"\xfe\x00\xfb\x02\x00"
+    body += b"\xf9\xff" + b"\x00" + string("hot") + string("value")
+    body += b"\xf9\x05" + b"\x0e" + string("queue") + length(1)
+    body += string(ziplist(["job1", "job2", 7]))
+    write("lfu_freq.rdb", 9, body)
+
+
+def module_aux():
+    module = length(0x3EB3CA9F000000) + length(2) + length(42)
+    module += length(5) + string("payload") + length(3) + struct.pack("<f", 0.5)
+    module += length(4) + struct.pack("<d", 0.25) + length(1) + length(7) + length(0)
+    body = aux("redis-ver", "5.0.7")
+    body += b"\xf7" + length(0x3EB3CA9F000000) + length(2) + length(2) + length(0)
+    body += b"\xfe\x00\xfb\x02\x00"
+    body += b"\x07" + string("custom") + module
+    body += b"\x00" + string("after") + string("module")
+    write("module_with_aux.rdb", 9, body)
+
+
+def quicklist():
+    body = aux("redis-ver", "3.2.13") + b"\xfe\x00\xfb\x01\x00"
+    body += b"\x0e" + string("quicklist") + length(3)
+    body += string(ziplist(["a", "b", "c"]))
+    body += string(ziplist([1, 2]))
+    body += string(ziplist(["x" * 70]))
+    write("quicklist_with_multiple_nodes.rdb", 7, body)
+
+
+if __name__ == "__main__":
+    rdb_version_1()
+    zset2()
+    lru_idle()
+    lfu_freq()
+    module_aux()
+    quicklist()
@@ -16,9 +16,9 @@ fi
 cargo build $ARG
 
 if [ "$ARG" = "--release" ]; then
-  BIN=./target/release/rdb
+  BIN=./target/release/redis-canal-rs
 else
-  BIN=./target/debug/rdb
+  BIN=./target/debug/redis-canal-rs
 fi
 
 failure=0
@@ -29,7 +29,7 @@ for dump in $(find "$DUMP_DIRECTORY" -type f -name "*.rdb"); do
   json=$(basename $dump) 
   json=${json/.rdb/.json}
   diff -q  \
-    <($BIN --format json $dump 2>/dev/null) \
+    <($BIN parse --format json $dump 2>/dev/null) \
     $DUMP_DIRECTORY/json/$json >/dev/null 2>&1
 
   if [ $? -ne 0 ]; then
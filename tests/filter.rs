@@ -0,0 +1,322 @@
+extern crate redis_canal_rs as rdb;
+use rdb::filter::{
+    glob_match, parse_expr, AnyOf, CommandKey, Filter, Not, Prefixes, Reservoir, Sample, Simple,
+};
+use rdb::reader::{Entry, RdbReader};
+use rdb::rewrite::Rewrite;
+use rdb::{KeyRecord, RedisValue};
+use regex::bytes::Regex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+fn read_keys<L: Filter>(dump: &str, filter: L) -> Vec<KeyRecord> {
+    let path = format!("tests/dumps/{}", dump);
+    RdbReader::with_filter(BufReader::new(File::open(path).unwrap()), filter)
+        .filter_map(|entry| match entry.unwrap() {
+            Entry::Key(key) => Some(key),
+            _ => None,
+        })
+        .collect()
+}
+
+fn names(keys: &[KeyRecord]) -> Vec<Vec<u8>> {
+    keys.iter().map(|record| record.key.clone()).collect()
+}
+
+#[test]
+fn test_combinators() {
+    let mut zeroth = Simple::new();
+    zeroth.add_database(0);
+    let mut second = Simple::new();
+    second.add_database(2);
+
+    let mut any = AnyOf::new();
+    any.add(zeroth);
+    any.add(second);
+    assert_eq!(read_keys("multiple_databases.rdb", any).len(), 2);
+    assert!(read_keys("multiple_databases.rdb", AnyOf::new()).is_empty());
+
+    let mut second = Simple::new();
+    second.add_database(2);
+    let keys = read_keys("multiple_databases.rdb", Not::new(second));
+    assert_eq!(names(&keys), [b"key_in_zeroth_database".to_vec()]);
+}
+
+#[test]
+fn test_expression() {
+    let all = read_keys("parser_filters.rdb", Simple::new());
+    let expected: Vec<Vec<u8>> = all
+        .iter()
+        .filter(|record| {
+            let key = String::from_utf8_lossy(&record.key);
+            (record.value.type_name() == "hash" || record.value.type_name() == "list")
+                && key.starts_with(['h', 'l'])
+                && !key.contains('1')
+                || key == "s2"
+        })
+        .map(|record| record.key.clone())
+        .collect();
+    assert!(expected.len() > 2);
+
+    let filter = parse_expr(
+        r#"db in (0, 3) and type in (hash, list) and key ~ "^[hl]" and not key ~ "1" or key = s2"#,
+    )
+    .unwrap();
+    assert_eq!(names(&read_keys("parser_filters.rdb", filter)), expected);
+
+    let filter = parse_expr(r#"db != 0 or (type = string and key !~ ".")"#).unwrap();
+    assert!(read_keys("parser_filters.rdb", filter).is_empty());
+}
+
+#[test]
+fn test_expression_errors() {
+    for (expr, message) in &[
+        ("db in (0, 3", "Expected ) at offset 11"),
+        ("type = tree", "Unknown type tree at offset 7"),
+        ("color = red", "Unknown field color at offset 0"),
+        ("key ~ \"[\"", "Invalid regular expression"),
+        ("db = 0 key = a", "Unexpected input at offset 7"),
+    ] {
+        let err = parse_expr(expr).err().unwrap().to_string();
+        assert!(err.starts_with(message), "{}: {}", expr, err);
+    }
+}
+
+#[test]
+fn test_glob_match() {
+    for &(pattern, string, expected) in &[
+        (&b"*"[..], &b"anything"[..], true),
+        (b"h?llo", b"hello", true),
+        (b"h?llo", b"hllo", false),
+        (b"h*llo", b"heeeello", true),
+        (b"h[ae]llo", b"hallo", true),
+        (b"h[ae]llo", b"hillo", false),
+        (b"h[^e]llo", b"hallo", true),
+        (b"h[^e]llo", b"hello", false),
+        (b"h[b-a]llo", b"hallo", true),
+        (b"h[\\]]llo", b"h]llo", true),
+        (b"h\\*llo", b"h*llo", true),
+        (b"h\\*llo", b"hello", false),
+        (b"a[b", b"ab", true),
+        (b"a[", b"ab", false),
+        (b"session:??:*", b"session:ab:1", true),
+        (b"session:??:*", b"session:abc:1", false),
+        (b"user:*", b"user:\xff", true),
+        (b"a*a*a*a*a*a*a*a*a*a*b", &[b'a'; 64], false),
+    ] {
+        assert_eq!(
+            glob_match(pattern, string, false),
+            expected,
+            "{:?} {:?}",
+            String::from_utf8_lossy(pattern),
+            String::from_utf8_lossy(string)
+        );
+    }
+    assert!(glob_match(b"HE[K-M]LO", b"hello", true));
+    assert!(!glob_match(b"HE[K-M]LO", b"hello", false));
+}
+
+#[test]
+fn test_prefixes() {
+    let mut prefixes = Prefixes::new();
+    assert!(prefixes.matches_key(b"anything"));
+
+    for prefix in &[&b"user:"[..], b"us", b"tenant:42:", b"tenant:7:"] {
+        prefixes.add_prefix(prefix);
+    }
+    assert!(prefixes.matches_key(b"us"));
+    assert!(prefixes.matches_key(b"user:1"));
+    assert!(prefixes.matches_key(b"tenant:42:\xff"));
+    assert!(!prefixes.matches_key(b"u"));
+    assert!(!prefixes.matches_key(b"tenant:4"));
+    assert!(!prefixes.matches_key(b"tenant:420"));
+
+    let mut simple = Simple::new();
+    simple.add_keys(Regex::new("^user:").unwrap());
+    assert!(simple.matches_key(b"user:\xff\xfe"));
+
+    let all = read_keys("parser_filters.rdb", Simple::new());
+    let expected: Vec<Vec<u8>> = all
+        .iter()
+        .map(|record| record.key.clone())
+        .filter(|key| (key.len() == 2 && b"hl".contains(&key[0])) || key.starts_with(b"s"))
+        .collect();
+    let filter = parse_expr(r#"key glob "[hl]?" or key prefix in (s, set)"#).unwrap();
+    assert_eq!(names(&read_keys("parser_filters.rdb", filter)), expected);
+}
+
+fn len(value: &RedisValue) -> u64 {
+    match value {
+        RedisValue::String(string) => string.len() as u64,
+        RedisValue::List(elements) | RedisValue::Set(elements) => elements.len() as u64,
+        RedisValue::SortedSet(elements) => elements.len() as u64,
+        RedisValue::Hash(elements) => elements.len() as u64,
+        RedisValue::Stream { length } => *length,
+        RedisValue::Module { .. } => 0,
+    }
+}
+
+#[test]
+fn test_value_filters() {
+    let all = read_keys("parser_filters.rdb", Simple::new());
+    let select = |predicate: &dyn Fn(&KeyRecord) -> bool| -> Vec<Vec<u8>> {
+        all.iter()
+            .filter(|record| predicate(record))
+            .map(|record| record.key.clone())
+            .collect()
+    };
+    let filtered = |expr: &str| names(&read_keys("parser_filters.rdb", parse_expr(expr).unwrap()));
+
+    let expected = select(&|record| len(&record.value) > 3 && record.value.type_name() != "string");
+    assert!(!expected.is_empty());
+    assert_eq!(filtered("len > 3 and type != string"), expected);
+
+    let expected = select(&|record| record.key == b"k1" || len(&record.value) <= 2);
+    assert_eq!(filtered("key = k1 or not len > 2"), expected);
+
+    let expected = select(&|record| record.encoding.name() == "ziplist");
+    assert!(!expected.is_empty());
+    assert_eq!(filtered("encoding = ziplist"), expected);
+
+    // A length byte and the 8 bytes of "ssssssss".
+    assert!(filtered("size = 9").contains(&b"k1".to_vec()));
+    let mut sizes = filtered("size < 1k");
+    sizes.extend(filtered("size >= 1kb"));
+    sizes.sort();
+    let mut expected = select(&|_| true);
+    expected.sort();
+    assert_eq!(sizes, expected);
+
+    let filter = parse_expr("len > 3").unwrap();
+    let input = BufReader::new(File::open("tests/dumps/parser_filters.rdb").unwrap());
+    let written = Rewrite::new(filter).run(input, Vec::new()).unwrap();
+    let rewritten: Vec<Vec<u8>> = RdbReader::new(Cursor::new(written))
+        .filter_map(|entry| match entry.unwrap() {
+            Entry::Key(record) => Some(record.key),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(rewritten, filtered("len > 3"));
+}
+
+#[test]
+fn test_scanned_lengths() {
+    // Compressed ziplists and strings, quicklists and intsets are scanned
+    // from their headers, the rest of the value is only read if it matches.
+    let dumps = [
+        "ziplist_that_compresses_easily.rdb",
+        "zipmap_that_compresses_easily.rdb",
+        "easily_compressible_string_key.rdb",
+        "quicklist_with_multiple_nodes.rdb",
+        "intset_64.rdb",
+        "integer_keys.rdb",
+        "redis_50_with_streams.rdb",
+    ];
+    for dump in dumps.iter() {
+        let all = read_keys(dump, Simple::new());
+        for record in &all {
+            let expr = format!("len = {}", len(&record.value));
+            let filtered = read_keys(dump, parse_expr(&expr).unwrap());
+            let expected: Vec<&KeyRecord> = all
+                .iter()
+                .filter(|other| len(&other.value) == len(&record.value))
+                .collect();
+            assert_eq!(
+                filtered.iter().collect::<Vec<_>>(),
+                expected,
+                "{} {}",
+                dump,
+                expr
+            );
+        }
+    }
+}
+
+#[test]
+fn test_expiry_filters() {
+    let filtered = |expr: &str| read_keys("keys_with_expiry.rdb", parse_expr(expr).unwrap()).len();
+    assert_eq!(filtered("expiry != none"), 1);
+    assert_eq!(filtered("expiry = none"), 0);
+    assert_eq!(filtered("expiry <= 1671963072573"), 1);
+    assert_eq!(filtered("expiry > 1671963072573"), 0);
+    assert_eq!(filtered("expired"), 1);
+    assert_eq!(filtered("not expired or key = foo"), 0);
+}
+
+#[test]
+fn test_sample() {
+    let keys: Vec<Vec<u8>> = (0..10000)
+        .map(|i| format!("user:{}", i).into_bytes())
+        .collect();
+    let picked = |sample: &Sample| -> Vec<&Vec<u8>> {
+        keys.iter().filter(|key| sample.matches_key(key)).collect()
+    };
+
+    assert!(picked(&Sample::new(0.0)).is_empty());
+    assert_eq!(picked(&Sample::new(1.0)).len(), keys.len());
+
+    let tenth = picked(&Sample::new(0.1));
+    assert!((900..1100).contains(&tenth.len()), "{}", tenth.len());
+    assert_eq!(picked(&Sample::new(0.1)), tenth);
+    // A bigger fraction picks the same keys and more.
+    let half = picked(&Sample::new(0.5));
+    assert!(tenth.iter().all(|key| half.contains(key)));
+
+    let mut seeded = Sample::new(0.1);
+    seeded.set_seed(42);
+    assert_ne!(picked(&seeded), tenth);
+
+    for key in &keys[..100] {
+        let command = CommandKey {
+            db: 3,
+            enc_type: None,
+            key,
+        };
+        assert_eq!(
+            seeded.matches_command(&command),
+            Some(seeded.matches_key(key))
+        );
+    }
+}
+
+#[test]
+fn test_reservoir() {
+    let all = read_keys("parser_filters.rdb", Simple::new());
+    let mut types: HashMap<&str, usize> = HashMap::new();
+    for record in &all {
+        *types.entry(record.value.type_name()).or_default() += 1;
+    }
+
+    let reservoir = Reservoir::new(Simple::new(), 2);
+    assert!(read_keys("parser_filters.rdb", &reservoir).is_empty());
+    let keys = reservoir.into_keys();
+    let sampled = read_keys("parser_filters.rdb", keys);
+    for (typ, count) in &types {
+        let picked = sampled
+            .iter()
+            .filter(|record| record.value.type_name() == *typ)
+            .count();
+        assert_eq!(picked, (*count).min(2), "{}", typ);
+    }
+
+    // The same seed picks the same keys.
+    let reservoir = Reservoir::new(Simple::new(), 2);
+    read_keys("parser_filters.rdb", &reservoir);
+    assert_eq!(
+        names(&read_keys("parser_filters.rdb", reservoir.into_keys())),
+        names(&sampled)
+    );
+
+    // Only keys the filter matches are picked.
+    let mut hashes = Simple::new();
+    hashes.add_type(rdb::Type::Hash);
+    let reservoir = Reservoir::new(hashes, 100);
+    read_keys("parser_filters.rdb", &reservoir);
+    let keys = reservoir.into_keys();
+    assert_eq!(keys.len(), types["hash"]);
+    let sampled = read_keys("parser_filters.rdb", keys);
+    assert!(sampled
+        .iter()
+        .all(|record| record.value.type_name() == "hash"));
+}
@@ -0,0 +1,141 @@
+extern crate redis_canal_rs as rdb;
+mod common;
+use common::dumps;
+use rdb::filter::Simple;
+use rdb::formatter::{Memory, Plain, Protocol, Records, Visitor, JSON, NDJSON};
+use rdb::parser::RdbParser;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+// Each dump in tests/dumps is rendered with every formatter and compared to
+// tests/golden/<format>/<dump>.<ext>, or to tests/dumps/json/<dump>.json
+// which tests/eq-tests.sh uses too. Run with UPDATE_GOLDEN=1 to rewrite the
+// golden files after an intended output change.
+const FORMATS: &[(&str, &str)] = &[
+    ("json", "json"),
+    ("ndjson", "ndjson"),
+    ("memory", "csv"),
+    ("plain", "txt"),
+    ("protocol", "resp"),
+];
+
+// Fails every write once `limit` bytes were written.
+struct Full {
+    written: usize,
+    limit: usize,
+}
+
+impl Write for Full {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written + buf.len() > self.limit {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "output full"));
+        }
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Parses the dump with `formatter`, giving it back for its output.
+fn parse_with<V: Visitor>(dump: &Path, formatter: V) -> V {
+    let input = BufReader::new(File::open(dump).unwrap());
+    let mut parser = RdbParser::new(input, formatter, Simple::new());
+    parser
+        .parse()
+        .unwrap_or_else(|e| panic!("{:?} failed to parse: {}", dump, e));
+    parser.into_formatter()
+}
+
+fn render(dump: &Path, format: &str) -> Vec<u8> {
+    match format {
+        "json" => parse_with(dump, JSON::with_output(Vec::new())).into_output(),
+        "ndjson" => {
+            let records = Records::new(NDJSON::with_output(Vec::new()));
+            parse_with(dump, records).into_handler().into_output()
+        }
+        "memory" => parse_with(dump, Memory::with_output(Vec::new())).into_output(),
+        "plain" => parse_with(dump, Plain::with_output(Vec::new())).into_output(),
+        "protocol" => parse_with(dump, Protocol::with_output(Vec::new())).into_output(),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_golden_outputs() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+
+    for dump in dumps() {
+        for &(format, ext) in FORMATS {
+            let dir = match format {
+                "json" => Path::new("tests/dumps/json").to_path_buf(),
+                _ => Path::new("tests/golden").join(format),
+            };
+            let golden = dir.join(dump.with_extension(ext).file_name().unwrap());
+            let actual = render(&dump, format);
+
+            if update {
+                fs::create_dir_all(golden.parent().unwrap()).unwrap();
+                fs::write(&golden, &actual).unwrap();
+                continue;
+            }
+
+            match fs::read(&golden) {
+                Ok(ref expected) if *expected == actual => {}
+                Ok(_) => failures.push(format!("{:?} differs", golden)),
+                Err(_) => failures.push(format!("{:?} is missing", golden)),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "golden outputs do not match, rerun with UPDATE_GOLDEN=1 if intended:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_write_errors() {
+    for &(format, _) in FORMATS {
+        let mut reader = BufReader::new(File::open("tests/dumps/regular_set.rdb").unwrap());
+        let out = Full {
+            written: 0,
+            limit: 20,
+        };
+        let res = match format {
+            "json" => rdb::parse(&mut reader, JSON::with_output(out), Simple::new()),
+            "ndjson" => {
+                let records = Records::new(NDJSON::with_output(out));
+                rdb::parse(&mut reader, records, Simple::new())
+            }
+            "memory" => rdb::parse(&mut reader, Memory::with_output(out), Simple::new()),
+            "plain" => rdb::parse(&mut reader, Plain::with_output(out), Simple::new()),
+            "protocol" => rdb::parse(&mut reader, Protocol::with_output(out), Simple::new()),
+            _ => unreachable!(),
+        };
+        let err = res.expect_err(format);
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero, "{}", format);
+    }
+}
+
+// Hashes, sets and lists stored as listpacks came with RDB 10, which is
+// refused before a key is read.
+#[test]
+fn test_listpack_dump() {
+    // The hash h holding a=1, as a listpack of two entries.
+    let listpack = b"\x0C\x00\x00\x00\x02\x00\x81a\x02\x01\x01\xFF";
+    let mut dump = b"REDIS0010\xFE\x00\x10\x01h\x0C".to_vec();
+    dump.extend_from_slice(listpack);
+    dump.extend_from_slice(b"\xFF\x00\x00\x00\x00\x00\x00\x00\x00");
+
+    let mut parser = RdbParser::new(&dump[..], Plain::with_output(Vec::new()), Simple::new());
+    let err = parser.parse().unwrap_err();
+    assert_eq!(err.to_string(), "Version not supported");
+    assert!(parser.into_formatter().into_output().is_empty());
+}
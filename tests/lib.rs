@@ -1,4 +1,6 @@
 extern crate redis_canal_rs as rdb;
+use rdb::crc64::crc64;
+use rdb::dump;
 use rdb::parser::{
     read_blob, read_length, read_length_with_encoding, verify_magic, verify_version,
 };
@@ -47,10 +49,7 @@ fn test_verify_version() {
         verify_version(&mut Cursor::new(vec![0x30, 0x30, 0x30, 0x33])).unwrap()
     );
 
-    match verify_version(&mut Cursor::new(vec![0x30, 0x30, 0x30, 0x3a])) {
-        Ok(_) => assert!(false),
-        Err(_) => assert!(true),
-    }
+    assert!(verify_version(&mut Cursor::new(vec![0x30, 0x30, 0x30, 0x3a])).is_err());
 }
 
 #[test]
@@ -60,8 +59,54 @@ fn test_verify_magic() {
         verify_magic(&mut Cursor::new(vec![0x52, 0x45, 0x44, 0x49, 0x53])).unwrap()
     );
 
-    match verify_magic(&mut Cursor::new(vec![0x51, 0x0, 0x0, 0x0, 0x0])) {
-        Ok(_) => assert!(false),
-        Err(_) => assert!(true),
+    assert!(verify_magic(&mut Cursor::new(vec![0x51, 0x0, 0x0, 0x0, 0x0])).is_err());
+}
+
+#[test]
+fn test_crc64() {
+    assert_eq!(0xe9c6d914c4b8d9ca, crc64(0, b"123456789"));
+}
+
+#[test]
+fn test_dump_payload() {
+    for &len in &[0u64, 63, 64, 16383, 16384, 1 << 32] {
+        let mut buf = Vec::new();
+        dump::write_length(&mut buf, len).unwrap();
+        assert_eq!(len, read_length(&mut Cursor::new(buf)).unwrap());
+    }
+
+    // The footer is the RDB version followed by the CRC64 of everything
+    // before it, both little endian.
+    let payload = dump::string_payload(b"abcd");
+    assert_eq!(&payload[..8], &[0, 4, b'a', b'b', b'c', b'd', 9, 0]);
+    assert_eq!(crc64(0, &payload[..8]).to_le_bytes(), payload[8..]);
+}
+
+#[test]
+fn test_byte_encodings() {
+    use rdb::formatter::ByteEncoding;
+
+    let values: &[&[u8]] = &[
+        b"",
+        b"plain",
+        b"\\x41",
+        b"\xff\x00\xe2\x82",
+        "caf\u{e9}".as_bytes(),
+        b"base64:abc",
+    ];
+    for encoding in &[
+        ByteEncoding::Escape,
+        ByteEncoding::Base64,
+        ByteEncoding::Hex,
+    ] {
+        for value in values {
+            let encoded = encoding.encode(value);
+            assert_eq!(encoding.decode(&encoded).unwrap(), *value, "{:?}", encoding);
+        }
     }
+
+    assert_eq!(ByteEncoding::Escape.encode(b"a\\\xffb"), "a\\\\\\xffb");
+    assert_eq!(ByteEncoding::Base64.encode(b"\xff"), "base64:/w==");
+    assert_eq!(ByteEncoding::Hex.encode(b"\x01a"), "0161");
+    assert!(ByteEncoding::Escape.decode("\\x4").is_err());
 }
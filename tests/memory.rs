@@ -0,0 +1,110 @@
+extern crate redis_canal_rs as rdb;
+use rdb::filter::Simple;
+use rdb::formatter::Memory;
+use rdb::parser::RdbParser;
+use rdb::types::EncodingType;
+use rdb::writer::{Encoding, RdbWriter};
+use rdb::{KeyMetadata, KeyRecord, RedisValue};
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+fn report(top: Option<usize>) -> Vec<String> {
+    let file = File::open("tests/dumps/parser_filters.rdb").unwrap();
+    rows(BufReader::new(file), top)
+}
+
+fn rows<R: Read>(input: R, top: Option<usize>) -> Vec<String> {
+    let mut memory = Memory::with_output(Vec::new());
+    if let Some(top) = top {
+        memory.set_top(top);
+    }
+
+    let mut parser = RdbParser::new(input, memory, Simple::new());
+    parser.parse().unwrap();
+    let output = parser.into_formatter().into_output();
+    String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+fn size(row: &str) -> u64 {
+    row.split(',').nth(3).unwrap().parse().unwrap()
+}
+
+#[test]
+fn test_top_keys() {
+    let mut all = report(None);
+    let top = report(Some(3));
+    assert_eq!(top.len(), 1 + 3);
+    assert_eq!(top[0], all[0]);
+
+    let mut rows = all.split_off(1);
+    rows.sort_by_key(|row| std::cmp::Reverse(size(row)));
+    assert_eq!(top[1..], rows[..3]);
+    assert!(rows.iter().all(|row| size(row) > 0));
+}
+
+fn strings(values: &[&str]) -> Vec<Vec<u8>> {
+    values
+        .iter()
+        .map(|value| value.as_bytes().to_vec())
+        .collect()
+}
+
+// The same values written with compact encodings and without.
+fn encoded(encoding: Encoding) -> Vec<String> {
+    let record = |key: &str, value: RedisValue| KeyRecord {
+        db: 0,
+        key: key.as_bytes().to_vec(),
+        meta: KeyMetadata::default(),
+        value,
+        encoding: EncodingType::String,
+    };
+    let fields = strings(&["f1", "v1", "f2", "v2", "f3", "v3"]);
+    let fields = fields
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect();
+    let records = [
+        record("h", RedisValue::Hash(fields)),
+        record("s", RedisValue::Set(strings(&["1", "2", "3"]))),
+        record("l", RedisValue::List(strings(&["a", "b", "c"]))),
+    ];
+
+    let mut writer = RdbWriter::new(Vec::new());
+    writer.set_encoding(encoding);
+    for record in records.iter() {
+        writer.write_record(record).unwrap();
+    }
+    let dump = writer.finish().unwrap();
+    rows(&dump[..], None).split_off(1)
+}
+
+#[test]
+fn test_encoding_sizes() {
+    // Every key takes 48 bytes for its dict entry, robj and the sds of its
+    // one character name.
+    assert_eq!(
+        encoded(Encoding::Compact),
+        vec![
+            // A 35 byte ziplist.
+            "0,hash,h,96,ziplist,3,2,",
+            // A 14 byte intset.
+            "0,set,s,64,intset,3,1,",
+            // A quicklist of one node holding 9 bytes of entries.
+            "0,list,l,152,quicklist,3,1,",
+        ]
+    );
+    assert_eq!(
+        encoded(Encoding::Plain),
+        vec![
+            // A dict of 4 buckets and 3 entries of 2 sds.
+            "0,hash,h,308,hashtable,3,2,",
+            // Integers below 10000 are shared.
+            "0,set,s,260,hashtable,3,1,",
+            "0,list,l,240,linkedlist,3,1,",
+        ]
+    );
+}
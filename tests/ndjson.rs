@@ -0,0 +1,45 @@
+extern crate redis_canal_rs as rdb;
+use rdb::filter::Simple;
+use rdb::formatter::{Records, NDJSON};
+use serde_json::Value;
+use std::fs::File;
+use std::io::BufReader;
+
+fn lines(dump: &str, max_size: Option<usize>) -> Vec<Value> {
+    let path = format!("tests/dumps/{}", dump);
+    let mut reader = BufReader::new(File::open(path).unwrap());
+    let mut records = Records::new(NDJSON::with_output(Vec::new()));
+    if let Some(max_size) = max_size {
+        records.set_max_size(max_size);
+    }
+
+    let mut parser = rdb::parser::RdbParser::new(&mut reader, records, Simple::new());
+    parser.parse().unwrap();
+    let output = parser.into_formatter().into_handler().into_output();
+
+    String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_chunks() {
+    let whole = lines("regular_sorted_set.rdb", None);
+    let chunks = lines("regular_sorted_set.rdb", Some(1000));
+    assert_eq!(whole.len(), 1);
+    assert!(chunks.len() > 1);
+
+    let mut members = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        assert_eq!(chunk["key"], whole[0]["key"]);
+        assert_eq!(chunk["type"], "zset");
+        assert_eq!(chunk["chunk"], i);
+        assert_eq!(chunk["last"], i == chunks.len() - 1);
+        let value = chunk["value"].as_array().unwrap();
+        assert_eq!(chunk["size"], value.len());
+        members.extend(value.iter().cloned());
+    }
+    assert_eq!(Value::Array(members), whole[0]["value"]);
+}
@@ -0,0 +1,181 @@
+extern crate redis_canal_rs as rdb;
+use rdb::command::read_command;
+use rdb::dump;
+use rdb::filter::Simple;
+use rdb::formatter::Protocol;
+use rdb::parser::RdbParser;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+
+fn render<F: FnOnce(&mut Protocol<Vec<u8>>)>(dump: &str, configure: F) -> String {
+    let path = format!("tests/dumps/{}", dump);
+    render_from(BufReader::new(File::open(path).unwrap()), configure)
+}
+
+fn render_from<R: Read, F: FnOnce(&mut Protocol<Vec<u8>>)>(input: R, configure: F) -> String {
+    String::from_utf8_lossy(&render_bytes(input, configure)).into_owned()
+}
+
+fn render_bytes<R: Read, F: FnOnce(&mut Protocol<Vec<u8>>)>(input: R, configure: F) -> Vec<u8> {
+    let mut protocol = Protocol::with_output(Vec::new());
+    configure(&mut protocol);
+
+    let mut parser = RdbParser::new(input, protocol, Simple::new());
+    parser.parse().unwrap();
+    parser.into_formatter().into_output()
+}
+
+// The argument counts of all commands, in order.
+fn commands(output: &str) -> Vec<(String, usize)> {
+    let lines: Vec<&str> = output.split("\r\n").collect();
+    let mut commands = Vec::new();
+    let mut i = 0;
+    while i + 2 < lines.len() {
+        let args: usize = lines[i][1..].parse().unwrap();
+        commands.push((lines[i + 2].to_string(), args));
+        i += 1 + 2 * args;
+    }
+    commands
+}
+
+#[test]
+fn test_batches() {
+    let single = commands(&render("regular_set.rdb", |_| {}));
+    assert_eq!(single.iter().filter(|(c, _)| c == "SADD").count(), 6);
+
+    let batched = commands(&render("regular_set.rdb", |p| p.set_batch_size(4, 1024)));
+    assert_eq!(
+        batched,
+        vec![
+            ("SELECT".to_string(), 2),
+            ("SADD".to_string(), 6),
+            ("SADD".to_string(), 4)
+        ]
+    );
+
+    let hash = commands(&render("hash_as_ziplist.rdb", |p| {
+        p.set_batch_size(100, 1024)
+    }));
+    assert_eq!(hash[1], ("HSET".to_string(), 2 + 2 * 3));
+
+    let zset = commands(&render("regular_sorted_set.rdb", |p| {
+        p.set_batch_size(128, 1 << 20)
+    }));
+    let zadds: Vec<usize> = zset[1..].iter().map(|(_, args)| args).cloned().collect();
+    assert_eq!(
+        zadds,
+        vec![2 + 2 * 128, 2 + 2 * 128, 2 + 2 * 128, 2 + 2 * 116]
+    );
+}
+
+#[test]
+fn test_batch_bytes() {
+    // Every member of the set has 3 to 5 bytes.
+    let batched = commands(&render("regular_set.rdb", |p| p.set_batch_size(100, 10)));
+    assert!(batched[1..].iter().all(|(_, args)| *args <= 4));
+    let members: usize = batched[1..].iter().map(|(_, args)| args - 2).sum();
+    assert_eq!(members, 6);
+}
+
+#[test]
+fn test_replace() {
+    let output = commands(&render("regular_set.rdb", |p| {
+        p.set_replace(true);
+        p.set_batch_size(10, 1024)
+    }));
+    assert_eq!(
+        output,
+        vec![
+            ("SELECT".to_string(), 2),
+            ("DEL".to_string(), 2),
+            ("SADD".to_string(), 8)
+        ]
+    );
+
+    let strings = render("keys_with_expiry.rdb", |p| p.set_replace(true));
+    assert!(!strings.contains("$3\r\nDEL\r\n"));
+}
+
+const EXPIRY: u64 = 1671963072573;
+
+#[test]
+fn test_drop_expired() {
+    let dropped = commands(&render("keys_with_expiry.rdb", |p| {
+        p.set_reference_time(EXPIRY + 1);
+        p.set_drop_expired(true);
+    }));
+    assert_eq!(dropped, vec![("SELECT".to_string(), 2)]);
+
+    let kept = commands(&render("keys_with_expiry.rdb", |p| {
+        p.set_reference_time(EXPIRY - 1);
+        p.set_drop_expired(true);
+    }));
+    assert_eq!(kept.len(), 3);
+}
+
+#[test]
+fn test_relative_expiry() {
+    let output = render("keys_with_expiry.rdb", |p| {
+        p.set_reference_time(EXPIRY - 5000);
+        p.set_relative_expiry(true);
+    });
+    assert!(output.ends_with("$7\r\nPEXPIRE\r\n$20\r\nexpires_ms_precision\r\n$4\r\n5000\r\n"));
+
+    let output = render("keys_with_expiry.rdb", |p| {
+        p.set_reference_time(EXPIRY - 5000);
+        p.set_relative_expiry(true);
+        p.set_restore_mode(true);
+    });
+    assert!(output.contains("$4\r\n5000\r\n"));
+    assert!(!output.contains("ABSTTL"));
+}
+
+#[test]
+fn test_string_pxat() {
+    let output = commands(&render("keys_with_expiry.rdb", |p| p.set_string_pxat(true)));
+    assert_eq!(output[1..], [("SET".to_string(), 5)]);
+
+    let output = render("keys_with_expiry.rdb", |p| {
+        p.set_reference_time(EXPIRY - 5000);
+        p.set_relative_expiry(true);
+        p.set_string_pxat(true);
+    });
+    assert!(output.ends_with("$2\r\nPX\r\n$4\r\n5000\r\n"));
+}
+
+#[test]
+fn test_empty_keys() {
+    // An expiring quicklist without any nodes.
+    let mut dump = b"REDIS0009\xFE\x00\xFC".to_vec();
+    dump.extend_from_slice(&EXPIRY.to_le_bytes());
+    dump.extend_from_slice(b"\x0E\x01k\x00\xFF");
+
+    let output = commands(&render_from(Cursor::new(&dump), |_| {}));
+    assert_eq!(output, vec![("SELECT".to_string(), 2)]);
+
+    let output = commands(&render_from(Cursor::new(&dump), |p| {
+        p.set_restore_mode(true)
+    }));
+    assert_eq!(output, vec![("SELECT".to_string(), 2)]);
+}
+
+#[test]
+fn test_stream_and_module_payloads() {
+    let dump = File::open("tests/dumps/redis_50_with_streams.rdb").unwrap();
+    let mut input = Cursor::new(render_bytes(dump, |_| {}));
+    let restore = loop {
+        let (args, _) = read_command(&mut input).unwrap();
+        if args[0] == b"RESTORE" {
+            break args;
+        }
+    };
+    assert_eq!(restore[4], b"REPLACE");
+
+    // The payload holds the stream as the dump does.
+    let decoded = dump::decode(&restore[1], &restore[3], Protocol::with_output(Vec::new()));
+    let decoded = decoded.unwrap().into_output();
+    assert_eq!(read_command(&mut Cursor::new(decoded)).unwrap().0, restore);
+
+    let output = commands(&render("redis_40_with_module.rdb", |_| {}));
+    assert!(output.contains(&("RESTORE".to_string(), 5)));
+}
@@ -0,0 +1,134 @@
+extern crate redis_canal_rs as rdb;
+use rdb::filter::Simple;
+use rdb::reader::{Entry, RdbReader};
+use rdb::types::EncodingType;
+use rdb::{KeyMetadata, KeyRecord, RedisValue};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+fn open(name: &str) -> RdbReader<BufReader<File>> {
+    let path = format!("tests/dumps/{}", name);
+    RdbReader::new(BufReader::new(File::open(path).unwrap()))
+}
+
+fn read_keys(reader: RdbReader<BufReader<File>>) -> Vec<KeyRecord> {
+    reader
+        .filter_map(|entry| match entry.unwrap() {
+            Entry::Key(key) => Some(key),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_entries() {
+    let entries: Vec<Entry> = open("lru_idle.rdb").map(Result::unwrap).collect();
+
+    assert_eq!(
+        entries[..3],
+        [
+            Entry::AuxField {
+                key: b"redis-ver".to_vec(),
+                value: b"5.0.7".to_vec()
+            },
+            Entry::AuxField {
+                key: b"maxmemory-policy".to_vec(),
+                value: b"allkeys-lru".to_vec()
+            },
+            Entry::SelectDb(0),
+        ]
+    );
+    assert_eq!(
+        entries.last(),
+        Some(&Entry::Key(KeyRecord {
+            db: 0,
+            key: b"warm".to_vec(),
+            meta: KeyMetadata {
+                expiry: Some(1671963072573),
+                idle: Some(5),
+                freq: None
+            },
+            value: RedisValue::String(b"value".to_vec()),
+            encoding: EncodingType::String,
+        }))
+    );
+}
+
+#[test]
+fn test_values() {
+    let keys = read_keys(open("multiple_databases.rdb"));
+    assert_eq!(
+        keys.iter().map(|key| key.db).collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+
+    let keys = read_keys(open("quicklist_with_multiple_nodes.rdb"));
+    match &keys[0].value {
+        RedisValue::List(values) => assert!(values.len() > 1),
+        value => panic!("unexpected value {:?}", value),
+    }
+
+    let keys = read_keys(open("regular_sorted_set.rdb"));
+    match &keys[0].value {
+        RedisValue::SortedSet(members) => assert_eq!(members.len(), 500),
+        value => panic!("unexpected value {:?}", value),
+    }
+}
+
+#[test]
+fn test_filter_and_early_exit() {
+    let mut filter = Simple::new();
+    filter.add_database(2);
+    let file = BufReader::new(File::open("tests/dumps/multiple_databases.rdb").unwrap());
+    let keys = read_keys(RdbReader::with_filter(file, filter));
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].db, 2);
+
+    assert_eq!(open("regular_set.rdb").take(1).count(), 1);
+}
+
+#[test]
+fn test_error_ends_iteration() {
+    let mut reader = RdbReader::new(Cursor::new(b"REDIS0009\xFE\x00\x00\x05ab".to_vec()));
+    assert_eq!(reader.next().unwrap().unwrap(), Entry::SelectDb(0));
+    assert!(reader.next().unwrap().is_err());
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn test_streams_and_modules() {
+    let keys = read_keys(open("redis_50_with_streams.rdb"));
+    assert!(keys
+        .iter()
+        .any(|key| matches!(key.value, RedisValue::Stream { .. })));
+
+    let keys = read_keys(open("module_with_aux.rdb"));
+    assert!(keys
+        .iter()
+        .any(|key| matches!(key.value, RedisValue::Module { .. })));
+}
+
+#[test]
+fn test_chunked_values() {
+    let mut reader = open("regular_sorted_set.rdb");
+    reader.set_max_size(100);
+
+    let mut members = 0;
+    let mut chunks = 0;
+    for entry in reader {
+        match entry.unwrap() {
+            Entry::Chunk { record, last } => {
+                members += record.value.len();
+                chunks += 1;
+                if last {
+                    break;
+                }
+                assert!(record.value.len() > 1);
+            }
+            Entry::Key(record) => panic!("unexpected whole key {:?}", record.key),
+            _ => {}
+        }
+    }
+    assert_eq!(members, 500);
+    assert!(chunks > 10);
+}
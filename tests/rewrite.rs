@@ -0,0 +1,70 @@
+extern crate redis_canal_rs as rdb;
+mod common;
+use common::{dumps, keys};
+use rdb::crc64::crc64;
+use rdb::filter::Simple;
+use rdb::rewrite::Rewrite;
+use rdb::Type;
+use regex::bytes::Regex;
+use std::fs::{self, File};
+use std::io::BufReader;
+
+fn rewrite(dump: &str, rewrite: Rewrite<Simple>) -> Vec<u8> {
+    let input = BufReader::new(File::open(format!("tests/dumps/{}", dump)).unwrap());
+    rewrite.run(input, Vec::new()).unwrap()
+}
+
+#[test]
+fn test_unfiltered_copy() {
+    for path in dumps() {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let data = fs::read(&path).unwrap();
+        let written = rewrite(name, Rewrite::new(Simple::new()));
+
+        assert_eq!(keys(&data), keys(&written), "{}", name);
+        assert_eq!(&data[..9], &written[..9], "{}", name);
+
+        // Checksums exist since version 5.
+        if written[5..9] >= b"0005"[..] {
+            let (data, checksum) = written.split_at(written.len() - 8);
+            assert_eq!(crc64(0, data).to_le_bytes(), checksum, "{}", name);
+        }
+    }
+}
+
+#[test]
+fn test_filtered_copy() {
+    let mut filter = Simple::new();
+    filter.add_type(Type::Hash);
+    filter.add_keys(Regex::new("^zipmap").unwrap());
+    let written = rewrite("zipmap_with_big_values.rdb", Rewrite::new(filter));
+    assert_eq!(
+        keys(&written),
+        keys(&fs::read("tests/dumps/zipmap_with_big_values.rdb").unwrap())
+    );
+
+    let mut filter = Simple::new();
+    filter.add_database(2);
+    let mut rewrite_db = Rewrite::new(filter);
+    rewrite_db.renumber_database(2, 5);
+    let written = rewrite("multiple_databases.rdb", rewrite_db);
+    let keys = keys(&written);
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].db, 5);
+}
+
+#[test]
+fn test_strip_expired() {
+    let all = keys(&fs::read("tests/dumps/keys_with_expiry.rdb").unwrap());
+    let expiring = all.iter().filter(|key| key.meta.expiry.is_some()).count();
+    assert!(expiring > 0);
+
+    let mut strip = Rewrite::new(Simple::new());
+    strip.strip_expired(u64::MAX);
+    let written = keys(&rewrite("keys_with_expiry.rdb", strip));
+    assert_eq!(written.len(), all.len() - expiring);
+
+    let mut strip = Rewrite::new(Simple::new());
+    strip.strip_expired(0);
+    assert_eq!(keys(&rewrite("keys_with_expiry.rdb", strip)), all);
+}
@@ -0,0 +1,96 @@
+extern crate redis_canal_rs as rdb;
+use rdb::filter::Simple;
+use rdb::formatter::{Stats, StatsFormat};
+use rdb::parser::RdbParser;
+use rdb::types::EncodingType;
+use rdb::writer::RdbWriter;
+use rdb::{KeyMetadata, KeyRecord, RedisValue};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+fn report(dump: &str, configure: impl FnOnce(&mut Stats<Vec<u8>>)) -> String {
+    let path = format!("tests/dumps/{}", dump);
+    report_of(BufReader::new(File::open(path).unwrap()), configure)
+}
+
+fn report_of<R: Read>(input: R, configure: impl FnOnce(&mut Stats<Vec<u8>>)) -> String {
+    let mut stats = Stats::with_output(Vec::new());
+    configure(&mut stats);
+
+    let mut parser = RdbParser::new(input, stats, Simple::new());
+    parser.parse().unwrap();
+    String::from_utf8(parser.into_formatter().into_output()).unwrap()
+}
+
+#[test]
+fn test_text_report() {
+    let report = report("parser_filters.rdb", |stats| {
+        stats.set_top(2);
+        stats.set_namespace_delimiter(b"_");
+    });
+    let lines: Vec<&str> = report.lines().collect();
+    assert!(lines[0].starts_with("db 0: "));
+    assert!(lines.iter().any(|line| line.starts_with("  list: ")));
+    assert!(lines.contains(&"  namespaces:"));
+}
+
+#[test]
+fn test_json_report() {
+    let report = report("keys_with_expiry.rdb", |stats| {
+        stats.set_format(StatsFormat::JSON);
+        stats.set_reference_time(1671963072573 - 1000);
+    });
+    let report: Value = serde_json::from_str(&report).unwrap();
+    let strings = &report["databases"][0]["types"]["string"];
+    assert_eq!(strings["keys"], 1);
+    assert_eq!(strings["expiry"][2]["bucket"], "<1h");
+    assert_eq!(strings["expiry"][2]["keys"], 1);
+    assert_eq!(strings["biggest"][0]["key"], "expires_ms_precision");
+    assert_eq!(strings["elements"]["p99"], 1);
+}
+
+#[test]
+fn test_percentiles_and_namespaces() {
+    // Lists of 1 to 100 elements, and two keys without a namespace.
+    let record = |key: String, value: RedisValue| KeyRecord {
+        db: 0,
+        key: key.into_bytes(),
+        meta: KeyMetadata::default(),
+        value,
+        encoding: EncodingType::LinkedList,
+    };
+    let mut writer = RdbWriter::new(Vec::new());
+    for length in 1..=100 {
+        let elements = (0..length).map(|i| i.to_string().into_bytes()).collect();
+        let list = record(format!("list:{}", length), RedisValue::List(elements));
+        writer.write_record(&list).unwrap();
+    }
+    for key in ["first", "second"].iter() {
+        let string = record(key.to_string(), RedisValue::String(b"1".to_vec()));
+        writer.write_record(&string).unwrap();
+    }
+    let dump = writer.finish().unwrap();
+
+    let report = report_of(&dump[..], |stats| {
+        stats.set_format(StatsFormat::JSON);
+        stats.set_namespace_delimiter(b":");
+    });
+    let report: Value = serde_json::from_str(&report).unwrap();
+    let database = &report["databases"][0];
+
+    // Above 16 the percentiles are the highest count of their bucket.
+    let elements = &database["types"]["list"]["elements"];
+    assert_eq!(elements["total"], 5050);
+    assert_eq!(elements["p50"], 51);
+    assert_eq!(elements["p90"], 95);
+    assert_eq!(elements["p99"], 100);
+    assert_eq!(elements["max"], 100);
+
+    let namespaces = database["namespaces"].as_array().unwrap();
+    assert_eq!(namespaces.len(), 2);
+    assert_eq!(namespaces[0]["namespace"], "list");
+    assert_eq!(namespaces[0]["keys"], 100);
+    assert_eq!(namespaces[1]["namespace"], Value::Null);
+    assert_eq!(namespaces[1]["keys"], 2);
+}
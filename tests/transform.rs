@@ -0,0 +1,129 @@
+extern crate redis_canal_rs as rdb;
+mod common;
+use common::{args, keys};
+use rdb::filter::Simple;
+use rdb::formatter::{Control, Visitor};
+use rdb::rewrite::Rewrite;
+use rdb::transform::{Transform, Transforming};
+use rdb::{KeyMetadata, RdbResult};
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::BufReader;
+
+// Logs the databases and the keys of strings.
+#[derive(Default)]
+struct Log {
+    events: Vec<String>,
+}
+
+impl Visitor for Log {
+    fn start_database(&mut self, db_index: u64) -> RdbResult<Control> {
+        self.events.push(format!("db {}", db_index));
+        Ok(Control::Continue)
+    }
+
+    fn set(&mut self, key: &[u8], _value: &[u8], _meta: &KeyMetadata) -> RdbResult<Control> {
+        self.events.push(String::from_utf8_lossy(key).into_owned());
+        Ok(Control::Continue)
+    }
+}
+
+#[test]
+fn test_key_names() {
+    let mut transform = Transform::new();
+    assert!(transform.is_identity());
+    transform.strip_prefix(b"old:");
+    transform.replace_keys(Regex::new("^user:(\\d+)$").unwrap(), "account:$1");
+    transform.add_prefix(b"new:");
+    transform.map_database(0, 3);
+    assert!(!transform.is_identity());
+
+    assert_eq!(&*transform.key(b"old:user:42"), b"new:account:42");
+    assert_eq!(&*transform.key(b"user:42:name"), b"new:user:42:name");
+    assert_eq!(&*transform.key(b"\xffold"), b"new:\xffold");
+    assert_eq!(transform.db(0), 3);
+    assert_eq!(transform.db(1), 1);
+
+    let mut transform = Transform::new();
+    transform.replace_keys(Regex::new("^tmp:").unwrap(), "");
+    assert!(matches!(transform.key(b"cache:1"), Cow::Borrowed(_)));
+    assert_eq!(&*transform.key(b"tmp:1"), b"1");
+}
+
+#[test]
+fn test_commands() {
+    let mut transform = Transform::new();
+    transform.add_prefix(b"p:");
+    transform.map_database(0, 5);
+    transform.map_database(2, 7);
+
+    let cases = [
+        ("SET a 1", "SET p:a 1"),
+        ("RENAME a b", "RENAME p:a p:b"),
+        ("MSET a 1 b 2", "MSET p:a 1 p:b 2"),
+        (
+            "ZUNIONSTORE d 2 a b WEIGHTS 1 2",
+            "ZUNIONSTORE p:d 2 p:a p:b WEIGHTS 1 2",
+        ),
+        ("EVAL script 1 a b", "EVAL script 1 p:a b"),
+        ("SORT a BY w STORE d", "SORT p:a BY w STORE p:d"),
+        ("SELECT 2", "SELECT 7"),
+        ("SELECT 1", "SELECT 1"),
+        ("SWAPDB 0 2", "SWAPDB 5 7"),
+        ("MOVE a 0", "MOVE p:a 5"),
+        ("COPY a b DB 2 REPLACE", "COPY p:a p:b DB 7 REPLACE"),
+        ("PUBLISH channel message", "PUBLISH channel message"),
+        ("MULTI", "MULTI"),
+    ];
+    for (command, expected) in cases.iter() {
+        assert_eq!(transform.apply_command(args(command)), args(expected));
+    }
+}
+
+#[test]
+fn test_visitor() {
+    let path = "tests/dumps/multiple_databases.rdb";
+    let mut transform = Transform::new();
+    transform.add_prefix(b"moved:");
+    transform.map_database(0, 4);
+
+    let mut expected = Vec::new();
+    let mut db = None;
+    for mut record in keys(&fs::read(path).unwrap()) {
+        transform.apply_record(&mut record);
+        if db != Some(record.db) {
+            db = Some(record.db);
+            expected.push(format!("db {}", record.db));
+        }
+        assert!(record.key.starts_with(b"moved:"));
+        expected.push(String::from_utf8_lossy(&record.key).into_owned());
+    }
+    assert!(expected.contains(&"db 4".to_string()));
+
+    let input = BufReader::new(File::open(path).unwrap());
+    let visitor = Transforming::new(Log::default(), transform);
+    let mut parser = rdb::parser::RdbParser::new(input, visitor, Simple::new());
+    parser.parse().unwrap();
+    assert_eq!(parser.into_formatter().into_inner().events, expected);
+}
+
+#[test]
+fn test_rewrite() {
+    let path = "tests/dumps/easily_compressible_string_key.rdb";
+    let mut transform = Transform::new();
+    transform.replace_keys(Regex::new("^a+").unwrap(), "b");
+    transform.map_database(0, 1);
+
+    let mut rewrite = Rewrite::new(Simple::new());
+    rewrite.set_transform(transform.clone());
+    let input = BufReader::new(File::open(path).unwrap());
+    let written = rewrite.run(input, Vec::new()).unwrap();
+
+    let mut expected = keys(&fs::read(path).unwrap());
+    for record in &mut expected {
+        transform.apply_record(record);
+    }
+    assert!(expected.iter().all(|record| record.key.starts_with(b"b")));
+    assert_eq!(keys(&written), expected);
+}
@@ -0,0 +1,37 @@
+extern crate redis_canal_rs as rdb;
+use rdb::verify::{verify, Checksum, Verification};
+use std::fs;
+use std::io::{Cursor, ErrorKind};
+
+fn read(name: &str) -> Vec<u8> {
+    fs::read(format!("tests/dumps/{}", name)).unwrap()
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(
+        verify(Cursor::new(read("rdb_version_5_with_checksum.rdb"))).unwrap(),
+        Verification {
+            version: 5,
+            databases: 1,
+            keys: 6,
+            checksum: Checksum::Valid,
+        }
+    );
+    let checksum = |name| verify(Cursor::new(read(name))).unwrap().checksum;
+    assert_eq!(checksum("redis_40_with_module.rdb"), Checksum::Disabled);
+    assert_eq!(checksum("multiple_databases.rdb"), Checksum::Absent);
+}
+
+#[test]
+fn test_corruption() {
+    let mut data = read("rdb_version_5_with_checksum.rdb");
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    let err = verify(Cursor::new(&data)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("Checksum mismatch"), "{}", err);
+
+    let data = read("zipmap_with_big_values.rdb");
+    assert!(verify(Cursor::new(&data[..data.len() / 2])).is_err());
+}
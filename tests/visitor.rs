@@ -0,0 +1,160 @@
+extern crate redis_canal_rs as rdb;
+use rdb::filter::Simple;
+use rdb::formatter::{Control, Visitor};
+use rdb::parser::{EncodingType, RdbParser};
+use rdb::writer::{Encoding, RdbWriter};
+use rdb::{KeyMetadata, KeyRecord, RdbResult, RedisValue};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+// Logs the callbacks it gets and answers them with `control`.
+struct Log {
+    events: Vec<String>,
+    control: fn(&str) -> Control,
+}
+
+impl Log {
+    fn event(&mut self, event: String) -> RdbResult<Control> {
+        let control = (self.control)(&event);
+        self.events.push(event);
+        Ok(control)
+    }
+}
+
+impl Visitor for Log {
+    fn end_rdb(&mut self) -> RdbResult<Control> {
+        self.event("end".to_string())
+    }
+
+    fn set(&mut self, key: &[u8], _value: &[u8], _meta: &KeyMetadata) -> RdbResult<Control> {
+        self.event(format!("set {}", String::from_utf8_lossy(key)))
+    }
+
+    fn start_list(
+        &mut self,
+        key: &[u8],
+        _length: u64,
+        _meta: &KeyMetadata,
+        _info: EncodingType,
+    ) -> RdbResult<Control> {
+        self.event(format!("start {}", String::from_utf8_lossy(key)))
+    }
+
+    fn list_element(&mut self, key: &[u8], value: &[u8]) -> RdbResult<Control> {
+        let key = String::from_utf8_lossy(key);
+        self.event(format!("{}[{}]", key, String::from_utf8_lossy(value)))
+    }
+
+    fn end_list(&mut self, key: &[u8]) -> RdbResult<Control> {
+        self.event(format!("end {}", String::from_utf8_lossy(key)))
+    }
+}
+
+fn visit(control: fn(&str) -> Control) -> Vec<String> {
+    let file = File::open("tests/dumps/parser_filters.rdb").unwrap();
+    let log = Log {
+        events: Vec::new(),
+        control,
+    };
+    let mut parser = RdbParser::new(BufReader::new(file), log, Simple::new());
+    parser.parse().unwrap();
+    parser.into_formatter().events
+}
+
+#[test]
+fn test_skip_key() {
+    let events = visit(|event| match event {
+        "start l10" | "l11[9999999998]" => Control::SkipKey,
+        _ => Control::Continue,
+    });
+    let list = |key: &str| -> Vec<&String> {
+        events
+            .iter()
+            .filter(|event| event.contains(&format!(" {}", key)) || event.starts_with(key))
+            .collect()
+    };
+
+    assert_eq!(list("l10"), vec!["start l10"]);
+    assert_eq!(
+        list("l11"),
+        vec!["start l11", "l11[9999999999]", "l11[9999999998]"]
+    );
+    assert_eq!(list("l12").len(), 5);
+    assert_eq!(events.last().unwrap(), "end");
+}
+
+#[test]
+fn test_stop() {
+    let events = visit(|event| match event {
+        "set s2" => Control::Stop,
+        _ => Control::Continue,
+    });
+    assert_eq!(events, vec!["set k1", "set k3", "set s1", "set s2"]);
+}
+
+// A list of large strings, each of them LZF compressed, and a string.
+fn large_list() -> Vec<u8> {
+    let record = |key: &str, value: RedisValue| KeyRecord {
+        db: 0,
+        key: key.as_bytes().to_vec(),
+        meta: KeyMetadata::default(),
+        value,
+        encoding: EncodingType::LinkedList,
+    };
+    let mut writer = RdbWriter::new(Vec::new());
+    writer.set_encoding(Encoding::Plain);
+    let elements = vec![vec![b'x'; 10_000]; 1000];
+    writer
+        .write_record(&record("list", RedisValue::List(elements)))
+        .unwrap();
+    writer
+        .write_record(&record("last", RedisValue::String(b"1".to_vec())))
+        .unwrap();
+    writer.finish().unwrap()
+}
+
+#[test]
+fn test_interrupt_large_value() {
+    let data = large_list();
+    let run = |control: fn(&str) -> Control| {
+        let mut input = Cursor::new(&data);
+        let log = Log {
+            events: Vec::new(),
+            control,
+        };
+        let mut parser = RdbParser::new(&mut input, log, Simple::new());
+        parser.parse().unwrap();
+        let events: Vec<String> = parser
+            .into_formatter()
+            .events
+            .iter()
+            .map(|event| event.chars().take(12).collect())
+            .collect();
+        (events, input.position() as usize)
+    };
+
+    // Nothing more is read once stopped.
+    let (events, read) = run(|event| {
+        if event.starts_with("list[") {
+            Control::Stop
+        } else {
+            Control::Continue
+        }
+    });
+    assert_eq!(events, vec!["start list", "list[xxxxxxx"]);
+    assert!(read < data.len() / 100, "{} of {}", read, data.len());
+
+    // The rest of a skipped key is skipped, the keys after it are read.
+    let (events, read) = run(|event| {
+        if event.starts_with("list[") {
+            Control::SkipKey
+        } else {
+            Control::Continue
+        }
+    });
+    assert_eq!(
+        events,
+        vec!["start list", "list[xxxxxxx", "set last", "end"]
+    );
+    assert_eq!(read, data.len());
+}
@@ -0,0 +1,217 @@
+extern crate redis_canal_rs as rdb;
+mod common;
+use common::dumps;
+use rdb::crc64::crc64;
+use rdb::filter::Simple;
+use rdb::formatter::Records;
+use rdb::parser::RdbParser;
+use rdb::reader::{Entry, RdbReader};
+use rdb::types::EncodingType;
+use rdb::writer::{Encoding, RdbWriter};
+use rdb::{KeyMetadata, KeyRecord, RedisValue};
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor};
+
+// Streams and modules are never written, and set members come back sorted
+// from intsets.
+fn normalized(entries: Vec<Entry>) -> Vec<KeyRecord> {
+    entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Entry::Key(mut record) => {
+                if let RedisValue::Set(members) = &mut record.value {
+                    members.sort();
+                }
+                Some(record)
+            }
+            _ => None,
+        })
+        .filter(|record| {
+            !matches!(
+                record.value,
+                RedisValue::Stream { .. } | RedisValue::Module { .. }
+            )
+        })
+        .collect()
+}
+
+fn read(data: &[u8]) -> Vec<Entry> {
+    RdbReader::new(Cursor::new(data))
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+fn write(entries: &[Entry], version: u32, encoding: Encoding) -> Vec<u8> {
+    let mut writer = RdbWriter::new(Vec::new());
+    writer.set_version(version).unwrap();
+    writer.set_encoding(encoding);
+
+    for entry in entries {
+        match entry {
+            Entry::AuxField { key, value } => writer.write_aux(key, value).unwrap(),
+            Entry::SelectDb(db) => writer.select_db(*db).unwrap(),
+            Entry::Key(record) => writer.write_record(record).unwrap(),
+            _ => {}
+        }
+    }
+    writer.finish().unwrap()
+}
+
+fn compare_keys(mut expected: Vec<KeyRecord>, actual: Vec<KeyRecord>, version: u32) {
+    for record in &mut expected {
+        if version < 9 {
+            record.meta.idle = None;
+            record.meta.freq = None;
+        }
+        // Expiries are written in seconds before version 3.
+        if version < 3 {
+            record.meta.expiry = record.meta.expiry.map(|expiry| expiry / 1000 * 1000);
+        }
+    }
+    assert_eq!(expected.len(), actual.len());
+    for (expected, actual) in expected.iter().zip(&actual) {
+        assert_eq!(expected.db, actual.db);
+        assert_eq!(expected.key, actual.key);
+        assert_eq!(expected.meta, actual.meta);
+        assert_eq!(expected.value, actual.value, "{:?}", expected.key);
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    for dump in dumps() {
+        let entries = read(&fs::read(&dump).unwrap());
+
+        for &version in &[9, 7, 6, 5, 2] {
+            for &encoding in &[Encoding::Compact, Encoding::Plain] {
+                let written = write(&entries, version, encoding);
+                let keys = normalized(read(&written));
+                compare_keys(normalized(entries.clone()), keys, version);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_aux_fields_and_checksum() {
+    let entries = read(&fs::read("tests/dumps/lru_idle.rdb").unwrap());
+    let written = write(&entries, 9, Encoding::Compact);
+
+    assert_eq!(&written[..9], b"REDIS0009");
+    let (data, checksum) = written.split_at(written.len() - 8);
+    assert_eq!(crc64(0, data).to_le_bytes(), checksum);
+
+    let aux: Vec<&Entry> = entries
+        .iter()
+        .filter(|entry| matches!(entry, Entry::AuxField { .. }))
+        .collect();
+    let written_aux = read(&written);
+    assert_eq!(aux, written_aux.iter().take(aux.len()).collect::<Vec<_>>());
+
+    // Versions before 5 have no checksum.
+    let written = write(&entries, 4, Encoding::Plain);
+    assert_eq!(written.last(), Some(&0xFF));
+}
+
+#[test]
+fn test_compression() {
+    let data = fs::read("tests/dumps/easily_compressible_string_key.rdb").unwrap();
+    let entries = read(&data);
+
+    let compressed = write(&entries, 9, Encoding::Plain);
+    let mut writer = RdbWriter::new(Vec::new());
+    writer.set_compression(false);
+    for entry in &entries {
+        if let Entry::Key(record) = entry {
+            writer.write_record(record).unwrap();
+        }
+    }
+    let uncompressed = writer.finish().unwrap();
+    assert!(compressed.len() * 2 < uncompressed.len());
+}
+
+#[test]
+fn test_formatter_events() {
+    let path = "tests/dumps/regular_sorted_set.rdb";
+    let entries = read(&fs::read(path).unwrap());
+
+    let mut records = Records::new(RdbWriter::new(Vec::new()));
+    records.set_max_size(100);
+    let input = BufReader::new(File::open(path).unwrap());
+    let mut parser = RdbParser::new(input, records, Simple::new());
+    parser.parse().unwrap();
+    let written = parser.into_formatter().into_handler().finish().unwrap();
+
+    compare_keys(normalized(entries), normalized(read(&written)), 9);
+}
+
+#[test]
+fn test_scores_and_expiries() {
+    let scores = vec![
+        (b"large".to_vec(), 1e300),
+        (b"small".to_vec(), -1e-300),
+        (b"third".to_vec(), 1.0 / 3.0),
+        (b"whole".to_vec(), 12.0),
+    ];
+    let record = KeyRecord {
+        db: 0,
+        key: b"zset".to_vec(),
+        meta: KeyMetadata {
+            expiry: Some(1_500_000_000_123),
+            ..KeyMetadata::default()
+        },
+        value: RedisValue::SortedSet(scores),
+        encoding: EncodingType::Skiplist,
+    };
+    let entries = [Entry::SelectDb(0), Entry::Key(record.clone())];
+
+    // Text scores before version 8, millisecond expiries since version 3.
+    for &version in &[7, 5] {
+        for &encoding in &[Encoding::Compact, Encoding::Plain] {
+            let written = write(&entries, version, encoding);
+            let keys = normalized(read(&written));
+            compare_keys(vec![record.clone()], keys, version);
+        }
+    }
+    let written = write(&entries, 7, Encoding::Plain);
+    let text = String::from_utf8_lossy(&written);
+    assert!(text.contains("\x051e300"), "{:?}", text);
+    assert!(text.contains("\x07-1e-300"), "{:?}", text);
+}
+
+#[test]
+fn test_encodings_by_version() {
+    let record = |key: &str, value| KeyRecord {
+        db: 0,
+        key: key.as_bytes().to_vec(),
+        meta: KeyMetadata::default(),
+        value,
+        encoding: EncodingType::Hashtable,
+    };
+    let entries = [
+        Entry::SelectDb(0),
+        Entry::Key(record(
+            "hash",
+            RedisValue::Hash(vec![(b"field".to_vec(), b"7".to_vec())]),
+        )),
+        Entry::Key(record("list", RedisValue::List(vec![b"7".to_vec()]))),
+    ];
+
+    for &(version, hash, list) in &[
+        (5, "zipmap", "ziplist"),
+        (6, "ziplist", "ziplist"),
+        (7, "ziplist", "quicklist"),
+    ] {
+        let written = write(&entries, version, Encoding::Compact);
+        let encodings: Vec<&str> = normalized(read(&written))
+            .iter()
+            .map(|record| record.encoding.name())
+            .collect();
+        assert_eq!(encodings, [hash, list], "version {}", version);
+
+        // 7 is held in the entry header from version 6 on, as a 16 bit
+        // integer before.
+        let old_integer = written.windows(4).any(|w| w == [0, 0xC0, 7, 0]);
+        assert_eq!(old_integer, version < 6, "version {}", version);
+    }
+}